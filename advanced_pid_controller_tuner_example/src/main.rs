@@ -1,3 +1,4 @@
+use examples_common::control::settling_time;
 use rig::providers::openai;
 use rig::completion::Prompt;
 use serde::{Deserialize, Serialize};
@@ -55,8 +56,37 @@ impl PIDController {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct PerformanceMetrics {
+    settling_time: f64,
+    max_overshoot: f64,
+    steady_state_error: f64,
+}
+
+// Weights of the composite score: steady state error matters most, then overshoot,
+// and settling time breaks ties
+const SETTLING_TIME_WEIGHT: f64 = 0.1;
+const OVERSHOOT_WEIGHT: f64 = 1.0;
+const STEADY_STATE_ERROR_WEIGHT: f64 = 10.0;
+
+impl PerformanceMetrics {
+    // Composite score of an iteration; lower is better
+    fn score(&self) -> f64 {
+        SETTLING_TIME_WEIGHT * self.settling_time
+            + OVERSHOOT_WEIGHT * self.max_overshoot
+            + STEADY_STATE_ERROR_WEIGHT * self.steady_state_error
+    }
+}
+
+// Iteration indices ordered from best to worst score
+fn rank_iterations(metrics: &[PerformanceMetrics]) -> Vec<usize> {
+    let mut ranked: Vec<usize> = (0..metrics.len()).collect();
+    ranked.sort_by(|&a, &b| metrics[a].score().total_cmp(&metrics[b].score()));
+    ranked
+}
+
 // Performance metrics calculation
-fn calculate_performance_metrics(response: &[f64], setpoint: f64, dt: f64) -> (f64, f64, f64) {
+fn calculate_performance_metrics(response: &[f64], setpoint: f64, dt: f64) -> PerformanceMetrics {
     let steady_state_error = (response.last().unwrap() - setpoint).abs();
     
     let mut max_overshoot = 0.0;
@@ -67,9 +97,13 @@ fn calculate_performance_metrics(response: &[f64], setpoint: f64, dt: f64) -> (f
         }
     }
     
-    let settling_time = response.len() as f64 * dt;  // Simplified
+    let settling_time = settling_time(response, setpoint, dt);
 
-    (settling_time, max_overshoot, steady_state_error)
+    PerformanceMetrics {
+        settling_time,
+        max_overshoot,
+        steady_state_error,
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -83,6 +117,7 @@ fn generate_chart(
     responses: &[Vec<f64>],
     iteration: usize,
     pid_params: &[PIDParams],
+    metrics: &[PerformanceMetrics],
     file_name: &str,
 ) -> Result<(), Box<dyn Error>> {
     let root = BitMapBackend::new(file_name, (800, 600)).into_drawing_area();
@@ -99,15 +134,19 @@ fn generate_chart(
 
     let colors = [RED, BLUE, GREEN, CYAN, MAGENTA, YELLOW];
 
-    for (i, response) in responses.iter().enumerate() {
-        let color = colors[i % colors.len()];
+    // Draw the best iteration first so the legend is sorted by score; each iteration
+    // keeps its color across charts, and the best one is starred and drawn thicker
+    for (rank, i) in rank_iterations(metrics).into_iter().enumerate() {
+        let best = rank == 0;
+        let style = colors[i % colors.len()].stroke_width(if best { 3 } else { 1 });
         chart.draw_series(LineSeries::new(
-            response.iter().enumerate().map(|(x, y)| (x as f32 / 100.0, *y as f32)),
-            color,
+            responses[i].iter().enumerate().map(|(x, y)| (x as f32 / 100.0, *y as f32)),
+            style,
         ))?
-        .label(format!("Iteration {} (Kp={:.2}, Ki={:.2}, Kd={:.2})",
-                       i, pid_params[i].kp, pid_params[i].ki, pid_params[i].kd))
-        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        .label(format!("{}Iteration {} (Kp={:.2}, Ki={:.2}, Kd={:.2}) score {:.3}",
+                       if best { "* " } else { "" },
+                       i, pid_params[i].kp, pid_params[i].ki, pid_params[i].kd, metrics[i].score()))
+        .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
     }
 
     chart.configure_series_labels()
@@ -126,6 +165,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let mut all_responses = Vec::new();
     let mut all_pid_params = Vec::new();
+    let mut all_metrics = Vec::new();
 
     let setpoint = 1.0;
     let dt = 0.01;
@@ -147,14 +187,15 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
         all_responses.push(response.clone());
 
-        let (settling_time, max_overshoot, steady_state_error) = 
-            calculate_performance_metrics(&response, setpoint, dt);
+        let metrics = calculate_performance_metrics(&response, setpoint, dt);
+        all_metrics.push(metrics);
+        let PerformanceMetrics { settling_time, max_overshoot, steady_state_error } = metrics;
 
         println!("Iteration {}: ST = {:.2}, MO = {:.2}, SSE = {:.4}", 
                  iteration, settling_time, max_overshoot, steady_state_error);
 
         // Generate chart for this iteration
-        generate_chart(&all_responses, iteration, &all_pid_params, 
+        generate_chart(&all_responses, iteration, &all_pid_params, &all_metrics,
                        &format!("system_response_iteration_{}.png", iteration))?;
 
         // Ask AI to suggest new PID parameters
@@ -178,7 +219,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
 
     // Generate final overlay chart
-    generate_chart(&all_responses, all_responses.len() - 1, &all_pid_params, "system_response_overlay.png")?;
+    generate_chart(&all_responses, all_responses.len() - 1, &all_pid_params, &all_metrics, "system_response_overlay.png")?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_iterations_by_score() {
+        let metrics = [
+            PerformanceMetrics { settling_time: 10.0, max_overshoot: 0.5, steady_state_error: 0.1 },
+            PerformanceMetrics { settling_time: 10.0, max_overshoot: 0.2, steady_state_error: 0.01 },
+            PerformanceMetrics { settling_time: 4.0, max_overshoot: 0.3, steady_state_error: 0.2 },
+        ];
+        assert_eq!(rank_iterations(&metrics), vec![1, 0, 2]);
+    }
+}
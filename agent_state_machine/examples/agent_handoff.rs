@@ -0,0 +1,60 @@
+// examples/agent_handoff.rs
+//
+// A researcher agent gathers facts, then hands the conversation to a writer agent.
+// Both machines attach the same history store under one session id, so the writer
+// starts from the researcher's full history instead of a pasted summary.
+
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, InMemoryHistoryStore};
+use rig::providers::openai;
+
+const SESSION: &str = "article-on-rust-async";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("=== Agent Handoff Demo ===\n");
+
+    let client = openai_client_from_env();
+    // Swap in a FileHistoryStore to hand off between processes
+    let store = InMemoryHistoryStore::new();
+
+    // 1. The researcher collects material; every turn is saved to the store
+    let mut researcher = ChatAgentStateMachine::new(
+        client
+            .agent(openai::GPT_4)
+            .preamble("You are a researcher. Answer with concise, factual bullet points.")
+            .build(),
+    );
+    researcher.attach_history_store(store.clone(), SESSION).await?;
+
+    for question in [
+        "What problems does async/await solve in Rust?",
+        "What are the most common pitfalls when starting with Tokio?",
+    ] {
+        println!("🔎 Researcher asked: {}", question);
+        let notes = researcher.process_single_message(question).await?;
+        println!("📝 Notes:\n{}\n", notes);
+    }
+
+    // 2. The writer picks up the same session, restoring the researcher's history
+    let mut writer = ChatAgentStateMachine::new(
+        client
+            .agent(openai::GPT_4)
+            .preamble("You are a technical writer. Turn research notes into clear, engaging prose.")
+            .build(),
+    );
+    let restored = writer.attach_history_store(store, SESSION).await?;
+    println!(
+        "🤝 Handoff: writer restored {} ({} messages)\n",
+        if restored { "the researcher's history" } else { "nothing" },
+        writer.history().len()
+    );
+
+    // 3. The writer works from the full context and continues the shared session
+    let article = writer
+        .process_single_message("Write a short blog post for Rust beginners based on the research above.")
+        .await?;
+    println!("✍️ Writer:\n{}", article);
+
+    println!("\n=== Demo Complete ===");
+    Ok(())
+}
@@ -0,0 +1,74 @@
+// examples/delegation.rs
+//
+// A router agent hands questions to specialist agents, each running in its own state
+// machine and exposed to the router as a tool with StateMachineTool.
+
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, StateMachineTool};
+use rig::completion::Prompt;
+use rig::providers::openai;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("=== Delegation Demo ===\n");
+
+    let client = openai_client_from_env();
+
+    let rust_expert = StateMachineTool::new(
+        ChatAgentStateMachine::new(
+            client
+                .agent(openai::GPT_4)
+                .preamble("You are a Rust expert. Answer in at most three sentences.")
+                .build(),
+        ),
+        "ask_rust_expert",
+        "Ask a Rust programming expert. Use this for any question about Rust code, crates or tooling.",
+    );
+    let travel_agent = StateMachineTool::new(
+        ChatAgentStateMachine::new(
+            client
+                .agent(openai::GPT_4)
+                .preamble("You are a travel agent. Give short, practical advice.")
+                .build(),
+        ),
+        "ask_travel_agent",
+        "Ask a travel agent. Use this for questions about destinations, trips and travel planning.",
+    );
+
+    // Keep handles to the specialists' machines to inspect them afterwards
+    let rust_machine = rust_expert.machine();
+    let travel_machine = travel_agent.machine();
+
+    let router = client
+        .agent(openai::GPT_4)
+        .preamble(
+            "You route questions to the right specialist. Always answer by calling exactly \
+            one of your tools with the user's question as the message.",
+        )
+        .tool(rust_expert)
+        .tool(travel_agent)
+        .build();
+
+    let questions = [
+        "How do I share a value between threads?",
+        "What should I pack for a week in Lisbon in March?",
+        "And when should I reach for a Mutex instead of an RwLock?",
+    ];
+
+    for question in questions {
+        println!("👤 User: {}", question);
+        // rig returns a tool's output JSON-encoded
+        let output = router.prompt(question).await?;
+        let answer: String = serde_json::from_str(&output).unwrap_or(output);
+        println!("🤖 Specialist: {}\n", answer);
+    }
+
+    // Each specialist kept its own conversation, so follow-ups have context
+    println!(
+        "📚 Rust expert history: {} messages, travel agent history: {} messages",
+        rust_machine.lock().await.history().len(),
+        travel_machine.lock().await.history().len(),
+    );
+
+    println!("\n=== Demo Complete ===");
+    Ok(())
+}
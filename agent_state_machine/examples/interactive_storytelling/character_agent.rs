@@ -1,25 +1,26 @@
 // examples/interactive_storytelling/character_agent.rs
 
-use agent_state_machine::{ChatAgentStateMachine, AgentState};
+use crate::phase::{NarrativePhase, StoryMachine};
+use agent_state_machine::{AgentState, ChatAgentStateMachine, MachineError};
 use rig::completion::{Chat, PromptError};
 
 pub struct CharacterAgent<A: Chat> {
-    pub inner: ChatAgentStateMachine<A>,
+    pub inner: StoryMachine<A>,
 }
 
 impl<A: Chat> CharacterAgent<A> {
     pub fn new(agent: A) -> Self {
         Self {
-            inner: ChatAgentStateMachine::new(agent),
+            inner: ChatAgentStateMachine::new_custom(agent),
         }
     }
 
     pub async fn update_characters(
         &mut self,
         narrative_context: &str,
-    ) -> Result<String, PromptError> {
+    ) -> Result<String, MachineError<PromptError, NarrativePhase>> {
         self.inner
-            .transition_to(AgentState::Custom("UpdatingCharacters".into()));
+            .transition_to(AgentState::Custom(NarrativePhase::UpdatingCharacters))?;
 
         let prompt = format!(
             "Based on the following narrative context, update the characters' states and actions:\n\n{}",
@@ -29,11 +30,11 @@ impl<A: Chat> CharacterAgent<A> {
         let response = self.inner.process_single_message(&prompt).await?;
 
         self.inner
-            .transition_to(AgentState::Custom("Completed".into()));
+            .transition_to(AgentState::Custom(NarrativePhase::Completed))?;
         Ok(response)
     }
 
-    pub fn current_state(&self) -> &AgentState {
+    pub fn current_state(&self) -> &AgentState<NarrativePhase> {
         self.inner.current_state()
     }
 }
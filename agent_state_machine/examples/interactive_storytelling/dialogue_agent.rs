@@ -1,25 +1,26 @@
 // examples/interactive_storytelling/dialogue_agent.rs
 
-use agent_state_machine::{ChatAgentStateMachine, AgentState};
+use crate::phase::{NarrativePhase, StoryMachine};
+use agent_state_machine::{AgentState, ChatAgentStateMachine, MachineError};
 use rig::completion::{Chat, PromptError};
 
 pub struct DialogueAgent<A: Chat> {
-    pub inner: ChatAgentStateMachine<A>,
+    pub inner: StoryMachine<A>,
 }
 
 impl<A: Chat> DialogueAgent<A> {
     pub fn new(agent: A) -> Self {
         Self {
-            inner: ChatAgentStateMachine::new(agent),
+            inner: ChatAgentStateMachine::new_custom(agent),
         }
     }
 
     pub async fn generate_dialogue(
         &mut self,
         character_context: &str,
-    ) -> Result<String, PromptError> {
+    ) -> Result<String, MachineError<PromptError, NarrativePhase>> {
         self.inner
-            .transition_to(AgentState::Custom("GeneratingDialogue".into()));
+            .transition_to(AgentState::Custom(NarrativePhase::GeneratingDialogue))?;
 
         let prompt = format!(
             "Generate a dialogue between characters based on the following context:\n\n{}",
@@ -29,11 +30,11 @@ impl<A: Chat> DialogueAgent<A> {
         let response = self.inner.process_single_message(&prompt).await?;
 
         self.inner
-            .transition_to(AgentState::Custom("Completed".into()));
+            .transition_to(AgentState::Custom(NarrativePhase::Completed))?;
         Ok(response)
     }
 
-    pub fn current_state(&self) -> &AgentState {
+    pub fn current_state(&self) -> &AgentState<NarrativePhase> {
         self.inner.current_state()
     }
 }
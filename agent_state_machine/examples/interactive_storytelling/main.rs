@@ -4,23 +4,48 @@ mod narrative_agent;
 mod character_agent;
 mod dialogue_agent;
 mod environment_agent;
+mod phase;
+mod story_context;
 
 use narrative_agent::NarrativeAgent;
 use character_agent::CharacterAgent;
 use dialogue_agent::DialogueAgent;
 use environment_agent::EnvironmentAgent;
-
-use agent_state_machine::{ChatAgentStateMachine, AgentState};
-use rig::providers::openai::{self, GPT_4};
-use rig::completion::{Chat, PromptError};
-use tokio::io::{self, AsyncBufReadExt};
+use phase::StoryMachine;
+use story_context::{Directive, StoryContext};
+
+use agent_state_machine::{
+    openai_client_from_env, shutdown_signal, stdin_lines, AgentState, StateMultiplexer,
+};
+use rig::completion::Chat;
+use rig::providers::openai::GPT_4;
+use std::io::Write;
+
+/// Point every stage agent at the story's current genre and tone
+fn apply_style<A: Chat>(context: &StoryContext, machines: [&mut StoryMachine<A>; 4]) {
+    let preamble = context.preamble();
+    for machine in machines {
+        machine.set_system_preamble(&preamble);
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Interactive Storytelling Demo ===\n");
 
+    tokio::select! {
+        result = run_story() => result?,
+        _ = shutdown_signal() => println!("\nInterrupted."),
+    }
+
+    println!("\n=== The End ===");
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+async fn run_story() -> Result<(), Box<dyn std::error::Error>> {
     // Create OpenAI client
-    let client = openai::Client::from_env();
+    let client = openai_client_from_env();
 
     // Initialize agents
     let narrative_agent = client
@@ -47,7 +72,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build();
     let mut environment_state_machine = EnvironmentAgent::new(environment_agent);
 
+    // Follow all four agents' states in one tagged stream
+    let mut states = StateMultiplexer::new();
+    states.add("narrative", narrative_state_machine.inner.subscribe_to_state_changes());
+    states.add("characters", character_state_machine.inner.subscribe_to_state_changes());
+    states.add("dialogue", dialogue_state_machine.inner.subscribe_to_state_changes());
+    states.add("environment", environment_state_machine.inner.subscribe_to_state_changes());
+    tokio::spawn(async move {
+        while let Some((name, state)) = states.recv().await {
+            println!("📍 [{}] {}", name, state);
+        }
+    });
+
+    // Every stage agent writes under the same genre and tone
+    let mut context = StoryContext::default();
+    apply_style(
+        &context,
+        [
+            &mut narrative_state_machine.inner,
+            &mut character_state_machine.inner,
+            &mut dialogue_state_machine.inner,
+            &mut environment_state_machine.inner,
+        ],
+    );
+
     // Start the story
+    let mut lines = stdin_lines();
     let mut user_choice: Option<String> = None;
     loop {
         // Generate plot
@@ -70,24 +120,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("=== Story Segment ===");
         println!("{}\n{}\n{}\n", environment_output, narrative_output, dialogue_output);
 
-        // Ask for user input
-        println!("What do you want to do next?");
-        let stdin = io::BufReader::new(io::stdin());
-        let mut lines = stdin.lines();
-
-        let input = if let Ok(Some(line)) = lines.next_line().await {
-            line
-        } else {
-            break;
+        // Ask for user input. Directives like `/genre horror` change the style of
+        // the story and ask again; anything else continues the story.
+        let input = loop {
+            if let AgentState::AwaitingInput { prompt } = narrative_state_machine.current_state() {
+                println!("{}", prompt);
+            }
+            let Some(line) = lines.recv().await else {
+                break None;
+            };
+            let Some(directive) = Directive::parse(&line) else {
+                break Some(line);
+            };
+
+            match context.apply(directive) {
+                Ok(()) => {
+                    apply_style(
+                        &context,
+                        [
+                            &mut narrative_state_machine.inner,
+                            &mut character_state_machine.inner,
+                            &mut dialogue_state_machine.inner,
+                            &mut environment_state_machine.inner,
+                        ],
+                    );
+                    println!("🎭 Story style is now {}\n", context);
+                }
+                Err(message) => println!("⚠️  {}\n", message),
+            }
         };
 
-        if input.trim().is_empty() {
+        let Some(input) = input.filter(|input| !input.trim().is_empty()) else {
             break;
-        }
+        };
 
         user_choice = Some(input.trim().to_string());
     }
 
-    println!("\n=== The End ===");
     Ok(())
 }
@@ -1,39 +1,41 @@
 // examples/interactive_storytelling/narrative_agent.rs
 
-use agent_state_machine::{ChatAgentStateMachine, AgentState};
+use crate::phase::{NarrativePhase, StoryMachine};
+use agent_state_machine::{AgentState, ChatAgentStateMachine, MachineError};
 use rig::completion::{Chat, PromptError};
 
 pub struct NarrativeAgent<A: Chat> {
-    pub inner: ChatAgentStateMachine<A>,
+    pub inner: StoryMachine<A>,
 }
 
 impl<A: Chat> NarrativeAgent<A> {
     pub fn new(agent: A) -> Self {
         Self {
-            inner: ChatAgentStateMachine::new(agent),
+            inner: ChatAgentStateMachine::new_custom(agent),
         }
     }
 
     pub async fn generate_plot(
         &mut self,
         user_choice: Option<String>,
-    ) -> Result<String, PromptError> {
+    ) -> Result<String, MachineError<PromptError, NarrativePhase>> {
         self.inner
-            .transition_to(AgentState::Custom("GeneratingPlot".into()));
+            .transition_to(AgentState::Custom(NarrativePhase::GeneratingPlot))?;
 
         let prompt = match user_choice {
             Some(choice) => format!("Based on the user's choice '{}', continue the story.", choice),
-            None => "Start a new interactive story in the fantasy genre.".to_string(),
+            None => "Start a new interactive story.".to_string(),
         };
 
         let response = self.inner.process_single_message(&prompt).await?;
 
-        self.inner
-            .transition_to(AgentState::Custom("WaitingForChoice".into()));
+        self.inner.transition_to(AgentState::AwaitingInput {
+            prompt: "What do you want to do next?".into(),
+        })?;
         Ok(response)
     }
 
-    pub fn current_state(&self) -> &AgentState {
+    pub fn current_state(&self) -> &AgentState<NarrativePhase> {
         self.inner.current_state()
     }
 }
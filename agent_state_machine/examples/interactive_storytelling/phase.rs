@@ -0,0 +1,24 @@
+// examples/interactive_storytelling/phase.rs
+
+use agent_state_machine::ChatAgentStateMachine;
+use std::fmt;
+
+/// What a story agent is busy with, as its machine's custom state. Being an enum, a
+/// misspelled phase doesn't compile instead of quietly becoming a new state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NarrativePhase {
+    GeneratingPlot,
+    UpdatingCharacters,
+    DescribingEnvironment,
+    GeneratingDialogue,
+    Completed,
+}
+
+impl fmt::Display for NarrativePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// The state machine every story agent wraps
+pub type StoryMachine<A> = ChatAgentStateMachine<A, NarrativePhase>;
@@ -0,0 +1,92 @@
+// examples/interactive_storytelling/story_context.rs
+
+use std::fmt;
+
+/// A command typed by the user instead of a story choice
+pub enum Directive {
+    /// `/genre <genre>`: switch the story to another genre
+    Genre(String),
+    /// `/tone <tone>`: change how the story feels, e.g. `lighter` or `darker`
+    Tone(String),
+    /// A `/` command that isn't recognized, or is missing its argument
+    Invalid(String),
+}
+
+impl Directive {
+    /// Parse a line of input, returning `None` if it isn't a directive
+    pub fn parse(input: &str) -> Option<Self> {
+        let command = input.trim().strip_prefix('/')?;
+        let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let argument = argument.trim().to_string();
+
+        Some(match name.to_lowercase().as_str() {
+            "genre" | "tone" if argument.is_empty() => {
+                Directive::Invalid(format!("/{} needs a value, e.g. /{} {}", name, name, example(name)))
+            }
+            "genre" => Directive::Genre(argument),
+            "tone" => Directive::Tone(argument),
+            _ => Directive::Invalid(format!(
+                "Unknown command /{}. Try /genre <genre> or /tone <tone>.",
+                name
+            )),
+        })
+    }
+}
+
+fn example(name: &str) -> &'static str {
+    if name.eq_ignore_ascii_case("genre") {
+        "horror"
+    } else {
+        "lighter"
+    }
+}
+
+/// The style settings every agent writes under, carried across the whole story
+pub struct StoryContext {
+    pub genre: String,
+    pub tone: Option<String>,
+}
+
+impl Default for StoryContext {
+    fn default() -> Self {
+        Self {
+            genre: "fantasy".to_string(),
+            tone: None,
+        }
+    }
+}
+
+impl StoryContext {
+    /// Apply a directive, returning an error message for invalid ones
+    pub fn apply(&mut self, directive: Directive) -> Result<(), String> {
+        match directive {
+            Directive::Genre(genre) => self.genre = genre,
+            Directive::Tone(tone) => self.tone = Some(tone),
+            Directive::Invalid(message) => return Err(message),
+        }
+        Ok(())
+    }
+
+    /// System preamble describing the current style for the stage agents
+    pub fn preamble(&self) -> String {
+        let mut preamble = format!("The story is in the {} genre.", self.genre);
+        if let Some(tone) = &self.tone {
+            preamble.push_str(&format!(" Keep the tone {}.", tone));
+        }
+        preamble.push_str(
+            " If this differs from earlier parts of the story, shift to it smoothly while \
+            keeping the same characters, places and events.",
+        );
+        preamble
+    }
+}
+
+impl fmt::Display for StoryContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "genre: {}", self.genre)?;
+        if let Some(tone) = &self.tone {
+            write!(f, ", tone: {}", tone)?;
+        }
+        Ok(())
+    }
+}
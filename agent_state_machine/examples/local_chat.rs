@@ -0,0 +1,100 @@
+// examples/local_chat.rs
+//
+// Runs the chat state machine entirely against a local OpenAI-compatible server
+// (llama.cpp's `llama-server` or Ollama). Set OPENAI_BASE_URL / LOCAL_MODEL as needed,
+// and LOCAL_MODEL_TOOLS=true if the model supports native tool calling.
+
+use agent_state_machine::{AgentState, ChatAgentStateMachine, LocalModel, PromptedTools};
+use rig::completion::{Chat, ToolDefinition};
+use rig::tool::Tool;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+#[derive(Deserialize)]
+struct AddArgs {
+    x: i64,
+    y: i64,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Math error")]
+struct MathError;
+
+struct Adder;
+
+impl Tool for Adder {
+    const NAME: &'static str = "add";
+    type Error = MathError;
+    type Args = AddArgs;
+    type Output = i64;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Add x and y together".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "x": { "type": "number", "description": "The first number to add" },
+                    "y": { "type": "number", "description": "The second number to add" }
+                },
+                "required": ["x", "y"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        Ok(args.x + args.y)
+    }
+}
+
+async fn run<A: Chat>(agent: A) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state_machine = ChatAgentStateMachine::new(agent);
+    // Local models can stall, e.g. while loading; a request that takes over two minutes
+    // is skipped, passing through the TimedOut state, instead of hanging the demo
+    state_machine.set_message_timeout(Duration::from_secs(120));
+
+    state_machine.set_response_callback(|response| {
+        println!("🤖 Assistant: {}", response);
+    });
+
+    let mut state_rx = state_machine.subscribe_to_state_changes();
+    tokio::spawn(async move {
+        while let Ok(state) = state_rx.recv().await {
+            println!("📍 State: {}", state);
+        }
+    });
+
+    let mut final_state = AgentState::Ready;
+    for message in ["Hello! Who are you?", "What is 1234 + 4321?"] {
+        println!("\n👤 User: {}", message);
+        final_state = state_machine.process_message(message).await?;
+    }
+
+    println!("\n📍 Final state: {}", final_state);
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let local = LocalModel::from_env();
+    println!("=== Local Chat Demo ({} @ {}) ===", local.model, local.base_url);
+
+    let builder = local
+        .client()
+        .agent(&local.model)
+        .preamble("You are a helpful assistant. Keep your responses short.");
+
+    if local.supports_tools {
+        println!("Using native tool calling\n");
+        run(builder.tool(Adder).build()).await?;
+    } else {
+        println!("Model has no native tool calling, describing tools in the prompt\n");
+        run(PromptedTools::new(builder.build()).tool(Adder)).await?;
+    }
+
+    println!("\n=== Demo Complete ===");
+    Ok(())
+}
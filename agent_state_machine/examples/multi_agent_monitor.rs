@@ -0,0 +1,57 @@
+// examples/multi_agent_monitor.rs
+//
+// Runs two state machines side by side and follows both through a single
+// StateMultiplexer, so every state line says which agent it belongs to.
+
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, StateMultiplexer};
+use rig::providers::openai;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("=== Multi-Agent Monitor Demo ===\n");
+
+    let client = openai_client_from_env();
+
+    let mut poet = ChatAgentStateMachine::new(
+        client
+            .agent(openai::GPT_4)
+            .preamble("You are a poet. Answer every request with a four-line poem.")
+            .build(),
+    );
+    let mut critic = ChatAgentStateMachine::new(
+        client
+            .agent(openai::GPT_4)
+            .preamble("You are a literary critic. Keep your reviews to two sentences.")
+            .build(),
+    );
+
+    poet.set_response_callback(|response| println!("✍️  Poet:\n{}\n", response));
+    critic.set_response_callback(|response| println!("🧐 Critic:\n{}\n", response));
+
+    // Merge both state streams into one, tagged by agent name
+    let mut states = StateMultiplexer::new();
+    states.add("poet", poet.subscribe_to_state_changes());
+    states.add("critic", critic.subscribe_to_state_changes());
+
+    let monitor = tokio::spawn(async move {
+        while let Some((name, state)) = states.recv().await {
+            println!("📍 [{}] {}", name, state);
+        }
+    });
+
+    // Both agents work at the same time
+    let (poet_state, critic_state) = tokio::join!(
+        poet.process_message("Write a poem about the Rust borrow checker."),
+        critic.process_message("Review the opening line: 'It was a dark and stormy night.'"),
+    );
+    poet_state?;
+    critic_state?;
+
+    // Dropping the machines ends the multiplexed stream
+    drop(poet);
+    drop(critic);
+    monitor.await?;
+
+    println!("\n=== Demo Complete ===");
+    Ok(())
+}
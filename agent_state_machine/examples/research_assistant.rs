@@ -1,5 +1,7 @@
-use agent_state_machine::{ChatAgentStateMachine, AgentState}; // Added AgentState import
-use rig::providers::openai::{self, GPT_4};
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine};
+use clap::Parser;
+use rig::embeddings::EmbeddingModel;
+use rig::providers::openai::{GPT_4, TEXT_EMBEDDING_3_SMALL};
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use reqwest;
@@ -7,7 +9,33 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use quick_xml::de::from_str;
 use std::time::Duration;
-use tracing::error; // Removed unused imports
+
+/// Summarize arXiv papers on a topic, stopping early once the review is good enough
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Stop after this many papers were judged relevant
+    #[arg(long)]
+    max_relevant: Option<usize>,
+
+    /// Stop once a new summary leaves the combined review this similar (cosine, 0-1)
+    /// to what it was before, i.e. adds nothing new, e.g. 0.95
+    #[arg(long)]
+    novelty_threshold: Option<f64>,
+}
+
+/// Whether the agent judged the paper relevant, from the first line of its summary
+fn is_relevant(summary: &str) -> bool {
+    summary
+        .lines()
+        .next()
+        .is_some_and(|line| line.to_lowercase().contains("relevant: yes"))
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    dot / (norm(a) * norm(b))
+}
 
 #[derive(Debug, Deserialize)]
 struct SearchArgs {
@@ -119,9 +147,11 @@ impl Tool for ArxivSearch {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
     println!("=== Research Assistant State Machine Demo ===\n");
 
-    let openai_client = openai::Client::from_env();
+    let openai_client = openai_client_from_env();
+    let embedding_model = openai_client.embedding_model(TEXT_EMBEDDING_3_SMALL);
 
     // Create ArxivSearch tool
     let arxiv_search_tool = ArxivSearch::new();
@@ -157,25 +187,66 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🔍 Searching arXiv for '{}'", query);
     let results = arxiv_search_tool.search(query).await?;
 
+    // Keep the search in history so later turns can refer back to the results
+    state_machine.record_tool_call(ArxivSearch::NAME, json!({ "query": query }).to_string());
+    state_machine.record_tool_result(ArxivSearch::NAME, serde_json::to_string(&results)?);
+
+    // The relevant summaries so far, and the embedding of that review for the novelty check
+    let mut review = String::new();
+    let mut review_embedding: Option<Vec<f64>> = None;
+    let mut relevant = 0;
+
     for (index, result) in results.iter().enumerate() {
         println!("\nProcessing result {}...", index + 1);
 
-        // Enqueue a message into the state machine for each result
         let message = format!(
-            "Please summarize the following paper:\nTitle: {}\nSummary: {}\nLink: {}",
-            result.title, result.summary, result.link
+            "Please summarize paper {} from the arXiv search results: {}\n\
+            Start your answer with a line saying 'Relevant: yes' or 'Relevant: no', \
+            depending on whether the paper is relevant to '{}'.",
+            index + 1,
+            result.title,
+            query
         );
 
-        state_machine.process_message(&message).await?;
+        let summary = state_machine.process_single_message(&message).await?;
+        println!("🤖 Assistant: {}", summary);
+
+        if !is_relevant(&summary) {
+            println!("⏭️ Not relevant, left out of the review");
+            continue;
+        }
+        relevant += 1;
+        review.push_str(&summary);
+        review.push_str("\n\n");
+
+        if let Some(threshold) = cli.novelty_threshold {
+            let embedding = embedding_model.embed_document(&review).await?.vec;
+            if let Some(previous) = &review_embedding {
+                let similarity = cosine_similarity(previous, &embedding);
+                println!("🧭 Review similarity to before this paper: {:.3}", similarity);
+                if similarity >= threshold {
+                    println!("🛑 The review stopped gaining new information, stopping");
+                    break;
+                }
+            }
+            review_embedding = Some(embedding);
+        }
 
-        while state_machine.current_state() != &AgentState::Ready {
-            tokio::time::sleep(Duration::from_millis(100)).await;
+        if cli.max_relevant == Some(relevant) {
+            println!("🛑 Summarized {} relevant papers, stopping", relevant);
+            break;
         }
 
         // Small delay to make the interaction feel more natural
         tokio::time::sleep(Duration::from_millis(500)).await;
     }
 
+    // A follow-up that only works if the search results are still remembered
+    println!("\nAsking a follow-up question...");
+    state_machine
+        .process_message("Which of the papers from the search would you read first, and why? Include its link.")
+        .await?;
+
     println!("\n=== Demo Complete ===");
     Ok(())
 }
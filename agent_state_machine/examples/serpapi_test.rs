@@ -1,5 +1,5 @@
-use agent_state_machine::ChatAgentStateMachine;
-use rig::providers::openai::{self, GPT_4};
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine};
+use rig::providers::openai::GPT_4;
 use rig::completion::{ToolDefinition};
 use rig::tool::Tool;
 use reqwest;
@@ -117,7 +117,7 @@ enum ResearchState {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let openai_client = openai::Client::from_env();
+    let openai_client = openai_client_from_env();
 
     // Create ArxivSearch tool
     let arxiv_search_tool = ArxivSearch::new();
@@ -141,8 +141,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     // Process a query using the state machine
-    let response = state_machine.process_message("Search for the latest research on quantum computing").await?;
-    println!("Response: {}", response);
+    let state = state_machine.process_message("Search for the latest research on quantum computing").await?;
+    println!("📍 Final state: {}", state);
 
     // Small delay to make the interaction feel more natural
     tokio::time::sleep(Duration::from_millis(500)).await;
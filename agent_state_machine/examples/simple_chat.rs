@@ -1,13 +1,12 @@
-use agent_state_machine::{ChatAgentStateMachine, AgentState}; // Added AgentState import
-use rig::providers::openai::{self, GPT_4};
-use std::time::Duration;
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, MachineError};
+use rig::providers::openai::GPT_4;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Chat Agent State Machine Demo ===\n");
     
     // Create OpenAI client
-    let client = openai::Client::from_env();
+    let client = openai_client_from_env();
 
     // Create a basic chat agent
     let agent = client
@@ -46,19 +45,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "What is the airspeed velocity of a coconut-laden swallow?",
     ];
 
-    // Enqueue all messages into the state machine
-    for message in messages {
-        println!("\n👤 User: {}", message);
-        
-        // Enqueue the message
-        state_machine.process_message(message).await?;
+    for message in &messages {
+        println!("👤 User: {}", message);
     }
 
-    // Wait until all messages have been processed
-    while state_machine.current_state() != &AgentState::Ready {
-        tokio::time::sleep(Duration::from_millis(100)).await;
+    // Enqueue all messages as one batch; this returns once they have been processed
+    match state_machine.process_messages(&messages).await {
+        Ok(enqueued) => println!("\n📨 Processed {} messages", enqueued),
+        Err(MachineError::EmptyMessage) => println!("\n⚠️ Skipped the batch: it contains an empty message"),
+        Err(e) => return Err(e.into()),
     }
 
+    println!("\n📍 Final state: {}", state_machine.current_state());
+
     println!("\n=== Demo Complete ===");
     Ok(())
 }
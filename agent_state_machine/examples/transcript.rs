@@ -0,0 +1,34 @@
+// examples/transcript.rs
+//
+// Feeds a file of user messages through the state machine, one per line, and
+// writes the conversation to a JSONL transcript. Useful for re-running the same
+// conversation after changing a prompt and diffing the results.
+//
+// cargo run --example transcript -- messages.txt transcript.jsonl
+
+use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine};
+use rig::providers::openai::GPT_4;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let (Some(path_in), Some(path_out)) = (args.next(), args.next()) else {
+        eprintln!("Usage: transcript <messages.txt> <transcript.jsonl>");
+        std::process::exit(2);
+    };
+
+    let client = openai_client_from_env();
+    let agent = client
+        .agent(GPT_4)
+        .preamble("You are a helpful and friendly AI assistant. Keep your responses concise.")
+        .build();
+
+    let mut state_machine = ChatAgentStateMachine::new(agent);
+    // Responses go to the transcript instead of the terminal
+    state_machine.set_response_callback(|_| {});
+
+    let written = state_machine.run_transcript(&path_in, &path_out).await?;
+    println!("Wrote {} exchanges to {}", written, path_out);
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+// src/cancel.rs
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Cancels whatever a [`ChatAgentStateMachine`](crate::ChatAgentStateMachine) is
+/// processing, from another task.
+///
+/// Get one from
+/// [`cancellation_handle`](crate::ChatAgentStateMachine::cancellation_handle) before
+/// handing the machine to the task that drives it. Clones cancel the same machine.
+#[derive(Debug, Clone)]
+pub struct CancellationHandle {
+    requested: Arc<watch::Sender<bool>>,
+}
+
+impl CancellationHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            requested: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Abort the request in flight and drop the rest of the queue. Does nothing if the
+    /// machine is idle; it doesn't carry over to the next message.
+    pub fn cancel(&self) {
+        self.requested.send_replace(true);
+    }
+
+    /// Whether a cancel was requested and not yet handled
+    pub fn is_cancelled(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Forget any earlier request, as the machine starts new work
+    pub(crate) fn reset(&self) {
+        self.requested.send_replace(false);
+    }
+
+    /// Resolves once a cancel is requested
+    pub(crate) async fn cancelled(&self) {
+        let mut requested = self.requested.subscribe();
+        // The sender lives in `self`, so the channel can't close while this waits
+        let _ = requested.wait_for(|&requested| requested).await;
+    }
+}
@@ -0,0 +1,12 @@
+// src/client.rs
+
+use rig::providers::openai;
+
+/// Build an OpenAI client from the environment, honoring `OPENAI_BASE_URL`.
+///
+/// When `OPENAI_BASE_URL` is unset this is the same as `openai::Client::from_env()`.
+/// When it is set, requests go to that server instead (a LiteLLM proxy, Ollama,
+/// llama.cpp, ...). See `examples_common::openai` for the details.
+pub fn openai_client_from_env() -> openai::Client {
+    examples_common::openai::openai_client_from_env(openai::Client::from_env, openai::Client::from_url)
+}
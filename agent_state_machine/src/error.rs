@@ -0,0 +1,69 @@
+// src/error.rs
+
+use crate::state::{AgentState, CustomState};
+use std::time::Duration;
+
+/// Errors from processing messages with a [`ChatAgentStateMachine`](crate::ChatAgentStateMachine).
+///
+/// `E` is the agent's error type, [`PromptError`](rig::completion::PromptError) for
+/// rig agents. Only [`MachineError::Agent`] comes from the provider; the other
+/// variants are the machine's own, so callers can tell e.g. a full queue from a
+/// failed request. `S` is the machine's [custom state](crate::CustomState) type.
+#[derive(Debug, thiserror::Error)]
+pub enum MachineError<E, S: CustomState = String> {
+    /// The message was empty or only whitespace, so it was not enqueued
+    #[error("message is empty")]
+    EmptyMessage,
+    /// The input guard refused the message, for the given reason
+    #[error("message rejected: {0}")]
+    GuardRejected(String),
+    /// The queue already holds as many messages as it may, so the message was not enqueued
+    #[error("queue is full ({0} messages)")]
+    QueueFull(usize),
+    /// The machine was shut down and takes no more messages
+    #[error("machine is shut down")]
+    Shutdown,
+    /// The machine is paused, so the message can't be answered until it is resumed
+    #[error("machine is paused")]
+    Paused,
+    /// The agent did not answer within the message timeout
+    #[error("no response within {0:?}")]
+    Timeout(Duration),
+    /// The request was cancelled through a
+    /// [`CancellationHandle`](crate::CancellationHandle)
+    #[error("cancelled")]
+    Cancelled,
+    /// A state change was refused by the transition rules
+    #[error(transparent)]
+    State(#[from] StateError<S>),
+    /// The agent failed to answer
+    #[error(transparent)]
+    Agent(E),
+}
+
+/// Errors from changing a machine's state with
+/// [`transition_to`](crate::ChatAgentStateMachine::transition_to)
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum StateError<S: CustomState = String> {
+    /// The machine's [`TransitionRules`](crate::TransitionRules) don't allow moving
+    /// from `from` to `to`; the machine stays in `from`
+    #[error("transition from {from} to {to} is not allowed")]
+    InvalidTransition { from: AgentState<S>, to: AgentState<S> },
+}
+
+/// What a machine does with the rest of its queue when a message fails.
+///
+/// Timeouts are always skipped, whatever the policy; see
+/// [`set_message_timeout`](crate::ChatAgentStateMachine::set_message_timeout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Stop processing and leave the remaining messages queued
+    #[default]
+    StopQueue,
+    /// Pass through the `Error` state and carry on with the next message
+    SkipAndContinue,
+    /// Retry a failed message up to `max_retries` times, then skip it. This replaces
+    /// [`set_max_retries`](crate::ChatAgentStateMachine::set_max_retries) for queued
+    /// messages if it allows more retries.
+    RetryThenSkip { max_retries: u32 },
+}
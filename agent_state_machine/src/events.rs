@@ -0,0 +1,47 @@
+// src/events.rs
+
+use std::time::Duration;
+
+/// Identifies a message from the moment it is enqueued. Ids increase by one per
+/// message and are never reused by the same machine.
+pub type MessageId = u64;
+
+/// How urgent a message is. High-priority messages wait behind earlier high-priority
+/// ones but go ahead of every queued normal message; neither interrupts the message
+/// being processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+}
+
+/// What happened to a queued message, as sent by
+/// [`subscribe_to_queue_events`](crate::ChatAgentStateMachine::subscribe_to_queue_events)
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueEvent {
+    /// The message was added to the queue
+    Enqueued { id: MessageId },
+    /// The message was taken off the queue and sent to the agent
+    Started { id: MessageId },
+    /// The agent answered, `duration` after the message started
+    Completed { id: MessageId, duration: Duration },
+    /// The agent failed to answer; the queue stops after this message unless the
+    /// [`ErrorPolicy`](crate::ErrorPolicy) skips it
+    Failed { id: MessageId, error: String },
+    /// The message was taken out of the queue before it started
+    Removed { id: MessageId },
+}
+
+impl QueueEvent {
+    /// The message the event is about
+    pub fn id(&self) -> MessageId {
+        match self {
+            QueueEvent::Enqueued { id }
+            | QueueEvent::Started { id }
+            | QueueEvent::Completed { id, .. }
+            | QueueEvent::Failed { id, .. }
+            | QueueEvent::Removed { id } => *id,
+        }
+    }
+}
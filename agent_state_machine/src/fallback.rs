@@ -0,0 +1,156 @@
+// src/fallback.rs
+
+use futures::future::BoxFuture;
+use rig::completion::{Chat, CompletionError, Message, PromptError};
+use tracing::warn;
+
+/// Object-safe view of a [`Chat`] agent, so agents of different types can share a list
+pub(crate) trait DynChat: Send + Sync {
+    fn chat_boxed<'a>(&'a self, prompt: &'a str, history: Vec<Message>) -> BoxFuture<'a, Result<String, PromptError>>;
+}
+
+impl<C: Chat> DynChat for C {
+    fn chat_boxed<'a>(&'a self, prompt: &'a str, history: Vec<Message>) -> BoxFuture<'a, Result<String, PromptError>> {
+        Box::pin(self.chat(prompt, history))
+    }
+}
+
+/// A [`Chat`] agent that tries several agents in order, e.g. "gpt-4o, then
+/// gpt-4o-mini, then Cohere".
+///
+/// Each request goes to the first agent; when it fails with an error another agent
+/// could avoid (see [`is_retryable`]) the next one gets the same prompt and history,
+/// and the first success is returned. Errors caused by the request itself fail fast
+/// without trying the rest. If every agent fails, the last error is returned.
+///
+/// Since it is a `Chat` agent itself, a state machine can be built over it like any
+/// other:
+///
+/// ```rust,no_run
+/// use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, FallbackChat};
+/// use rig::providers::openai;
+///
+/// let client = openai_client_from_env();
+/// let agent = FallbackChat::new(client.agent(openai::GPT_4O).build())
+///     .fallback(client.agent("gpt-4o-mini").build());
+/// let machine = ChatAgentStateMachine::new(agent);
+/// ```
+pub struct FallbackChat {
+    agents: Vec<Box<dyn DynChat>>,
+}
+
+impl FallbackChat {
+    /// Start a chain with `primary`, the agent every request goes to first
+    pub fn new(primary: impl Chat + 'static) -> Self {
+        Self {
+            agents: vec![Box::new(primary)],
+        }
+    }
+
+    /// Add `agent` to the end of the chain
+    pub fn fallback(mut self, agent: impl Chat + 'static) -> Self {
+        self.agents.push(Box::new(agent));
+        self
+    }
+}
+
+impl Chat for FallbackChat {
+    async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+        let mut agents = self.agents.iter().enumerate().peekable();
+        loop {
+            // The chain always holds at least the primary agent
+            let (i, agent) = agents.next().expect("fallback chain is empty");
+            match agent.chat_boxed(prompt, history.clone()).await {
+                Err(e) if agents.peek().is_some() && is_retryable(&e) => {
+                    warn!("Agent {} of the fallback chain failed ({}), trying the next one", i + 1, e);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether another agent might succeed where this error occurred.
+///
+/// Connection failures, timeouts, rate limits, server errors and provider or response
+/// errors are retryable. Client errors are not: HTTP 4xx responses other than 408 and
+/// 429, requests that could not be built or serialized, and failing tool calls, since
+/// the same request would fail again.
+pub fn is_retryable(error: &PromptError) -> bool {
+    match error {
+        PromptError::CompletionError(CompletionError::HttpError(e)) => match e.status() {
+            Some(status) if status.is_client_error() => matches!(status.as_u16(), 408 | 429),
+            _ => true,
+        },
+        PromptError::CompletionError(CompletionError::ProviderError(_))
+        | PromptError::CompletionError(CompletionError::ResponseError(_)) => true,
+        PromptError::CompletionError(CompletionError::JsonError(_))
+        | PromptError::CompletionError(CompletionError::RequestError(_))
+        | PromptError::ToolError(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatAgentStateMachine;
+    use std::sync::{Arc, Mutex};
+
+    /// Fails every request with the error `make` builds, counting the calls
+    struct BrokenAgent {
+        calls: Arc<Mutex<u32>>,
+        make: fn() -> PromptError,
+    }
+
+    impl Chat for BrokenAgent {
+        async fn chat(&self, _prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            *self.calls.lock().unwrap() += 1;
+            Err((self.make)())
+        }
+    }
+
+    struct EchoAgent;
+
+    impl Chat for EchoAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            Ok(format!("Echo: {}", prompt))
+        }
+    }
+
+    fn outage() -> PromptError {
+        CompletionError::ProviderError("The server is overloaded".into()).into()
+    }
+
+    fn bad_request() -> PromptError {
+        CompletionError::RequestError("invalid tool definition".into()).into()
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_retryable_errors() {
+        let calls = Arc::new(Mutex::new(0));
+        let agent = FallbackChat::new(BrokenAgent {
+            calls: Arc::clone(&calls),
+            make: outage,
+        })
+        .fallback(EchoAgent);
+        let mut machine = ChatAgentStateMachine::new(agent);
+
+        let response = machine.process_single_message("Hello").await.unwrap();
+        assert_eq!(response, "Echo: Hello");
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_fail_fast() {
+        let calls = Arc::new(Mutex::new(0));
+        let agent = FallbackChat::new(BrokenAgent {
+            calls: Arc::clone(&calls),
+            make: bad_request,
+        })
+        .fallback(EchoAgent);
+
+        let error = agent.chat("Hello", vec![]).await.unwrap_err();
+        assert!(error.to_string().contains("invalid tool definition"));
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,143 @@
+// src/format.rs
+
+/// Shapes a raw model response for the place it will be shown.
+///
+/// Formatters run on each response before it reaches the response callback. They may
+/// split a response into several messages, e.g. to respect a chat platform's length
+/// limit; the callback is then called once per message. History always keeps the raw
+/// response.
+pub trait ResponseFormatter: Send + Sync {
+    /// Turn a response into one or more messages ready for display
+    fn format(&self, response: &str) -> Vec<String>;
+}
+
+/// Passes responses through unchanged. This is the default formatter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlainFormatter;
+
+impl ResponseFormatter for PlainFormatter {
+    fn format(&self, response: &str) -> Vec<String> {
+        vec![response.to_string()]
+    }
+}
+
+/// Splits markdown into Discord-sized messages with well-formed code fences.
+///
+/// Messages are split at line boundaries where possible. A code block that spans two
+/// messages is closed at the end of the first and reopened, with the same language
+/// tag, at the start of the next, and a fence left open by the model is closed.
+#[derive(Debug, Clone, Copy)]
+pub struct DiscordFormatter {
+    /// Maximum characters per message
+    pub max_len: usize,
+}
+
+impl Default for DiscordFormatter {
+    /// Discord's limit for a regular message is 2000 characters
+    fn default() -> Self {
+        Self { max_len: 2000 }
+    }
+}
+
+const FENCE: &str = "```";
+
+impl ResponseFormatter for DiscordFormatter {
+    fn format(&self, response: &str) -> Vec<String> {
+        // Room kept free for a fence reopened at the start and one closed at the end
+        const FENCE_ROOM: usize = 32;
+        let line_limit = self.max_len.saturating_sub(FENCE_ROOM).max(1);
+
+        let mut messages = Vec::new();
+        let mut current = String::new();
+        let mut open_fence: Option<String> = None;
+
+        for line in response.lines() {
+            let is_fence = line.trim_start().starts_with(FENCE);
+
+            for piece in split_chars(line, line_limit) {
+                let closing = if open_fence.is_some() { FENCE.len() + 1 } else { 0 };
+                let needed = current.chars().count() + piece.chars().count() + 1 + closing;
+
+                if needed > self.max_len && !current.trim().is_empty() {
+                    if open_fence.is_some() {
+                        current.push('\n');
+                        current.push_str(FENCE);
+                    }
+                    messages.push(std::mem::take(&mut current));
+                    if let Some(fence) = &open_fence {
+                        current.push_str(fence);
+                    }
+                }
+
+                if !current.is_empty() {
+                    current.push('\n');
+                }
+                current.push_str(piece);
+            }
+
+            if is_fence {
+                open_fence = match open_fence {
+                    Some(_) => None,
+                    None => Some(line.trim().to_string()),
+                };
+            }
+        }
+
+        if open_fence.is_some() {
+            current.push('\n');
+            current.push_str(FENCE);
+        }
+        if !current.trim().is_empty() {
+            messages.push(current);
+        }
+
+        messages
+    }
+}
+
+/// Split a line into pieces of at most `limit` characters
+fn split_chars(line: &str, limit: usize) -> Vec<&str> {
+    if line.is_empty() {
+        return vec![line];
+    }
+
+    let mut pieces = Vec::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let cut = rest
+            .char_indices()
+            .nth(limit)
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        pieces.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discord_formatter_balances_fences() {
+        let code = "let x = 1;\n".repeat(300);
+        let response = format!("Here you go:\n```rust\n{}```\nDone.", code);
+
+        let messages = DiscordFormatter::default().format(&response);
+
+        assert!(messages.len() > 1);
+        for message in &messages {
+            assert!(message.chars().count() <= 2000);
+            assert_eq!(message.matches(FENCE).count() % 2, 0);
+        }
+        assert!(messages[1].starts_with("```rust\n"));
+        assert!(messages.last().unwrap().ends_with("Done."));
+    }
+
+    #[test]
+    fn test_discord_formatter_closes_unterminated_fence() {
+        let messages = DiscordFormatter::default().format("```rust\nfn main() {}");
+        assert_eq!(messages, vec!["```rust\nfn main() {}\n```".to_string()]);
+    }
+}
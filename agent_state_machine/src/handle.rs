@@ -0,0 +1,137 @@
+// src/handle.rs
+
+use crate::machine::ChatAgentStateMachine;
+use crate::respond::Respond;
+use crate::state::{AgentState, CustomState};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, warn};
+
+/// Sends messages to a [`ChatAgentStateMachine`] running in a background task, as
+/// returned by [`spawn`](ChatAgentStateMachine::spawn).
+///
+/// Clones send to the same machine, so any number of tasks can enqueue. Once every
+/// handle is dropped, the machine finishes the messages it was already sent and the
+/// task ends.
+pub struct MachineHandle<S: CustomState = String> {
+    messages: mpsc::UnboundedSender<String>,
+    /// Kept only to hand out fresh subscriptions from
+    states: broadcast::Receiver<AgentState<S>>,
+}
+
+impl<S: CustomState> Clone for MachineHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            messages: self.messages.clone(),
+            states: self.states.resubscribe(),
+        }
+    }
+}
+
+impl<S: CustomState> MachineHandle<S> {
+    /// Queue `message` for the machine without waiting for it to be processed. Fails,
+    /// handing the message back, only if the background task has ended.
+    pub fn enqueue(&self, message: impl Into<String>) -> Result<(), mpsc::error::SendError<String>> {
+        self.messages.send(message.into())
+    }
+
+    /// Subscribe to the machine's state changes from now on, as
+    /// [`subscribe_to_state_changes`](ChatAgentStateMachine::subscribe_to_state_changes)
+    /// does
+    pub fn subscribe_to_state_changes(&self) -> broadcast::Receiver<AgentState<S>> {
+        self.states.resubscribe()
+    }
+
+    /// Whether the background task has ended, e.g. because it panicked
+    pub fn is_closed(&self) -> bool {
+        self.messages.is_closed()
+    }
+}
+
+impl<A, S> ChatAgentStateMachine<A, S>
+where
+    A: Respond + 'static,
+    A::Response: 'static,
+    A::Error: Sync + 'static,
+    S: CustomState,
+{
+    /// Move the machine into a background Tokio task and return a handle to send it
+    /// messages, so enqueueing never waits for the agent.
+    ///
+    /// Configure the machine first: responses go to the
+    /// [response callback](Self::set_response_callback), and errors are logged and
+    /// reported through states and queue events as usual. Messages wait in an unbounded
+    /// channel until the machine takes them, so
+    /// [`set_max_queue_len`](Self::set_max_queue_len) doesn't limit them.
+    ///
+    /// # Panics
+    ///
+    /// If called outside a Tokio runtime.
+    pub fn spawn(mut self) -> MachineHandle<S> {
+        let (messages, mut inbox) = mpsc::unbounded_channel::<String>();
+        let states = self.subscribe_to_state_changes();
+        tokio::spawn(async move {
+            while let Some(message) = inbox.recv().await {
+                if let Err(e) = self.process_message(&message).await {
+                    warn!("Background machine dropped a message: {}", e);
+                }
+            }
+            debug!("Every handle was dropped, stopping the background machine");
+            self.shutdown();
+        });
+        MachineHandle { messages, states }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::{Chat, Message, PromptError};
+    use std::sync::{Arc, Mutex};
+    use tokio::time::{sleep, Duration};
+
+    struct EchoAgent;
+
+    impl Chat for EchoAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            sleep(Duration::from_millis(10)).await;
+            Ok(format!("Echo: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_from_several_tasks() {
+        let mut machine = ChatAgentStateMachine::new(EchoAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+
+        let handle = machine.spawn();
+        let mut states = handle.subscribe_to_state_changes();
+        let tasks: Vec<_> = (0..4)
+            .map(|task| {
+                let handle = handle.clone();
+                tokio::spawn(async move {
+                    for message in 0..3 {
+                        handle.enqueue(format!("{}-{}", task, message)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Enqueueing returned straight away; the machine catches up in the background
+        while responses.lock().unwrap().len() < 12 {
+            states.recv().await.unwrap();
+        }
+        let mut responses = responses.lock().unwrap().clone();
+        responses.sort();
+        assert_eq!(responses.first().map(String::as_str), Some("Echo: 0-0"));
+        assert_eq!(responses.last().map(String::as_str), Some("Echo: 3-2"));
+
+        // Dropping every handle stops the task, so the state stream closes
+        drop(handle);
+        while states.recv().await.is_ok() {}
+    }
+}
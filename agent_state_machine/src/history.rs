@@ -0,0 +1,103 @@
+// src/history.rs
+
+use crate::message::{MessageExt, Role};
+use rig::completion::Message;
+use serde::{Deserialize, Serialize};
+
+/// One turn of the machine's chat history.
+///
+/// rig's [`Message`] only has a role and content, which can't record tool use. This
+/// adds tool-call and tool-result turns so follow-up messages still see what a tool
+/// returned. It serializes with a `role` tag, e.g.
+/// `{"role":"tool","name":"arxiv_search","content":"..."}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "snake_case")]
+pub enum HistoryEntry {
+    System { content: String },
+    User { content: String },
+    Assistant { content: String },
+    /// The model asked for a tool to be run
+    ToolCall { name: String, arguments: String },
+    /// What a tool returned
+    Tool { name: String, content: String },
+}
+
+impl HistoryEntry {
+    pub fn user(content: impl Into<String>) -> Self {
+        HistoryEntry::User {
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        HistoryEntry::Assistant {
+            content: content.into(),
+        }
+    }
+
+    /// The role of the turn's author. A tool call is made by the assistant.
+    pub fn role(&self) -> Role {
+        match self {
+            HistoryEntry::System { .. } => Role::System,
+            HistoryEntry::User { .. } => Role::User,
+            HistoryEntry::Assistant { .. } | HistoryEntry::ToolCall { .. } => Role::Assistant,
+            HistoryEntry::Tool { .. } => Role::Tool,
+        }
+    }
+
+    /// The text of the turn; the arguments for a tool call
+    pub fn content(&self) -> &str {
+        match self {
+            HistoryEntry::System { content }
+            | HistoryEntry::User { content }
+            | HistoryEntry::Assistant { content }
+            | HistoryEntry::Tool { content, .. } => content,
+            HistoryEntry::ToolCall { arguments, .. } => arguments,
+        }
+    }
+
+    /// Convert the turn into a message the provider will accept.
+    ///
+    /// OpenAI-style APIs reject `tool` messages that don't answer a native tool call, so
+    /// tool turns are spelled out as text: the call as an assistant message, the result
+    /// as a user message, the same way [`PromptedTools`](crate::PromptedTools) feeds
+    /// results back to the model.
+    pub fn to_message(&self) -> Message {
+        match self {
+            HistoryEntry::System { content } => Message::system(content.as_str()),
+            HistoryEntry::User { content } => Message::user(content.as_str()),
+            HistoryEntry::Assistant { content } => Message::assistant(content.as_str()),
+            HistoryEntry::ToolCall { name, arguments } => {
+                Message::assistant(format!("Calling tool `{}` with arguments: {}", name, arguments))
+            }
+            HistoryEntry::Tool { name, content } => {
+                Message::user(format!("The tool `{}` returned:\n{}", name, content))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_turns_serialize_and_convert() {
+        let result = HistoryEntry::Tool {
+            name: "arxiv_search".into(),
+            content: "[]".into(),
+        };
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"role":"tool","name":"arxiv_search","content":"[]"}"#
+        );
+        let call: HistoryEntry =
+            serde_json::from_str(r#"{"role":"tool_call","name":"arxiv_search","arguments":"{}"}"#).unwrap();
+        assert_eq!(call.role(), Role::Assistant);
+
+        // Tool turns reach the provider as plain user/assistant text
+        let message = result.to_message();
+        assert_eq!(message.role(), Some(Role::User));
+        assert!(message.content.contains("arxiv_search"));
+    }
+}
@@ -4,12 +4,12 @@
 //! 
 //! # Example
 //! ```rust,no_run
-//! use agent_state_machine::{ChatAgentStateMachine, AgentState};
+//! use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, AgentState};
 //! use rig::providers::openai;
 //! 
 //! #[tokio::main]
 //! async fn main() {
-//!     let client = openai::Client::from_env();
+//!     let client = openai_client_from_env();
 //!     let agent = client
 //!         .agent(openai::GPT_4)
 //!         .preamble("You are a helpful AI assistant.")
@@ -17,13 +17,56 @@
 //!     
 //!     let mut state_machine = ChatAgentStateMachine::new(agent);
 //!     
-//!     let response = state_machine.process_message("Hello!").await.unwrap();
+//!     // `prompt` waits for the answer; `process_message` hands it to the response
+//!     // callback instead
+//!     let response = state_machine.prompt("Hello!").await.unwrap();
 //!     println!("Response: {}", response);
 //! }
 //! ```
 
 mod state;
 mod machine;
+mod client;
+mod local;
+mod format;
+mod multiplex;
+mod message;
+mod history;
+mod transcript;
+mod respond;
+mod store;
+mod rate_limit;
+mod error;
+mod tool;
+mod events;
+mod fallback;
+mod orchestrator;
+mod transition;
+mod cancel;
+mod handle;
+mod metrics;
+mod pause;
 
-pub use state::AgentState;
-pub use machine::ChatAgentStateMachine;
\ No newline at end of file
+pub use state::{AgentState, CustomState, StateKind};
+pub use transition::TransitionRules;
+pub use machine::{ChatAgentStateMachine, TRUNCATED_MARKER};
+pub use error::{ErrorPolicy, MachineError, StateError};
+pub use events::{MessageId, Priority, QueueEvent};
+pub use cancel::CancellationHandle;
+pub use handle::MachineHandle;
+pub use metrics::Metrics;
+pub use pause::PauseHandle;
+pub use tool::{StateMachineTool, StateMachineToolArgs};
+pub use client::openai_client_from_env;
+pub use local::{LocalModel, PromptedTools};
+pub use examples_common::shutdown::{shutdown_signal, stdin_lines};
+pub use message::{MessageExt, Role};
+pub use history::HistoryEntry;
+pub use format::{DiscordFormatter, PlainFormatter, ResponseFormatter};
+pub use multiplex::StateMultiplexer;
+pub use orchestrator::{OrchestratedMachine, Orchestrator, Throttled};
+pub use transcript::{TranscriptEntry, TranscriptError};
+pub use respond::{Respond, ToolEvent};
+pub use fallback::{is_retryable, FallbackChat};
+pub use rate_limit::{RateLimited, RATE_LIMITED_STATE};
+pub use store::{FileHistoryStore, HistoryStore, InMemoryHistoryStore, StoreError};
\ No newline at end of file
@@ -0,0 +1,156 @@
+// src/local.rs
+
+use crate::message::MessageExt;
+use rig::completion::{Chat, Message, PromptError};
+use rig::providers::openai;
+use rig::tool::{ToolDyn, ToolSet};
+use serde::Deserialize;
+use tracing::debug;
+
+/// Connection settings for a local OpenAI-compatible server such as llama.cpp or Ollama
+#[derive(Debug, Clone)]
+pub struct LocalModel {
+    /// Server root, without `/v1`
+    pub base_url: String,
+    /// Model name as the server knows it
+    pub model: String,
+    /// Whether the model handles OpenAI-style tool calls natively
+    pub supports_tools: bool,
+}
+
+impl LocalModel {
+    /// Read settings from `OPENAI_BASE_URL` (default `http://localhost:11434`),
+    /// `LOCAL_MODEL` (default `llama3.1`) and `LOCAL_MODEL_TOOLS` (`true`/`1` to use
+    /// native tool calling, off by default).
+    pub fn from_env() -> Self {
+        let supports_tools = std::env::var("LOCAL_MODEL_TOOLS")
+            .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+
+        Self {
+            base_url: std::env::var("OPENAI_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:11434".to_string()),
+            model: std::env::var("LOCAL_MODEL").unwrap_or_else(|_| "llama3.1".to_string()),
+            supports_tools,
+        }
+    }
+
+    /// Build an OpenAI client pointed at the local server
+    pub fn client(&self) -> openai::Client {
+        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_default();
+        openai::Client::from_url(&api_key, self.base_url.trim_end_matches('/'))
+    }
+}
+
+/// A tool invocation requested in plain text by a model without native tool calling
+#[derive(Debug, Deserialize)]
+struct PromptedToolCall {
+    tool: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+/// Wraps a [`Chat`] agent and emulates tool calling through prompt instructions.
+///
+/// Many local models ignore the `tools` field of the request. This adapter describes
+/// the tools in the prompt, asks the model to answer with a JSON object when it wants
+/// one, runs the tool itself, and then asks the model to answer using the result.
+/// Replies that aren't a tool request are returned unchanged.
+pub struct PromptedTools<A: Chat> {
+    agent: A,
+    tools: ToolSet,
+}
+
+impl<A: Chat> PromptedTools<A> {
+    pub fn new(agent: A) -> Self {
+        Self {
+            agent,
+            tools: ToolSet::default(),
+        }
+    }
+
+    /// Register a tool the model may request
+    pub fn tool(mut self, tool: impl ToolDyn + 'static) -> Self {
+        self.tools.add_tool(tool);
+        self
+    }
+
+    async fn instructions(&self) -> Result<String, PromptError> {
+        let docs = self.tools.documents().await?;
+        let descriptions = docs
+            .iter()
+            .map(|doc| doc.text.clone())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(format!(
+            "You can use the following tools:\n{}\n\
+            If one of them is needed to answer, reply with ONLY a JSON object of the form \
+            {{\"tool\": \"<tool name>\", \"arguments\": {{...}}}} and nothing else. \
+            Otherwise, answer the request directly.",
+            descriptions
+        ))
+    }
+}
+
+/// Find a JSON tool request in a model reply, tolerating surrounding prose or code fences
+fn parse_tool_call(response: &str) -> Option<PromptedToolCall> {
+    let start = response.find('{')?;
+    let end = response.rfind('}')?;
+    if end < start {
+        return None;
+    }
+    serde_json::from_str(&response[start..=end]).ok()
+}
+
+impl<A: Chat> Chat for PromptedTools<A> {
+    async fn chat(&self, prompt: &str, chat_history: Vec<Message>) -> Result<String, PromptError> {
+        let instructions = self.instructions().await?;
+        let response = self
+            .agent
+            .chat(&format!("{}\n\n{}", instructions, prompt), chat_history.clone())
+            .await?;
+
+        let call = match parse_tool_call(&response) {
+            Some(call) if self.tools.contains(&call.tool) => call,
+            _ => return Ok(response),
+        };
+
+        debug!("Model requested tool {} via prompt", call.tool);
+        let output = self
+            .tools
+            .call(&call.tool, call.arguments.to_string())
+            .await?;
+
+        let mut history = chat_history;
+        history.push(Message::user(prompt));
+        history.push(Message::assistant(response));
+
+        self.agent
+            .chat(
+                &format!(
+                    "The tool `{}` returned:\n{}\n\nUsing this result, answer the original request: {}",
+                    call.tool, output, prompt
+                ),
+                history,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_call() {
+        let call = parse_tool_call(
+            "```json\n{\"tool\": \"add\", \"arguments\": {\"x\": 1, \"y\": 2}}\n```",
+        )
+        .unwrap();
+        assert_eq!(call.tool, "add");
+        assert_eq!(call.arguments["y"], 2);
+
+        assert!(parse_tool_call("The answer is 3.").is_none());
+    }
+}
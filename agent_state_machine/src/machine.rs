@@ -1,36 +1,246 @@
-use crate::state::AgentState;
-use rig::completion::{Chat, Message, PromptError};
+use crate::cancel::CancellationHandle;
+use crate::error::{ErrorPolicy, MachineError, StateError};
+use crate::events::{MessageId, Priority, QueueEvent};
+use crate::fallback::DynChat;
+use crate::format::{PlainFormatter, ResponseFormatter};
+use crate::history::HistoryEntry;
+use crate::message::MessageExt;
+use crate::pause::PauseHandle;
+use crate::metrics::Metrics;
+use crate::respond::{Respond, ToolEvent};
+use crate::state::{AgentState, CustomState};
+use crate::store::{HistoryStore, StoreError};
+use crate::transition::TransitionRules;
+use rig::completion::{Chat, Message};
 use std::collections::VecDeque;
-use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tracing::{debug, error, info, warn};
 
-/// A state machine for a chat agent that can process messages in a queue
-pub struct ChatAgentStateMachine<A: Chat> {
+/// Appended to responses cut short by a soft deadline, both in history and in the
+/// value handed back to the caller
+pub const TRUNCATED_MARKER: &str = " [truncated]";
+
+/// Asks the agent to condense the conversation when history is cleared with a summary
+const SUMMARY_PROMPT: &str = "Summarize our conversation so far in a few sentences. Keep the facts, \
+decisions and open questions someone would need to continue it, and leave out small talk.";
+
+/// Starts the system note that replaces a summarized history
+const SUMMARY_NOTE_PREFIX: &str = "Summary of the earlier conversation: ";
+
+/// First wait after a rate limit that doesn't say how long to wait
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How many state changes a subscriber may fall behind by default
+const STATE_CHANNEL_CAPACITY: usize = 32;
+
+/// Decides whether a response asks the user for input, returning the question if so
+type InputDetector = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Decides whether a message may be processed, returning the reason if not
+type InputGuard = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// Guesses how many tokens a piece of text takes up
+type TokenEstimator = Box<dyn Fn(&str) -> usize + Send + Sync>;
+
+/// The default token estimate, about four characters per token
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Receives response text as it streams in
+type StreamCallback = Box<dyn Fn(&str) + Send + Sync>;
+
+/// Runs when the machine enters or leaves a state
+type StateHook<S> = Box<dyn Fn(&AgentState<S>) + Send + Sync>;
+
+/// A time limit per message and what to answer with once it passes
+struct SoftDeadline<R> {
+    deadline: Duration,
+    /// Answer for requests that don't stream, which have nothing to show yet
+    fallback: R,
+    /// Turns the text streamed before the deadline into the answer
+    partial: fn(String) -> R,
+}
+
+/// A message waiting in the queue, with the id its lifecycle events carry
+struct QueuedMessage {
+    id: MessageId,
+    text: String,
+    priority: Priority,
+}
+
+/// What one run through the queue produced
+struct QueueRun<E, S: CustomState> {
+    /// Text of each response, in order
+    responses: Vec<String>,
+    /// Messages that failed or timed out and were skipped
+    skipped: Vec<(MessageId, MachineError<E, S>)>,
+    /// The error that stopped the run, if any
+    failure: Option<MachineError<E, S>>,
+}
+
+/// A state machine for a chat agent that can process messages in a queue.
+///
+/// `A` is usually a rig [`Chat`](rig::completion::Chat) agent, whose responses are
+/// strings, but any [`Respond`] implementation works, so agents that answer with
+/// typed values can be driven the same way. `S` is the type of the
+/// [custom states](CustomState) the machine can be moved to, strings by default.
+pub struct ChatAgentStateMachine<A: Respond, S: CustomState = String> {
     /// Current state of the agent
-    current_state: AgentState,
+    current_state: AgentState<S>,
+    /// Which changes `transition_to` accepts
+    transition_rules: TransitionRules,
     /// The underlying agent that handles the chat
     agent: A,
     /// Channel for broadcasting state changes
-    state_tx: broadcast::Sender<AgentState>,
-    /// Chat history
-    history: Vec<Message>,
+    state_tx: broadcast::Sender<AgentState<S>>,
+    /// Channel holding the latest state
+    state_watch: watch::Sender<AgentState<S>>,
+    /// Hooks run with each state the machine enters
+    enter_hooks: Vec<StateHook<S>>,
+    /// Hooks run with each state the machine leaves
+    exit_hooks: Vec<StateHook<S>>,
+    /// Chat history, including tool turns
+    history: Vec<HistoryEntry>,
     /// Queue of messages to process
-    queue: VecDeque<String>,
+    queue: VecDeque<QueuedMessage>,
+    /// Id the next enqueued message gets
+    next_message_id: MessageId,
+    /// Channel for broadcasting queue lifecycle events
+    queue_events_tx: broadcast::Sender<QueueEvent>,
     /// Optional response callback to handle outputs
     response_callback: Option<Box<dyn Fn(String) + Send + Sync>>,
+    /// Optional callback receiving response text as it streams in
+    stream_callback: Option<StreamCallback>,
+    /// Shapes each response before it is handed to the callback
+    formatter: Box<dyn ResponseFormatter>,
+    /// Optional check deciding whether a response asks the user for input
+    input_detector: Option<InputDetector>,
+    /// Optional check refusing messages before they are enqueued
+    input_guard: Option<InputGuard>,
+    /// Cap on the messages waiting in the queue, if any
+    max_queue_len: Option<usize>,
+    /// Whether `shutdown` was called, after which no messages are taken
+    shut_down: bool,
+    /// Optional hard limit on how long one request may take
+    message_timeout: Option<Duration>,
+    /// Lets other tasks abort the current work
+    cancellation: CancellationHandle,
+    /// Whether the queue is held, set here or from other tasks
+    paused: PauseHandle,
+    /// Optional soft deadline per message and the answer to give when it passes
+    soft_deadline: Option<SoftDeadline<A::Response>>,
+    /// Optional system message sent ahead of the history with every request
+    system_preamble: Option<String>,
+    /// Optional text sent to the response callback when a message starts processing
+    thinking_placeholder: Option<String>,
+    /// How many times a failed request is retried
+    max_retries: u32,
+    /// What happens to the rest of the queue when a message fails
+    error_policy: ErrorPolicy,
+    /// Cap on retries across all messages of one queue run, if any
+    retry_budget: Option<u32>,
+    /// Cap on the non-system history entries kept between turns, if any
+    max_history_messages: Option<usize>,
+    /// Agent that condenses old history, and the token budget that triggers it
+    auto_summarize: Option<(Box<dyn DynChat>, usize)>,
+    /// Estimates history size for auto-summarizing
+    token_estimator: TokenEstimator,
+    /// Store the history is saved to after each turn, and the session it is saved under
+    history_store: Option<(Arc<dyn HistoryStore>, String)>,
+    /// How long one message may wait out rate limits in total, if rate limits are waited out
+    max_rate_limit_wait: Option<Duration>,
+    /// Counts and latencies of the messages processed so far
+    metrics: Metrics,
 }
 
-impl<A: Chat> ChatAgentStateMachine<A> {
-    /// Create a new ChatAgentStateMachine with the given agent
+impl<A: Respond> ChatAgentStateMachine<A> {
+    /// Create a new ChatAgentStateMachine with the given agent, using strings for its
+    /// custom states
     pub fn new(agent: A) -> Self {
-        let (state_tx, _) = broadcast::channel(32);
+        Self::new_custom(agent)
+    }
+
+    /// Create a machine whose state subscribers may fall up to `capacity` changes
+    /// behind, instead of the default 32.
+    ///
+    /// A [`subscribe_to_state_changes`](Self::subscribe_to_state_changes) receiver
+    /// that falls further behind gets `RecvError::Lagged` with the number of states it
+    /// missed, then carries on from the oldest state still buffered. Raise the capacity
+    /// for slow consumers that must see every state; the
+    /// [`current_state_watch`](Self::current_state_watch) never lags.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn new_with_capacity(agent: A, capacity: usize) -> Self {
+        Self::new_custom_with_capacity(agent, capacity)
+    }
+
+    /// Create a machine whose every request starts with `prompt` as a system message,
+    /// like calling [`set_system_preamble`](Self::set_system_preamble) after
+    /// [`new`](Self::new).
+    ///
+    /// The prompt is kept apart from the history, so neither the history cap nor
+    /// [`clear_history`](Self::clear_history) can remove it.
+    pub fn with_system_prompt(agent: A, prompt: &str) -> Self {
+        let mut machine = Self::new(agent);
+        machine.set_system_preamble(prompt);
+        machine
+    }
+}
+
+impl<A: Respond, S: CustomState> ChatAgentStateMachine<A, S> {
+    /// Create a new ChatAgentStateMachine with the given agent and custom state type,
+    /// e.g. `ChatAgentStateMachine::<_, NarrativePhase>::new_custom(agent)`
+    pub fn new_custom(agent: A) -> Self {
+        Self::new_custom_with_capacity(agent, STATE_CHANNEL_CAPACITY)
+    }
+
+    /// Create a machine with a custom state type whose state subscribers may fall up
+    /// to `capacity` changes behind; see [`new_with_capacity`](ChatAgentStateMachine::new_with_capacity)
+    pub fn new_custom_with_capacity(agent: A, capacity: usize) -> Self {
+        let (state_tx, _) = broadcast::channel(capacity);
+        let (state_watch, _) = watch::channel(AgentState::Ready);
+        let (queue_events_tx, _) = broadcast::channel(64);
         let machine = Self {
             current_state: AgentState::Ready,
+            transition_rules: TransitionRules::default(),
             agent,
             state_tx,
+            state_watch,
+            enter_hooks: Vec::new(),
+            exit_hooks: Vec::new(),
             history: Vec::new(),
             queue: VecDeque::new(),
+            next_message_id: 0,
+            queue_events_tx,
             response_callback: None,
+            stream_callback: None,
+            formatter: Box::new(PlainFormatter),
+            input_detector: None,
+            input_guard: None,
+            max_queue_len: None,
+            shut_down: false,
+            message_timeout: None,
+            cancellation: CancellationHandle::new(),
+            paused: PauseHandle::default(),
+            soft_deadline: None,
+            system_preamble: None,
+            thinking_placeholder: None,
+            max_retries: 0,
+            error_policy: ErrorPolicy::default(),
+            retry_budget: None,
+            max_history_messages: None,
+            auto_summarize: None,
+            token_estimator: Box::new(estimate_tokens),
+            history_store: None,
+            max_rate_limit_wait: None,
+            metrics: Metrics::default(),
         };
 
         info!("Agent initialized in state: {}", machine.current_state);
@@ -38,6 +248,14 @@ impl<A: Chat> ChatAgentStateMachine<A> {
         machine
     }
 
+    /// Restrict which state changes [`transition_to`](Self::transition_to) accepts.
+    ///
+    /// Without this every change is allowed.
+    pub fn with_transition_rules(mut self, rules: TransitionRules) -> Self {
+        self.transition_rules = rules;
+        self
+    }
+
     /// Set a response callback to handle outputs
     pub fn set_response_callback<F>(&mut self, callback: F)
     where
@@ -46,148 +264,2280 @@ impl<A: Chat> ChatAgentStateMachine<A> {
         self.response_callback = Some(Box::new(callback));
     }
 
-    /// Enqueue a user message for processing
-    pub async fn process_message(&mut self, message: &str) -> Result<(), PromptError> {
-        debug!("Enqueuing message: {}", message);
-        self.queue.push_back(message.to_string());
+    /// Run `hook` with every state the machine enters, e.g. to log transitions or
+    /// update a UI. Hooks run synchronously in the order they were added, after the
+    /// state changed and before subscribers are notified.
+    ///
+    /// They fire for the machine's own transitions as well as for
+    /// [`transition_to`](Self::transition_to), but not for the `Ready` state a new
+    /// machine starts in.
+    pub fn on_enter<F>(&mut self, hook: F)
+    where
+        F: Fn(&AgentState<S>) + Send + Sync + 'static,
+    {
+        self.enter_hooks.push(Box::new(hook));
+    }
+
+    /// Run `hook` with every state the machine leaves, just before the new state is
+    /// set. See [`on_enter`](Self::on_enter).
+    pub fn on_exit<F>(&mut self, hook: F)
+    where
+        F: Fn(&AgentState<S>) + Send + Sync + 'static,
+    {
+        self.exit_hooks.push(Box::new(hook));
+    }
+
+    /// Set the callback that receives response text as it arrives during
+    /// [`process_message_streaming`](Self::process_message_streaming).
+    ///
+    /// Chunks are raw text: the formatter only applies to whole responses. Agents only
+    /// stream if they override [`Respond::respond_streaming`]; rig `Chat` agents pass
+    /// the whole response as a single chunk.
+    pub fn set_stream_callback<F>(&mut self, callback: F)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.stream_callback = Some(Box::new(callback));
+    }
+
+    /// Set the formatter applied to responses before they reach the callback.
+    ///
+    /// The default, [`PlainFormatter`], passes responses through unchanged. If the
+    /// formatter splits a response, the callback is called once per part.
+    pub fn set_formatter<F>(&mut self, formatter: F)
+    where
+        F: ResponseFormatter + 'static,
+    {
+        self.formatter = Box::new(formatter);
+    }
+
+    /// Set a check that decides whether a response is the agent asking the user for input.
+    ///
+    /// When it returns `Some(prompt)`, the machine moves to [`AgentState::AwaitingInput`]
+    /// and stops draining the queue. The next call to `process_message` is treated as the
+    /// user's answer: it is processed ahead of anything still queued, after which the
+    /// queue resumes.
+    pub fn set_input_detector<F>(&mut self, detector: F)
+    where
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+    {
+        self.input_detector = Some(Box::new(detector));
+    }
+
+    /// Set a check that every message must pass before it is enqueued, e.g. a length
+    /// limit or a content filter.
+    ///
+    /// When it returns `Err(reason)`, the message is refused with
+    /// [`MachineError::GuardRejected`] and never reaches the agent.
+    pub fn set_input_guard<F>(&mut self, guard: F)
+    where
+        F: Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.input_guard = Some(Box::new(guard));
+    }
+
+    /// Refuse new messages with [`MachineError::QueueFull`] while `max` messages are
+    /// already waiting. Unbounded by default.
+    pub fn set_max_queue_len(&mut self, max: usize) {
+        self.max_queue_len = Some(max);
+    }
+
+    /// Give up on a request that takes longer than `timeout`, failing it with
+    /// [`MachineError::Timeout`].
+    ///
+    /// Unlike [`set_soft_deadline`](Self::set_soft_deadline) this is an error; like
+    /// other errors, a timed-out request is retried if retries are enabled. When a
+    /// queued message still times out, the machine passes through
+    /// [`AgentState::TimedOut`], skips the message and carries on with the rest of the
+    /// queue, whereas other errors stop it. Off by default.
+    pub fn set_message_timeout(&mut self, timeout: Duration) {
+        self.message_timeout = Some(timeout);
+    }
+
+    /// Hold the queue, e.g. while a bot is in maintenance mode. Messages sent while
+    /// paused are queued but not processed until [`resume`](Self::resume), and
+    /// [`prompt`](Self::prompt) fails with [`MachineError::Paused`].
+    ///
+    /// An idle machine moves to [`AgentState::Paused`] at once. To pause a machine
+    /// that is working through its queue, use a [`pause_handle`](Self::pause_handle)
+    /// from another task: the message in progress is finished first.
+    pub fn pause(&mut self) {
+        self.paused.pause();
+        if matches!(self.current_state, AgentState::Ready) {
+            self.set_state(AgentState::Paused);
+        }
+    }
+
+    /// A handle other tasks can use to pause the machine while it is processing
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.paused.clone()
+    }
+
+    /// Whether the machine is paused or will pause after the message in progress
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_paused()
+    }
 
-        if self.current_state == AgentState::Ready {
+    /// Undo [`pause`](Self::pause) and process the messages that were queued in the
+    /// meantime, in order, before returning
+    pub async fn resume(&mut self) {
+        self.paused.resume();
+        if self.current_state != AgentState::Paused {
+            return;
+        }
+        info!("Resuming with {} queued messages", self.queue.len());
+        self.set_state(AgentState::Ready);
+        if !self.queue.is_empty() {
             self.process_queue().await;
         }
+    }
+
+    /// Stop taking messages, e.g. when the program is exiting.
+    ///
+    /// Messages still waiting in the queue are dropped, each reported as
+    /// [`QueueEvent::Failed`], and every later call that sends a message fails with
+    /// [`MachineError::Shutdown`]. A message already being processed is not interrupted.
+    pub fn shutdown(&mut self) {
+        self.shut_down = true;
+        let dropped: Vec<MessageId> = self.queue.drain(..).map(|message| message.id).collect();
+        if !dropped.is_empty() {
+            info!("Shutting down, dropping {} queued messages", dropped.len());
+        }
+        for id in dropped {
+            self.emit(QueueEvent::Failed {
+                id,
+                error: MachineError::<A::Error>::Shutdown.to_string(),
+            });
+        }
+    }
+
+    /// Set a system message that is sent ahead of the history with every request.
+    ///
+    /// A rig agent's preamble is fixed when it is built; this adds instructions on top
+    /// of it that can change at any time, e.g. to switch a persona mid-conversation.
+    /// Calling it again replaces the previous preamble. The history is left untouched,
+    /// so the conversation carries on under the new instructions.
+    pub fn set_system_preamble(&mut self, preamble: &str) {
+        self.system_preamble = Some(preamble.to_string());
+    }
+
+    /// The system message sent ahead of the history, if one was set
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system_preamble.as_deref()
+    }
+
+    /// Send `placeholder` (e.g. `"…"`) to the response callback as soon as each queued
+    /// message starts processing.
+    ///
+    /// Off by default. A UI can show it while the request is in flight and replace it
+    /// with the response that follows, which is always the next thing the callback
+    /// receives for that message. It is not formatted or recorded in history, and
+    /// nothing is sent if no callback is set.
+    pub fn set_thinking_placeholder(&mut self, placeholder: &str) {
+        self.thinking_placeholder = Some(placeholder.to_string());
+    }
+
+    /// Retry a failed request up to `max_retries` times before giving up on the message.
+    ///
+    /// Off (0) by default. Retries are sent right away with the same history.
+    pub fn set_max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+    }
+
+    /// Choose what happens to the rest of the queue when a message fails.
+    ///
+    /// The default, [`ErrorPolicy::StopQueue`], stops at the failed message and leaves
+    /// the others queued until the next call. With the other policies the failed
+    /// message is dropped after passing through [`AgentState::Error`], and the queue
+    /// carries on.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    /// Cap the total number of retries across all messages handled by one queue run.
+    ///
+    /// During an outage every message would otherwise use its full `max_retries`; once
+    /// the budget is spent, further failures in the same run are returned immediately.
+    /// Each call to `process_message` that drains the queue starts with a fresh budget,
+    /// as does each direct call to `process_single_message`.
+    pub fn set_retry_budget(&mut self, budget: u32) {
+        self.retry_budget = Some(budget);
+    }
+
+    /// Wait out rate limits instead of failing, for up to `max_wait` per message.
+    ///
+    /// When the agent reports a rate limit (see [`Respond::rate_limited`]), the machine
+    /// moves to `AgentState::RateLimited`, sleeps for as long as the provider
+    /// asked, or 1s doubling on each repeat if it didn't say, then returns to its
+    /// previous state and sends the request again. These waits don't use up retries.
+    /// Once a message has waited `max_wait` in total, the rate limit is treated like any
+    /// other error. Off by default.
+    pub fn set_max_rate_limit_wait(&mut self, max_wait: Duration) {
+        self.max_rate_limit_wait = Some(max_wait);
+    }
+
+    /// Keep at most `max` user, assistant and tool entries in history.
+    ///
+    /// Before each turn the oldest turns are dropped whole, starting from a user message,
+    /// so the history never opens with an orphaned answer. System entries are always
+    /// kept. If the latest turn alone is longer than `max`, it is kept in full. This
+    /// counts messages, not tokens, so long messages still make for long requests.
+    pub fn set_max_history_messages(&mut self, max: usize) {
+        self.max_history_messages = Some(max);
+    }
+
+    /// Persist the history to `store` under `session_id` after every turn.
+    ///
+    /// If the store already holds a history for the session it replaces the current
+    /// one, so a conversation picks up where it left off, e.g. after a restart or on
+    /// another instance. Returns whether a saved history was found.
+    pub async fn attach_history_store(
+        &mut self,
+        store: impl HistoryStore + 'static,
+        session_id: &str,
+    ) -> Result<bool, StoreError> {
+        let saved = store.load(session_id).await?;
+        let found = saved.is_some();
+        if let Some(history) = saved {
+            info!("Restored {} history entries for session {}", history.len(), session_id);
+            self.history = history;
+        }
+        self.history_store = Some((Arc::new(store), session_id.to_string()));
+        Ok(found)
+    }
+
+    /// Enqueue a user message for processing.
+    ///
+    /// If the machine is idle the queue is drained before this returns. The returned
+    /// state is the one the machine was left in, so callers don't need to watch state
+    /// notifications (which a lagging subscriber may not have seen yet) to know that
+    /// processing finished.
+    ///
+    /// Empty or whitespace-only messages are rejected with
+    /// [`MachineError::EmptyMessage`] without being enqueued.
+    pub async fn process_message(&mut self, message: &str) -> Result<AgentState<S>, MachineError<A::Error, S>> {
+        self.process_message_with_priority(message, Priority::Normal).await
+    }
+
+    /// Enqueue a user message with the given priority, otherwise like
+    /// [`process_message`](Self::process_message).
+    ///
+    /// A [`Priority::High`] message, such as "stop" in a support bot, is processed
+    /// before every queued normal message but after the message in progress and any
+    /// earlier high-priority ones.
+    pub async fn process_message_with_priority(
+        &mut self,
+        message: &str,
+        priority: Priority,
+    ) -> Result<AgentState<S>, MachineError<A::Error, S>> {
+        self.check_input(message)?;
+        self.check_queue_room(1)?;
+        debug!("Enqueuing {:?} priority message: {}", priority, message);
+
+        match self.current_state {
+            AgentState::Ready => {
+                self.enqueue(&[message], false, priority);
+                self.process_queue().await;
+            }
+            AgentState::AwaitingInput { .. } => {
+                // The awaited answer goes first, then the rest of the queue resumes
+                self.enqueue(&[message], true, priority);
+                self.process_queue().await;
+            }
+            _ => {
+                self.enqueue(&[message], false, priority);
+            }
+        }
+
+        Ok(self.current_state.clone())
+    }
+
+    /// Enqueue several user messages at once, returning how many were enqueued.
+    ///
+    /// The messages are queued together before processing starts, so they are handled
+    /// back to back in the given order with nothing queued in between. As with
+    /// `process_message`, an idle machine drains the queue before this returns, and a
+    /// machine awaiting input treats the first message as the answer. If any message is
+    /// empty, none of them are enqueued.
+    pub async fn process_messages(&mut self, messages: &[&str]) -> Result<usize, MachineError<A::Error, S>> {
+        messages.iter().try_for_each(|message| self.check_input(message))?;
+        self.check_queue_room(messages.len())?;
+        debug!("Enqueuing {} messages", messages.len());
+
+        match self.current_state {
+            AgentState::Ready => {
+                self.enqueue(messages, false, Priority::Normal);
+                self.process_queue().await;
+            }
+            AgentState::AwaitingInput { .. } => {
+                self.enqueue(messages, true, Priority::Normal);
+                self.process_queue().await;
+            }
+            _ => {
+                self.enqueue(messages, false, Priority::Normal);
+            }
+        }
+
+        Ok(messages.len())
+    }
+
+    /// Send a message and wait for the answer.
+    ///
+    /// The message is enqueued and the queue is drained before this returns, like
+    /// `process_message` on an idle machine, with the callback and formatter applied as
+    /// usual, except that responses aren't printed when no callback is set. It returns
+    /// the responses of that run joined by blank lines: normally just the answer to
+    /// `message`, but any messages still waiting in the queue are processed in the same
+    /// run. Unless the machine is paused, the run always starts, whatever the current
+    /// state. If a message fails and
+    /// the [error policy](Self::set_error_policy) stops the queue, that error is
+    /// returned; if `message` itself fails and is skipped, its error is returned.
+    pub async fn prompt(&mut self, message: &str) -> Result<String, MachineError<A::Error, S>> {
+        self.check_input(message)?;
+        if self.is_paused() {
+            return Err(MachineError::Paused);
+        }
+        self.check_queue_room(1)?;
+        debug!("Prompting with message: {}", message);
+
+        let answer = matches!(self.current_state, AgentState::AwaitingInput { .. });
+        let id = self.enqueue(&[message], answer, Priority::Normal)[0];
+        let run = self.run_queue(false).await;
+        if let Some(e) = run.failure {
+            return Err(e);
+        }
+        if let Some((_, e)) = run.skipped.into_iter().find(|(skipped, _)| *skipped == id) {
+            return Err(e);
+        }
+        Ok(run.responses.join("\n\n"))
+    }
 
+    /// Refuse a message the machine won't process: after shutdown, when it is empty,
+    /// or when the input guard rejects it
+    fn check_input(&self, message: &str) -> Result<(), MachineError<A::Error, S>> {
+        if self.shut_down {
+            warn!("Rejecting message after shutdown");
+            return Err(MachineError::Shutdown);
+        }
+        reject_empty(message)?;
+        if let Some(guard) = &self.input_guard {
+            guard(message).map_err(|reason| {
+                warn!("Input guard rejected message: {}", reason);
+                MachineError::GuardRejected(reason)
+            })?;
+        }
         Ok(())
     }
 
-    /// Process messages from the queue
+    /// Refuse to enqueue `count` more messages if that would overfill the queue
+    fn check_queue_room(&self, count: usize) -> Result<(), MachineError<A::Error, S>> {
+        match self.max_queue_len {
+            Some(max) if self.queue.len() + count > max => {
+                warn!("Queue is full, rejecting {} messages", count);
+                Err(MachineError::QueueFull(max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Give each message an id and add them to the queue keeping their order, emitting
+    /// `Enqueued` for each. They go to the front with `front`, otherwise behind the
+    /// messages of the same or higher priority.
+    fn enqueue(&mut self, messages: &[&str], front: bool, priority: Priority) -> Vec<MessageId> {
+        let queued: Vec<QueuedMessage> = messages
+            .iter()
+            .map(|message| {
+                let id = self.next_message_id;
+                self.next_message_id += 1;
+                QueuedMessage {
+                    id,
+                    text: message.to_string(),
+                    priority,
+                }
+            })
+            .collect();
+        let ids: Vec<MessageId> = queued.iter().map(|message| message.id).collect();
+
+        if front {
+            for message in queued.into_iter().rev() {
+                self.queue.push_front(message);
+            }
+        } else if priority == Priority::High {
+            let first_normal = self
+                .queue
+                .iter()
+                .position(|message| message.priority == Priority::Normal)
+                .unwrap_or(self.queue.len());
+            for (offset, message) in queued.into_iter().enumerate() {
+                self.queue.insert(first_normal + offset, message);
+            }
+        } else {
+            self.queue.extend(queued);
+        }
+        for &id in &ids {
+            self.emit(QueueEvent::Enqueued { id });
+        }
+        ids
+    }
+
+    /// Send a queue event to subscribers, if there are any
+    fn emit(&self, event: QueueEvent) {
+        let _ = self.queue_events_tx.send(event);
+    }
+
+    /// Process messages from the queue. Failures are reported through the `Error` and
+    /// `TimedOut` states, so the result of the run isn't needed here.
     async fn process_queue(&mut self) {
-        self.transition_to(AgentState::ProcessingQueue);
+        self.run_queue(true).await;
+    }
 
-        while let Some(message) = self.queue.pop_front() {
-            self.transition_to(AgentState::Processing);
+    /// Process messages from the queue, collecting the responses and errors. Without a
+    /// callback, responses are printed if `print` is set.
+    async fn run_queue(&mut self, print: bool) -> QueueRun<A::Error, S> {
+        self.set_state(AgentState::ProcessingQueue);
+        self.cancellation.reset();
+        let mut retry_budget = self.retry_budget;
+        let max_retries = match self.error_policy {
+            ErrorPolicy::RetryThenSkip { max_retries } => max_retries.max(self.max_retries),
+            _ => self.max_retries,
+        };
+        let mut run = QueueRun {
+            responses: Vec::new(),
+            skipped: Vec::new(),
+            failure: None,
+        };
+
+        while let Some(queued) = self.queue.pop_front() {
+            if self.cancellation.is_cancelled() {
+                self.queue.push_front(queued);
+                self.cancel_queue();
+                run.failure = Some(MachineError::Cancelled);
+                break;
+            }
+            if self.paused.is_paused() {
+                info!("Pausing with {} queued messages", self.queue.len() + 1);
+                self.queue.push_front(queued);
+                self.set_state(AgentState::Paused);
+                return run;
+            }
+            let QueuedMessage { id, text: message, .. } = queued;
+            self.set_state(AgentState::Processing);
+            self.emit(QueueEvent::Started { id });
+            let started = Instant::now();
+            if let (Some(placeholder), Some(callback)) = (&self.thinking_placeholder, &self.response_callback) {
+                callback(placeholder.clone());
+            }
 
-            match self.process_single_message(&message).await {
+            match self.respond(&message, false, max_retries, &mut retry_budget).await {
                 Ok(response) => {
+                    self.emit(QueueEvent::Completed {
+                        id,
+                        duration: started.elapsed(),
+                    });
+                    let text = A::to_text(&response);
+                    let awaiting = self
+                        .input_detector
+                        .as_ref()
+                        .and_then(|detect| detect(&text));
+
                     // Handle the response (e.g., send it to the user)
-                    if let Some(callback) = &self.response_callback {
-                        callback(response);
-                    } else {
-                        println!("Response: {}", response);
+                    for part in self.formatter.format(&text) {
+                        if let Some(callback) = &self.response_callback {
+                            callback(part);
+                        } else if print {
+                            println!("Response: {}", part);
+                        }
+                    }
+
+                    run.responses.push(text);
+
+                    // Pause the queue until the user answers
+                    if let Some(prompt) = awaiting {
+                        self.set_state(AgentState::AwaitingInput { prompt });
+                        return run;
                     }
                 }
+                Err(MachineError::Cancelled) => {
+                    self.emit(QueueEvent::Failed {
+                        id,
+                        error: MachineError::<A::Error>::Cancelled.to_string(),
+                    });
+                    self.cancel_queue();
+                    run.failure = Some(MachineError::Cancelled);
+                    break;
+                }
+                Err(e @ MachineError::Timeout(_)) => {
+                    // A hung request shouldn't hold up the rest of the queue
+                    warn!("Skipping message {}: {}", id, e);
+                    self.emit(QueueEvent::Failed {
+                        id,
+                        error: e.to_string(),
+                    });
+                    self.set_state(AgentState::TimedOut);
+                    run.skipped.push((id, e));
+                }
                 Err(e) => {
                     error!("Error processing message: {}", e);
-                    self.transition_to(AgentState::Error(e.to_string()));
-                    // Decide whether to continue processing or break
-                    // For this example, we'll break on error
-                    break;
+                    self.emit(QueueEvent::Failed {
+                        id,
+                        error: e.to_string(),
+                    });
+                    self.set_state(AgentState::Error(e.to_string()));
+                    if self.error_policy == ErrorPolicy::StopQueue {
+                        run.failure = Some(e);
+                        break;
+                    }
+                    warn!("Skipping message {} and continuing the queue", id);
+                    run.skipped.push((id, e));
                 }
             }
         }
 
         // After processing the queue, transition back to Ready
-        self.transition_to(AgentState::Ready);
+        if self.paused.is_paused() {
+            self.set_state(AgentState::Paused);
+        } else {
+            self.set_state(AgentState::Ready);
+        }
+        run
+    }
+
+    /// Drop the queue after a cancel and clear the request
+    fn cancel_queue(&mut self) {
+        info!("Processing cancelled, dropping {} queued messages", self.queue.len());
+        self.clear_queue();
+        self.cancellation.reset();
     }
 
     /// Process a single message
-    pub async fn process_single_message(&mut self, message: &str) -> Result<String, PromptError> {
+    pub async fn process_single_message(&mut self, message: &str) -> Result<A::Response, MachineError<A::Error, S>> {
+        self.check_input(message)?;
+        self.cancellation.reset();
+        let mut retry_budget = self.retry_budget;
+        self.respond(message, false, self.max_retries, &mut retry_budget).await
+    }
+
+    /// Process a single message like
+    /// [`process_single_message`](Self::process_single_message), passing the response
+    /// text to the [stream callback](Self::set_stream_callback) as it arrives.
+    ///
+    /// The full response is still returned and recorded in history. If the request is
+    /// retried, the retry streams again from the start.
+    pub async fn process_message_streaming(&mut self, message: &str) -> Result<A::Response, MachineError<A::Error, S>> {
+        self.check_input(message)?;
+        self.cancellation.reset();
+        let mut retry_budget = self.retry_budget;
+        self.respond(message, true, self.max_retries, &mut retry_budget).await
+    }
+
+    /// Answer a message, retrying failures up to `max_retries` times while
+    /// `retry_budget` allows. With `stream`, the answer goes to the stream callback as
+    /// it arrives.
+    async fn respond(
+        &mut self,
+        message: &str,
+        stream: bool,
+        max_retries: u32,
+        retry_budget: &mut Option<u32>,
+    ) -> Result<A::Response, MachineError<A::Error, S>> {
         debug!("Processing message: {}", message);
 
-        self.history.push(Message {
-            role: "user".into(),
-            content: message.into(),
-        });
+        self.summarize_old_history().await;
+        self.trim_history();
+
+        // The message is sent as the prompt, so the history sent with it stops short of it
+        let messages: Vec<Message> = self
+            .system_preamble
+            .iter()
+            .map(|preamble| Message::system(preamble.as_str()))
+            .chain(self.history.iter().map(HistoryEntry::to_message))
+            .collect();
+
+        self.history.push(HistoryEntry::user(message));
+
+        let mut attempt = 0;
+        let mut rate_limit_waited = Duration::ZERO;
+        let mut rate_limit_backoff = RATE_LIMIT_BACKOFF;
+        let result = loop {
+            let started = Instant::now();
+            match self.chat(message, messages.clone(), stream).await {
+                Err(MachineError::Agent(e))
+                    if self.wait_out_rate_limit(&e, &mut rate_limit_waited, &mut rate_limit_backoff).await => {}
+                Err(MachineError::Cancelled) => break Err(MachineError::Cancelled),
+                Err(e) if attempt < max_retries => {
+                    if *retry_budget == Some(0) {
+                        warn!("Retry budget exhausted, not retrying: {}", e);
+                        break Err(e);
+                    }
+                    if let Some(budget) = retry_budget.as_mut() {
+                        *budget -= 1;
+                    }
+                    attempt += 1;
+                    warn!("Request failed ({}), retry {}/{}", e, attempt, max_retries);
+                }
+                result => break result.map(|response| (response, started.elapsed())),
+            }
+        };
 
-        match self.agent.chat(message, self.history.clone()).await {
-            Ok(response) => {
-                self.history.push(Message {
-                    role: "assistant".into(),
-                    content: response.clone(),
-                });
-                debug!("Successfully processed message");
+        let result = match result {
+            Ok((response, latency)) => {
+                self.history.push(HistoryEntry::assistant(A::to_text(&response)));
+                self.metrics.record_success(latency);
+                debug!("Successfully processed message in {:?}", latency);
                 Ok(response)
             }
             Err(e) => {
                 error!("Error processing message: {}", e);
+                self.metrics.record_error();
                 Err(e)
             }
+        };
+
+        self.persist_history().await;
+        result
+    }
+
+    /// Save the history to the attached store, if any. A failed save is logged rather
+    /// than failing the turn, since the answer was already produced.
+    async fn persist_history(&self) {
+        if let Some((store, session_id)) = &self.history_store {
+            if let Err(e) = store.save(session_id, &self.history).await {
+                warn!("Failed to save history for session {}: {}", session_id, e);
+            }
+        }
+    }
+
+    /// If `error` is a rate limit and the wait allowance isn't used up, sleep in the
+    /// `RateLimited` state and return true so the request is sent again
+    async fn wait_out_rate_limit(&mut self, error: &A::Error, waited: &mut Duration, backoff: &mut Duration) -> bool {
+        let (Some(max_wait), Some(rate_limited)) = (self.max_rate_limit_wait, A::rate_limited(error)) else {
+            return false;
+        };
+        let remaining = max_wait.saturating_sub(*waited);
+        if remaining.is_zero() {
+            warn!("Still rate limited after waiting {:?}, giving up", waited);
+            return false;
+        }
+
+        let wait = rate_limited.retry_after.unwrap_or(*backoff).min(remaining);
+        if rate_limited.retry_after.is_none() {
+            *backoff *= 2;
+        }
+        *waited += wait;
+
+        warn!("Rate limited, waiting {:?} before resuming", wait);
+        let previous = self.current_state.clone();
+        self.set_state(AgentState::RateLimited);
+        tokio::time::sleep(wait).await;
+        self.set_state(previous);
+        true
+    }
+
+    /// Condense the history with `summary_agent` whenever it grows past
+    /// `token_budget` tokens, instead of only dropping old turns.
+    ///
+    /// Before a message is sent, if the history is over budget, the oldest turns are
+    /// replaced by a single system note with the summary agent's summary of them. The
+    /// newest turns that fit in half the budget are kept as they are, so the next
+    /// few messages don't trigger another summary. If the summary request fails, the
+    /// old turns are dropped instead. Sizes are estimated at four characters per token
+    /// unless [`set_token_estimator`](Self::set_token_estimator) says otherwise.
+    pub fn enable_auto_summarize(&mut self, summary_agent: impl Chat + 'static, token_budget: usize) {
+        self.auto_summarize = Some((Box::new(summary_agent), token_budget));
+    }
+
+    /// Replace the token estimate used by auto-summarizing, e.g. with a real tokenizer
+    pub fn set_token_estimator<F>(&mut self, estimator: F)
+    where
+        F: Fn(&str) -> usize + Send + Sync + 'static,
+    {
+        self.token_estimator = Box::new(estimator);
+    }
+
+    /// If the history is over the auto-summarize budget, replace its oldest turns with
+    /// a summary, or drop them if no summary can be had
+    async fn summarize_old_history(&mut self) {
+        let Some((summary_agent, budget)) = &self.auto_summarize else {
+            return;
+        };
+        let tokens: Vec<usize> = self
+            .history
+            .iter()
+            .map(|entry| (self.token_estimator)(entry.content()))
+            .collect();
+        if tokens.iter().sum::<usize>() <= *budget {
+            return;
+        }
+
+        // Keep the newest whole turns that fit in half the budget, or at least the latest
+        let is_user = |entry: &HistoryEntry| matches!(entry, HistoryEntry::User { .. });
+        let mut kept = 0;
+        let mut cutoff = None;
+        for (i, entry) in self.history.iter().enumerate().rev() {
+            kept += tokens[i];
+            if kept > budget / 2 {
+                break;
+            }
+            if is_user(entry) {
+                cutoff = Some(i);
+            }
+        }
+        let Some(cutoff) = cutoff
+            .or_else(|| self.history.iter().rposition(is_user))
+            .filter(|&cutoff| cutoff > 0)
+        else {
+            return;
+        };
+
+        let old: Vec<Message> = self.history[..cutoff].iter().map(HistoryEntry::to_message).collect();
+        let summary = summary_agent.chat_boxed(SUMMARY_PROMPT, old).await;
+        let replacement = match summary {
+            Ok(summary) => {
+                info!("Summarized {} history entries over the token budget", cutoff);
+                vec![HistoryEntry::System {
+                    content: format!("{}{}", SUMMARY_NOTE_PREFIX, summary.trim()),
+                }]
+            }
+            Err(e) => {
+                warn!("Failed to summarize history ({}), dropping {} entries instead", e, cutoff);
+                Vec::new()
+            }
+        };
+        self.history.splice(..cutoff, replacement);
+    }
+
+    /// Drop the oldest turns until the history fits `max_history_messages`
+    fn trim_history(&mut self) {
+        let Some(max) = self.max_history_messages else {
+            return;
+        };
+        let is_system = |entry: &HistoryEntry| matches!(entry, HistoryEntry::System { .. });
+
+        // Walk back from the newest entry and remember the earliest turn start that fits
+        let mut kept = 0;
+        let mut cutoff = None;
+        for (i, entry) in self.history.iter().enumerate().rev() {
+            if is_system(entry) {
+                continue;
+            }
+            kept += 1;
+            if kept > max {
+                break;
+            }
+            if matches!(entry, HistoryEntry::User { .. }) {
+                cutoff = Some(i);
+            }
+        }
+        // Without a fitting turn start, keep the latest turn whole
+        let cutoff = cutoff.or_else(|| {
+            self.history
+                .iter()
+                .rposition(|entry| matches!(entry, HistoryEntry::User { .. }))
+        });
+
+        if let Some(cutoff) = cutoff {
+            let before = self.history.len();
+            let mut i = 0;
+            self.history.retain(|entry| {
+                i += 1;
+                i > cutoff || is_system(entry)
+            });
+            if self.history.len() < before {
+                debug!("Trimmed {} history entries", before - self.history.len());
+            }
+        }
+    }
+
+    /// Send one request to the agent, honoring the soft deadline, the timeout and
+    /// cancellation
+    async fn chat(
+        &self,
+        message: &str,
+        messages: Vec<Message>,
+        stream: bool,
+    ) -> Result<A::Response, MachineError<A::Error, S>> {
+        let chat = async {
+            let on_chunk = self.stream_callback.as_deref().filter(|_| stream);
+            // Streamed text so far, the partial answer if the soft deadline passes
+            let streamed = std::sync::Mutex::new(String::new());
+            let chat = async {
+                match on_chunk {
+                    Some(on_chunk) => {
+                        let on_chunk = |chunk: &str| {
+                            streamed.lock().unwrap().push_str(chunk);
+                            on_chunk(chunk);
+                        };
+                        self.agent.respond_streaming(message, messages, &on_chunk).await
+                    }
+                    None => {
+                        let on_tool = |event: ToolEvent| self.announce_tool(event);
+                        self.agent.respond_with_tools(message, messages, &on_tool).await
+                    }
+                }
+            };
+            match &self.soft_deadline {
+                Some(soft) => match tokio::time::timeout(soft.deadline, chat).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        warn!("Soft deadline of {:?} reached, returning partial answer", soft.deadline);
+                        if on_chunk.is_some() {
+                            Ok((soft.partial)(streamed.into_inner().unwrap()))
+                        } else {
+                            Ok(soft.fallback.clone())
+                        }
+                    }
+                },
+                None => chat.await,
+            }
+        };
+        let request = async {
+            match self.message_timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, chat).await {
+                    Ok(result) => result.map_err(MachineError::Agent),
+                    Err(_) => {
+                        warn!("No response within {:?}, giving up on the request", timeout);
+                        Err(MachineError::Timeout(timeout))
+                    }
+                },
+                None => chat.await.map_err(MachineError::Agent),
+            }
+        };
+        // Dropping the request on cancel discards any partial answer
+        tokio::select! {
+            result = request => result,
+            _ = self.cancellation.cancelled() => Err(MachineError::Cancelled),
         }
     }
 
     /// Get the current state
-    pub fn current_state(&self) -> &AgentState {
+    pub fn current_state(&self) -> &AgentState<S> {
         &self.current_state
     }
 
+    /// Counts and latencies of the messages processed so far, over every way of
+    /// sending a message
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
     /// Get the chat history
-    pub fn history(&self) -> &[Message] {
+    pub fn history(&self) -> &[HistoryEntry] {
         &self.history
     }
 
-    /// Subscribe to state changes
-    pub fn subscribe_to_state_changes(&self) -> broadcast::Receiver<AgentState> {
-        self.state_tx.subscribe()
+    /// The chat history as rig messages, as it is sent to the agent
+    pub fn export_history(&self) -> Vec<Message> {
+        self.history.iter().map(HistoryEntry::to_message).collect()
     }
 
-    /// Clear the chat history
-    pub fn clear_history(&mut self) {
-        self.history.clear();
+    /// Write the history to `path` as a JSON array of [`HistoryEntry`], e.g. to resume
+    /// the conversation in a later run with [`load_history`](Self::load_history)
+    pub fn save_history<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.history)?)
     }
 
-    pub fn transition_to(&mut self, new_state: AgentState) {
-        debug!("State transition: {} -> {}", self.current_state, new_state);
-        self.current_state = new_state.clone();
-        let _ = self.state_tx.send(new_state);
+    /// Replace the history with one saved by [`save_history`](Self::save_history).
+    /// On error the current history is kept.
+    pub fn load_history<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let json = std::fs::read_to_string(path)?;
+        self.history = serde_json::from_str(&json)?;
+        info!("Loaded {} history entries", self.history.len());
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::future::Future;
-    use std::pin::Pin;
-    use tokio::time::{sleep, Duration};
+    /// The messages waiting in the queue, next first, with their ids.
+    ///
+    /// The message being processed has already left the queue, so it is never listed
+    /// here and can't be moved or removed.
+    pub fn pending(&self) -> impl ExactSizeIterator<Item = (MessageId, &str)> + '_ {
+        self.queue.iter().map(|message| (message.id, message.text.as_str()))
+    }
 
-    struct MockAgent;
+    /// A handle other tasks can use to cancel the message being processed and drop
+    /// the queue, e.g. when a user types `/cancel`. Take it before moving the machine
+    /// into the task that drives it.
+    ///
+    /// A cancelled request fails with [`MachineError::Cancelled`] and leaves no
+    /// assistant message in history; the machine then returns to `Ready`.
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        self.cancellation.clone()
+    }
 
-    impl Chat for MockAgent {
-        fn chat<'a>(
-            &'a self,
-            prompt: &'a str,
-            _history: Vec<Message>,
-        ) -> Pin<Box<dyn Future<Output = Result<String, PromptError>> + Send + 'a>> {
-            let response = format!("Echo: {}", prompt);
-            Box::pin(async move {
-                // Simulate some processing delay
-                sleep(Duration::from_millis(50)).await;
-                Ok(response)
-            })
+    /// How many messages are waiting in the queue, e.g. to push back on senders when
+    /// a bot falls behind
+    pub fn pending_count(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether the machine is `Ready` with nothing queued
+    pub fn is_idle(&self) -> bool {
+        self.current_state == AgentState::Ready && self.queue.is_empty()
+    }
+
+    /// Drop every queued message, reporting each as [`QueueEvent::Removed`]. History
+    /// and the message being processed, if any, are left alone.
+    pub fn clear_queue(&mut self) {
+        debug!("Clearing {} queued messages", self.queue.len());
+        while let Some(message) = self.queue.pop_front() {
+            self.emit(QueueEvent::Removed { id: message.id });
         }
     }
 
-    #[tokio::test]
-    async fn test_process_message_queue() {
-        let mut machine = ChatAgentStateMachine::new(MockAgent);
-        let mut responses = Vec::new();
+    /// Move the queued message `id` to the front so it is processed next. Returns
+    /// false if it is no longer queued.
+    pub fn move_to_front(&mut self, id: MessageId) -> bool {
+        let Some(index) = self.queue.iter().position(|message| message.id == id) else {
+            return false;
+        };
+        if let Some(message) = self.queue.remove(index) {
+            self.queue.push_front(message);
+        }
+        true
+    }
+
+    /// Take the queued message `id` out of the queue, e.g. to cancel a request that
+    /// hasn't started. Returns its text, or `None` if it is no longer queued; a removed
+    /// message is reported as [`QueueEvent::Removed`].
+    pub fn remove_pending(&mut self, id: MessageId) -> Option<String> {
+        let index = self.queue.iter().position(|message| message.id == id)?;
+        let message = self.queue.remove(index)?;
+        debug!("Removed queued message {}", id);
+        self.emit(QueueEvent::Removed { id });
+        Some(message.text)
+    }
 
-        machine.set_response_callback(|response| {
-            responses.push(response);
+    /// Record that a tool was called, so later turns know the request was made
+    pub fn record_tool_call(&mut self, name: &str, arguments: impl Into<String>) {
+        self.history.push(HistoryEntry::ToolCall {
+            name: name.to_string(),
+            arguments: arguments.into(),
         });
+    }
 
-        machine.process_message("Message 1").await.unwrap();
-        machine.process_message("Message 2").await.unwrap();
-        machine.process_message("Message 3").await.unwrap();
+    /// Record a tool's output, so later turns can refer to it
+    pub fn record_tool_result(&mut self, name: &str, content: impl Into<String>) {
+        self.history.push(HistoryEntry::Tool {
+            name: name.to_string(),
+            content: content.into(),
+        });
+    }
+
+    /// Subscribe to every state change.
+    ///
+    /// Use this when each transition matters, e.g. for logging. Only transitions made
+    /// after subscribing are received, and a receiver that falls more than 32 states
+    /// behind, or the capacity given to
+    /// [`new_with_capacity`](ChatAgentStateMachine::new_with_capacity), gets
+    /// `RecvError::Lagged` and skips ahead.
+    pub fn subscribe_to_state_changes(&self) -> broadcast::Receiver<AgentState<S>> {
+        self.state_tx.subscribe()
+    }
+
+    /// Subscribe to the lifecycle of queued messages.
+    ///
+    /// Each message passed to `process_message` or `process_messages` gets an id and
+    /// produces [`QueueEvent::Enqueued`], then [`QueueEvent::Started`] when the agent
+    /// is asked, then [`QueueEvent::Completed`] with how long it took or
+    /// [`QueueEvent::Failed`]. Queue depth is the number of messages enqueued but not
+    /// yet started. `process_single_message` bypasses the queue and emits nothing. As
+    /// with state changes, a receiver more than 64 events behind gets `Lagged`.
+    pub fn subscribe_to_queue_events(&self) -> broadcast::Receiver<QueueEvent> {
+        self.queue_events_tx.subscribe()
+    }
+
+    /// Watch the latest state.
+    ///
+    /// Use this when only the current state matters, e.g. for a status display. The
+    /// receiver can read the state immediately with `borrow()`, however late it was
+    /// created, and `changed()` wakes it when the state moves on. Intermediate states
+    /// may be skipped if several transitions happen between reads.
+    pub fn current_state_watch(&self) -> watch::Receiver<AgentState<S>> {
+        self.state_watch.subscribe()
+    }
+
+    /// Clear the chat history. The [system prompt](Self::system_prompt) isn't part of
+    /// the history and stays in place.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Take back the last exchange: the last user message and the reply after it,
+    /// including any tool turns in between.
+    ///
+    /// Returns false and changes nothing if the history doesn't end with a reply to a
+    /// user message, or while a message is being processed, since its reply would
+    /// land after the cut.
+    pub fn undo_last_exchange(&mut self) -> bool {
+        if matches!(self.current_state, AgentState::Processing | AgentState::ProcessingQueue) {
+            warn!("Not undoing while a message is being processed");
+            return false;
+        }
+        if !matches!(self.history.last(), Some(HistoryEntry::Assistant { .. })) {
+            return false;
+        }
+        let Some(user) = self.history.iter().rposition(|entry| matches!(entry, HistoryEntry::User { .. })) else {
+            return false;
+        };
+        debug!("Undoing the last exchange ({} entries)", self.history.len() - user);
+        self.history.truncate(user);
+        true
+    }
+
+    /// Clear the chat history, but keep a summary of it as a single system note.
+    ///
+    /// The agent is asked to summarize the conversation first, so it keeps the gist
+    /// after the reset. If that request fails, the history is left as it was. An
+    /// empty history is left empty.
+    pub async fn clear_history_keeping_summary(&mut self) -> Result<(), MachineError<A::Error, S>> {
+        if self.history.is_empty() {
+            return Ok(());
+        }
+        let summary = self.summarize().await.map_err(MachineError::Agent)?;
+        info!("Replacing {} history entries with a summary", self.history.len());
+        self.history = vec![HistoryEntry::System {
+            content: format!("{}{}", SUMMARY_NOTE_PREFIX, summary.trim()),
+        }];
+        self.persist_history().await;
+        Ok(())
+    }
+
+    /// Ask the agent for a summary of the history, without recording the exchange
+    async fn summarize(&self) -> Result<String, A::Error> {
+        let messages: Vec<Message> = self.history.iter().map(HistoryEntry::to_message).collect();
+        let response = self.agent.respond(SUMMARY_PROMPT, messages).await?;
+        Ok(A::to_text(&response))
+    }
+
+    /// Move the machine to `new_state`, notifying subscribers and watchers as every
+    /// transition does.
+    ///
+    /// This is how wrappers drive their own sub-states, e.g.
+    /// `AgentState::Custom("GeneratingNarrative".into())` before a request and
+    /// `AgentState::Ready` after it. It bypasses the queue: nothing is processed or
+    /// dropped, and the state is not checked. The machine moves through its built-in
+    /// states itself while processing, so a custom state set before a call is replaced
+    /// once the queue runs. Note that `process_message` only drains the queue from
+    /// `Ready` or `AwaitingInput`, so messages sent while a custom state is set wait
+    /// until the state is moved back.
+    ///
+    /// If the [transition rules](Self::with_transition_rules) don't allow the change,
+    /// the state is left as it is and [`StateError::InvalidTransition`] is returned.
+    pub fn transition_to(&mut self, new_state: AgentState<S>) -> Result<(), StateError<S>> {
+        if !self
+            .transition_rules
+            .is_allowed(self.current_state.kind(), new_state.kind())
+        {
+            warn!("Refusing state transition: {} -> {}", self.current_state, new_state);
+            return Err(StateError::InvalidTransition {
+                from: self.current_state.clone(),
+                to: new_state,
+            });
+        }
+        self.set_state(new_state);
+        Ok(())
+    }
+
+    /// Move to `new_state` without checking the transition rules, for the machine's
+    /// own transitions
+    fn set_state(&mut self, new_state: AgentState<S>) {
+        debug!("State transition: {} -> {}", self.current_state, new_state);
+        let old_state = std::mem::replace(&mut self.current_state, new_state.clone());
+        self.notify(&old_state, new_state);
+    }
+
+    /// Show a tool the agent runs as `CallingTool`, then return to the current state.
+    ///
+    /// The request in flight borrows the machine, so `current_state` isn't updated;
+    /// nothing can read it before the request ends anyway.
+    fn announce_tool(&self, event: ToolEvent) {
+        let calling = |name| AgentState::CallingTool { name };
+        match event {
+            ToolEvent::Started { name } => {
+                debug!("Agent is calling tool {}", name);
+                self.notify(&self.current_state, calling(name));
+            }
+            ToolEvent::Finished { name } => self.notify(&calling(name), self.current_state.clone()),
+        }
+    }
+
+    /// Run the hooks for leaving `old_state` and entering `new_state`, then tell
+    /// watchers and subscribers
+    fn notify(&self, old_state: &AgentState<S>, new_state: AgentState<S>) {
+        for hook in &self.exit_hooks {
+            hook(old_state);
+        }
+        for hook in &self.enter_hooks {
+            hook(&new_state);
+        }
+        self.state_watch.send_replace(new_state.clone());
+        let _ = self.state_tx.send(new_state);
+    }
+}
+
+/// Refuse messages with nothing in them, which would only waste a request
+fn reject_empty<E, S: CustomState>(message: &str) -> Result<(), MachineError<E, S>> {
+    if message.trim().is_empty() {
+        warn!("Rejecting empty message");
+        return Err(MachineError::EmptyMessage);
+    }
+    Ok(())
+}
+
+impl<A: Respond<Response = String>, S: CustomState> ChatAgentStateMachine<A, S> {
+    /// Give each message a soft deadline.
+    ///
+    /// Unlike a hard timeout this never errors: when `deadline` passes, the in-flight
+    /// request is cancelled and whatever was produced so far is returned instead. For
+    /// [streaming](Self::process_message_streaming) requests that is the text streamed
+    /// before the deadline. Other responses arrive in one piece, so for them it is the
+    /// `fallback` text. The returned response and its history entry both end with
+    /// [`TRUNCATED_MARKER`].
+    pub fn set_soft_deadline(&mut self, deadline: Duration, fallback: &str) {
+        self.soft_deadline = Some(SoftDeadline {
+            deadline,
+            fallback: format!("{}{}", fallback, TRUNCATED_MARKER),
+            partial: |streamed| format!("{}{}", streamed, TRUNCATED_MARKER),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Role;
+    use rig::completion::{Chat, PromptError};
+    use std::sync::{Arc, Mutex};
+    use tokio::time::{sleep, Duration};
+
+    struct MockAgent;
+
+    impl Chat for MockAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            // Simulate some processing delay; "hang" stands in for a provider that stalls
+            let delay = if prompt == "hang" { 5_000 } else { 50 };
+            sleep(Duration::from_millis(delay)).await;
+            Ok(format!("Echo: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_message_queue() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| {
+            sink.lock().unwrap().push(response);
+        });
+
+        machine.process_message("Message 1").await.unwrap();
+        machine.process_message("Message 2").await.unwrap();
+        machine.process_message("Message 3").await.unwrap();
 
         // Wait until processing is complete
         while machine.current_state() != &AgentState::Ready {
             sleep(Duration::from_millis(10)).await;
         }
 
+        let responses = responses.lock().unwrap();
         assert_eq!(responses.len(), 3);
         assert_eq!(responses[0], "Echo: Message 1");
         assert_eq!(responses[1], "Echo: Message 2");
         assert_eq!(responses[2], "Echo: Message 3");
     }
 
+    #[tokio::test]
+    async fn test_process_messages_keeps_batch_together() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| {
+            sink.lock().unwrap().push(response);
+        });
+        machine.set_input_detector(|response| response.ends_with('?').then(|| "Answer".to_string()));
+
+        // The question pauses the queue with "Later" still waiting
+        machine.enqueue(&["Which way?", "Later"], false, Priority::Normal);
+        machine.process_queue().await;
+
+        // The batch answers the question and runs before "Later"
+        assert_eq!(machine.process_messages(&["Left", "Then right"]).await.unwrap(), 2);
+        assert_eq!(
+            responses.lock().unwrap().as_slice(),
+            ["Echo: Which way?", "Echo: Left", "Echo: Then right", "Echo: Later"]
+        );
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+    }
+
+    #[tokio::test]
+    async fn test_thinking_placeholder_precedes_each_response() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| {
+            sink.lock().unwrap().push(response);
+        });
+        machine.set_thinking_placeholder("…");
+
+        machine.process_messages(&["A", "B"]).await.unwrap();
+        assert_eq!(responses.lock().unwrap().as_slice(), ["…", "Echo: A", "…", "Echo: B"]);
+        assert_eq!(machine.history().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_empty_messages_are_rejected() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let mut state_rx = machine.subscribe_to_state_changes();
+
+        assert!(matches!(machine.process_message("  \n").await, Err(MachineError::EmptyMessage)));
+        assert!(matches!(
+            machine.process_messages(&["Hi", ""]).await,
+            Err(MachineError::EmptyMessage)
+        ));
+        assert!(matches!(machine.process_single_message("").await, Err(MachineError::EmptyMessage)));
+
+        // Nothing was queued, sent or recorded
+        assert!(machine.queue.is_empty());
+        assert!(machine.history().is_empty());
+        assert!(state_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_final_state_is_observable() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let mut state_rx = machine.subscribe_to_state_changes();
+
+        let final_state = machine.process_message("Hello").await.unwrap();
+        assert_eq!(final_state, AgentState::Ready);
+
+        // Every transition was broadcast before process_message returned
+        let mut states = Vec::new();
+        while let Ok(state) = state_rx.try_recv() {
+            states.push(state);
+        }
+        assert_eq!(
+            states,
+            vec![AgentState::ProcessingQueue, AgentState::Processing, AgentState::Ready]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_current_state_watch_sees_latest_state() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.process_message("Hello").await.unwrap();
+
+        // A watcher created after the fact still sees the current state right away
+        let state_watch = machine.current_state_watch();
+        assert_eq!(*state_watch.borrow(), AgentState::Ready);
+
+        machine.transition_to(AgentState::Processing).unwrap();
+        assert_eq!(*state_watch.borrow(), AgentState::Processing);
+    }
+
+    #[tokio::test]
+    async fn test_awaiting_input_pauses_queue() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| {
+            sink.lock().unwrap().push(response);
+        });
+        machine.set_input_detector(|response| {
+            response
+                .ends_with('?')
+                .then(|| "Answer the question".to_string())
+        });
+
+        // Queue a message behind the question so we can check it waits
+        machine.enqueue(&["Left or right?", "Later"], false, Priority::Normal);
+        machine.process_queue().await;
+
+        assert_eq!(
+            machine.current_state(),
+            &AgentState::AwaitingInput {
+                prompt: "Answer the question".into()
+            }
+        );
+        assert_eq!(machine.queue.len(), 1);
+        assert_eq!(responses.lock().unwrap().as_slice(), ["Echo: Left or right?"]);
+
+        machine.process_message("Left").await.unwrap();
+
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+        assert_eq!(
+            responses.lock().unwrap().as_slice(),
+            ["Echo: Left or right?", "Echo: Left", "Echo: Later"]
+        );
+    }
+
+    struct SlowAgent;
+
+    impl Chat for SlowAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            sleep(Duration::from_secs(5)).await;
+            Ok(format!("Echo: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_soft_deadline_returns_fallback() {
+        let mut machine = ChatAgentStateMachine::new(SlowAgent);
+        machine.set_soft_deadline(Duration::from_millis(50), "Still thinking...");
+
+        let response = machine.process_single_message("Hello").await.unwrap();
+
+        assert_eq!(response, "Still thinking... [truncated]");
+        assert_eq!(machine.history().last().unwrap().content(), response);
+        assert_eq!(machine.history().last().unwrap().role(), Role::Assistant);
+    }
+
+    #[tokio::test]
+    async fn test_soft_deadline_returns_streamed_text() {
+        let mut machine = ChatAgentStateMachine::new(StallingStreamer);
+        let chunks = collect_chunks(&mut machine);
+        machine.set_soft_deadline(Duration::from_millis(50), "Still thinking...");
+
+        let response = machine.process_message_streaming("Hello").await.unwrap();
+        assert_eq!(response, "The answer is  [truncated]");
+        assert_eq!(*chunks.lock().unwrap(), ["The ", "answer ", "is "]);
+        assert_eq!(machine.history().last().unwrap().content(), response);
+
+        // Without streaming there is nothing partial to return
+        let response = machine.process_single_message("Hello").await.unwrap();
+        assert_eq!(response, "Still thinking... [truncated]");
+    }
+
+    /// Replies with the system messages it was sent
+    struct SystemEcho;
+
+    impl Chat for SystemEcho {
+        async fn chat(&self, _prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            let system: Vec<String> = history
+                .into_iter()
+                .filter(|m| m.role() == Some(Role::System))
+                .map(|m| m.content)
+                .collect();
+            Ok(system.join("|"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_system_preamble_is_replaced_not_stored() {
+        let mut machine = ChatAgentStateMachine::new(SystemEcho);
+        assert_eq!(machine.process_single_message("Hi").await.unwrap(), "");
+
+        machine.set_system_preamble("Write horror.");
+        machine.set_system_preamble("Write comedy.");
+        assert_eq!(machine.process_single_message("Go on").await.unwrap(), "Write comedy.");
+        assert_eq!(machine.history().len(), 4);
+    }
+
+    /// Replies with every message content it received, prompt last
+    struct RecordingAgent;
+
+    impl Chat for RecordingAgent {
+        async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            let mut received: Vec<String> = history.into_iter().map(|m| m.content).collect();
+            received.push(prompt.to_string());
+            Ok(received.join("|"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_is_not_repeated_in_history() {
+        let mut machine = ChatAgentStateMachine::new(RecordingAgent);
+        assert_eq!(machine.process_single_message("Hi").await.unwrap(), "Hi");
+        assert_eq!(machine.process_single_message("Again").await.unwrap(), "Hi|Hi|Again");
+    }
+
+    #[tokio::test]
+    async fn test_max_history_messages_keeps_whole_turns() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.history.push(HistoryEntry::System {
+            content: "Be brief.".into(),
+        });
+        for i in 1..=5 {
+            machine.history.push(HistoryEntry::user(format!("Q{}", i)));
+            machine.history.push(HistoryEntry::assistant(format!("A{}", i)));
+        }
+
+        machine.set_max_history_messages(4);
+        machine.trim_history();
+
+        let contents: Vec<&str> = machine.history().iter().map(HistoryEntry::content).collect();
+        assert_eq!(contents, ["Be brief.", "Q4", "A4", "Q5", "A5"]);
+
+        // A cap that splits a turn drops the whole turn instead
+        machine.set_max_history_messages(3);
+        machine.trim_history();
+        let contents: Vec<&str> = machine.history().iter().map(HistoryEntry::content).collect();
+        assert_eq!(contents, ["Be brief.", "Q5", "A5"]);
+    }
+
+    /// Replies with how many history messages came with the prompt
+    struct HistoryCounter;
+
+    impl Chat for HistoryCounter {
+        async fn chat(&self, _prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            Ok(history.len().to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_history_messages_caps_long_sessions() {
+        let mut machine = ChatAgentStateMachine::new(HistoryCounter);
+        machine.set_max_history_messages(4);
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&sent);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+
+        let messages: Vec<String> = (0..20).map(|i| format!("Message {}", i)).collect();
+        let messages: Vec<&str> = messages.iter().map(String::as_str).collect();
+        machine.process_messages(&messages).await.unwrap();
+
+        // Requests grow until the cap and then stay there
+        let sent: Vec<usize> = sent.lock().unwrap().iter().map(|n| n.parse().unwrap()).collect();
+        assert_eq!(sent[..3], [0, 2, 4]);
+        assert!(sent.iter().all(|&n| n <= 4));
+        // Between turns the history holds the capped part plus the latest turn
+        assert_eq!(machine.history().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_history() {
+        let path = std::env::temp_dir().join(format!("history-test-{}.json", std::process::id()));
+        let mut machine = ChatAgentStateMachine::new(RecordingAgent);
+        machine.process_single_message("Hi").await.unwrap();
+        machine.record_tool_call("search", r#"{"query":"rust"}"#);
+        machine.record_tool_result("search", "3 results");
+        machine.save_history(&path).unwrap();
+
+        let mut resumed = ChatAgentStateMachine::new(RecordingAgent);
+        resumed.load_history(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(resumed.history(), machine.history());
+        assert_eq!(resumed.export_history().len(), 4);
+
+        // The resumed conversation carries on with the restored context
+        let response = resumed.process_single_message("More").await.unwrap();
+        assert!(response.starts_with("Hi|Hi|Calling tool `search`"));
+        assert!(response.ends_with("3 results|More"));
+
+        assert!(resumed.load_history("/nonexistent/history.json").is_err());
+        assert_eq!(resumed.history().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_history_store_persists_each_turn() {
+        let store = crate::store::InMemoryHistoryStore::new();
+
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        assert!(!machine.attach_history_store(store.clone(), "alice").await.unwrap());
+        machine.process_message("Hello").await.unwrap();
+
+        // Another machine on the same session continues the conversation
+        let mut resumed = ChatAgentStateMachine::new(MockAgent);
+        assert!(resumed.attach_history_store(store.clone(), "alice").await.unwrap());
+        assert_eq!(resumed.history(), machine.history());
+
+        resumed.process_message("Again").await.unwrap();
+        assert_eq!(store.load("alice").await.unwrap().unwrap().len(), 4);
+        assert!(store.load("bob").await.unwrap().is_none());
+    }
+
+    /// Rate limited on the first two calls
+    struct RateLimitedAgent(Arc<Mutex<u32>>);
+
+    impl Chat for RateLimitedAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            let mut calls = self.0.lock().unwrap();
+            *calls += 1;
+            if *calls <= 2 {
+                return Err(rig::completion::CompletionError::ProviderError(
+                    "Rate limit reached. Please try again in 20ms.".into(),
+                )
+                .into());
+            }
+            Ok(format!("Echo: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_are_waited_out() {
+        let calls = Arc::new(Mutex::new(0));
+        let mut machine = ChatAgentStateMachine::new(RateLimitedAgent(Arc::clone(&calls)));
+        machine.set_max_rate_limit_wait(Duration::from_secs(1));
+        let mut state_rx = machine.subscribe_to_state_changes();
+
+        assert_eq!(machine.process_message("Hi").await.unwrap(), AgentState::Ready);
+        assert_eq!(*calls.lock().unwrap(), 3);
+        assert_eq!(machine.history().last().unwrap().content(), "Echo: Hi");
+
+        let mut states = Vec::new();
+        while let Ok(state) = state_rx.try_recv() {
+            states.push(state);
+        }
+        let rate_limited = AgentState::RateLimited;
+        assert_eq!(states.iter().filter(|state| **state == rate_limited).count(), 2);
+        assert_eq!(states[2..4], [rate_limited, AgentState::Processing]);
+
+        // Without a wait allowance the rate limit is an ordinary error
+        *calls.lock().unwrap() = 0;
+        machine.max_rate_limit_wait = None;
+        machine.process_single_message("Hi").await.unwrap_err();
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    /// Always fails, counting how often it was called
+    struct FailingAgent(Arc<Mutex<u32>>);
+
+    impl Chat for FailingAgent {
+        async fn chat(&self, _prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            *self.0.lock().unwrap() += 1;
+            Err(rig::completion::CompletionError::ProviderError("outage".into()).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_bounds_chat_calls() {
+        let calls = Arc::new(Mutex::new(0));
+        let mut machine = ChatAgentStateMachine::new(FailingAgent(Arc::clone(&calls)));
+        machine.set_max_retries(5);
+        machine.set_retry_budget(3);
+
+        // One attempt plus at most three retries, instead of six calls per message
+        machine.enqueue(&["A", "B", "C"], false, Priority::Normal);
+        machine.process_queue().await;
+        assert_eq!(*calls.lock().unwrap(), 4);
+
+        // Without a budget each message uses its full retries
+        *calls.lock().unwrap() = 0;
+        machine.retry_budget = None;
+        machine.process_single_message("D").await.unwrap_err();
+        assert_eq!(*calls.lock().unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_transition_rules() {
+        use crate::{StateKind, TransitionRules};
+
+        // Permissive by default
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.transition_to(AgentState::Error("boom".into())).unwrap();
+        machine.transition_to(AgentState::Processing).unwrap();
+
+        let rules = TransitionRules::new()
+            .allow(StateKind::Ready, &[StateKind::Custom, StateKind::Error])
+            .allow(StateKind::Custom, &[StateKind::Ready])
+            .allow(StateKind::Error, &[StateKind::Ready]);
+        let mut machine = ChatAgentStateMachine::new(MockAgent).with_transition_rules(rules);
+        let mut states = machine.subscribe_to_state_changes();
+
+        machine.transition_to(AgentState::Error("boom".into())).unwrap();
+        assert_eq!(
+            machine.transition_to(AgentState::Processing),
+            Err(StateError::InvalidTransition {
+                from: AgentState::Error("boom".into()),
+                to: AgentState::Processing,
+            })
+        );
+        // A refused transition changes nothing and notifies no one
+        assert_eq!(machine.current_state(), &AgentState::Error("boom".into()));
+        assert_eq!(states.try_recv().unwrap(), AgentState::Error("boom".into()));
+        assert!(states.try_recv().is_err());
+
+        machine.transition_to(AgentState::Ready).unwrap();
+        machine.transition_to(AgentState::Custom("Plotting".into())).unwrap();
+
+        // The machine's own transitions aren't restricted
+        machine.transition_to(AgentState::Ready).unwrap();
+        assert_eq!(machine.prompt("Hello").await.unwrap(), "Echo: Hello");
+    }
+
+    #[tokio::test]
+    async fn test_prompt_returns_the_response() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        assert_eq!(machine.prompt("Hello").await.unwrap(), "Echo: Hello");
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+        assert_eq!(machine.history().len(), 2);
+
+        // Messages still waiting are answered in the same run
+        machine.enqueue(&["First"], false, Priority::Normal);
+        assert_eq!(machine.prompt("Second").await.unwrap(), "Echo: First\n\nEcho: Second");
+
+        let mut machine = ChatAgentStateMachine::new(FailingAgent(Arc::new(Mutex::new(0))));
+        assert!(matches!(machine.prompt("Hello").await, Err(MachineError::Agent(_))));
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_messages_are_skipped() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.set_message_timeout(Duration::from_millis(200));
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+        let mut states = machine.subscribe_to_state_changes();
+
+        machine.process_messages(&["A", "hang", "B"]).await.unwrap();
+        assert_eq!(*responses.lock().unwrap(), ["Echo: A", "Echo: B"]);
+        let mut seen = Vec::new();
+        while let Ok(state) = states.try_recv() {
+            seen.push(state);
+        }
+        assert!(seen.contains(&AgentState::TimedOut));
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+
+        assert!(matches!(machine.prompt("hang").await, Err(MachineError::Timeout(_))));
+    }
+
+    /// Fails on messages starting with "fail", counting every call it gets
+    struct PickyAgent(Arc<Mutex<u32>>);
+
+    impl Chat for PickyAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            *self.0.lock().unwrap() += 1;
+            if prompt.starts_with("fail") {
+                Err(rig::completion::CompletionError::ProviderError("refused".into()).into())
+            } else {
+                Ok(format!("Echo: {}", prompt))
+            }
+        }
+    }
+
+    async fn run_with_policy(policy: ErrorPolicy) -> (ChatAgentStateMachine<PickyAgent>, Vec<String>, u32) {
+        let calls = Arc::new(Mutex::new(0));
+        let mut machine = ChatAgentStateMachine::new(PickyAgent(Arc::clone(&calls)));
+        machine.set_error_policy(policy);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+
+        machine.process_messages(&["A", "fail 1", "B", "fail 2", "C"]).await.unwrap();
+        let responses = responses.lock().unwrap().clone();
+        let calls = *calls.lock().unwrap();
+        (machine, responses, calls)
+    }
+
+    #[tokio::test]
+    async fn test_error_policies() {
+        // The default stops at the first failure and keeps the rest queued
+        let (machine, responses, calls) = run_with_policy(ErrorPolicy::StopQueue).await;
+        assert_eq!(responses, ["Echo: A"]);
+        assert_eq!(calls, 2);
+        assert_eq!(machine.pending().map(|(_, text)| text).collect::<Vec<_>>(), ["B", "fail 2", "C"]);
+
+        // Switching policy lets the next run skip the failure and drain the queue
+        let (mut machine, _, _) = run_with_policy(ErrorPolicy::StopQueue).await;
+        machine.set_error_policy(ErrorPolicy::SkipAndContinue);
+        let mut states = machine.subscribe_to_state_changes();
+        machine.process_message("D").await.unwrap();
+        let mut seen = Vec::new();
+        while let Ok(state) = states.try_recv() {
+            seen.push(state);
+        }
+        assert!(seen.iter().any(|state| matches!(state, AgentState::Error(_))));
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+        assert_eq!(machine.pending().len(), 0);
+
+        // Skipping drops each failed message after passing through Error
+        let (_, responses, calls) = run_with_policy(ErrorPolicy::SkipAndContinue).await;
+        assert_eq!(responses, ["Echo: A", "Echo: B", "Echo: C"]);
+        assert_eq!(calls, 5);
+
+        // Retrying calls the agent again for each failure before skipping it
+        let (mut machine, responses, calls) = run_with_policy(ErrorPolicy::RetryThenSkip { max_retries: 2 }).await;
+        assert_eq!(responses, ["Echo: A", "Echo: B", "Echo: C"]);
+        assert_eq!(calls, 3 + 2 * 3);
+
+        // A skipped message still reports its own failure to the caller
+        assert!(matches!(machine.prompt("fail 3").await, Err(MachineError::Agent(_))));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_answers_and_errors() {
+        let mut machine = ChatAgentStateMachine::new(PickyAgent(Arc::new(Mutex::new(0))));
+        assert_eq!(machine.metrics().average_latency(), None);
+
+        for message in ["A", "B", "C"] {
+            machine.process_single_message(message).await.unwrap();
+        }
+        assert_eq!(machine.metrics().messages_processed, 3);
+        assert_eq!(machine.metrics().errors, 0);
+        assert!(machine.metrics().last_latency.is_some());
+        assert!(machine.metrics().average_latency().is_some());
+
+        // Queued messages count too, and a failure only adds an error
+        machine.process_messages(&["fail", "D"]).await.unwrap();
+        assert_eq!(machine.metrics().messages_processed, 3);
+        assert_eq!(machine.metrics().errors, 1);
+    }
+
+    #[tokio::test]
+    async fn test_machine_errors() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.set_input_guard(|message| {
+            if message.len() > 20 {
+                Err("too long".to_string())
+            } else {
+                Ok(())
+            }
+        });
+        assert!(matches!(
+            machine.process_message("This message is far too long").await,
+            Err(MachineError::GuardRejected(reason)) if reason == "too long"
+        ));
+
+        machine.set_message_timeout(Duration::from_millis(10));
+        assert!(matches!(
+            machine.process_single_message("Hi").await,
+            Err(MachineError::Timeout(_))
+        ));
+
+        // While busy, messages wait in the queue until it is full
+        machine.set_max_queue_len(2);
+        machine.transition_to(AgentState::Processing).unwrap();
+        machine.process_messages(&["A", "B"]).await.unwrap();
+        assert!(matches!(machine.process_message("C").await, Err(MachineError::QueueFull(2))));
+
+        // Shutting down drops the waiting messages and refuses new ones
+        let mut events = machine.subscribe_to_queue_events();
+        machine.shutdown();
+        assert!(machine.queue.is_empty());
+        assert!(matches!(events.try_recv().unwrap(), QueueEvent::Failed { id: 0, .. }));
+        assert!(matches!(machine.process_message("D").await, Err(MachineError::Shutdown)));
+    }
+
+    #[tokio::test]
+    async fn test_manage_pending_messages() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.enqueue(&["A", "B", "C"], false, Priority::Normal);
+        let mut events = machine.subscribe_to_queue_events();
+
+        assert!(machine.move_to_front(2));
+        assert_eq!(machine.remove_pending(1).as_deref(), Some("B"));
+        assert_eq!(machine.pending().collect::<Vec<_>>(), [(2, "C"), (0, "A")]);
+        assert_eq!(events.try_recv().unwrap(), QueueEvent::Removed { id: 1 });
+
+        // Ids that already left the queue are ignored
+        assert!(!machine.move_to_front(1));
+        assert_eq!(machine.remove_pending(7), None);
+
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+        machine.process_queue().await;
+        assert_eq!(*responses.lock().unwrap(), ["Echo: C", "Echo: A"]);
+        assert_eq!(machine.pending().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pending_count_and_clear_queue() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.process_single_message("Hi").await.unwrap();
+        assert!(machine.is_idle());
+
+        // While busy, messages pile up instead of being processed
+        machine.transition_to(AgentState::Processing).unwrap();
+        machine.process_messages(&["A", "B", "C"]).await.unwrap();
+        assert_eq!(machine.pending_count(), 3);
+        assert!(!machine.is_idle());
+
+        let mut events = machine.subscribe_to_queue_events();
+        machine.clear_queue();
+        assert_eq!(machine.pending_count(), 0);
+        assert_eq!(events.try_recv().unwrap(), QueueEvent::Removed { id: 0 });
+        assert_eq!(machine.history().len(), 2);
+
+        // Nothing queued, but the machine is still busy
+        assert!(!machine.is_idle());
+        machine.transition_to(AgentState::Ready).unwrap();
+        assert!(machine.is_idle());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_drops_the_queue() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+
+        // Cancel while the agent is still answering the first message
+        let cancel = machine.cancellation_handle();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(20)).await;
+            cancel.cancel();
+        });
+        machine.process_messages(&["A", "B", "C"]).await.unwrap();
+
+        assert!(responses.lock().unwrap().is_empty());
+        assert_eq!(machine.pending_count(), 0);
+        assert!(machine.history().iter().all(|entry| entry.role() != Role::Assistant));
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+
+        // The cancel doesn't carry over to the next message
+        assert_eq!(machine.prompt("D").await.unwrap(), "Echo: D");
+
+        let cancel = machine.cancellation_handle();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(20)).await;
+            cancel.cancel();
+        });
+        assert!(matches!(machine.process_single_message("E").await, Err(MachineError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_state_hooks_see_every_transition() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let entered = Arc::new(Mutex::new(Vec::new()));
+        let left = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&entered);
+        machine.on_enter(move |state| sink.lock().unwrap().push(state.clone()));
+        let sink = Arc::clone(&left);
+        machine.on_exit(move |state| sink.lock().unwrap().push(state.clone()));
+
+        machine.process_messages(&["A", "B"]).await.unwrap();
+        assert_eq!(
+            *entered.lock().unwrap(),
+            [
+                AgentState::ProcessingQueue,
+                AgentState::Processing,
+                AgentState::Processing,
+                AgentState::Ready
+            ]
+        );
+        assert_eq!(
+            *left.lock().unwrap(),
+            [
+                AgentState::Ready,
+                AgentState::ProcessingQueue,
+                AgentState::Processing,
+                AgentState::Processing
+            ]
+        );
+
+        // Requested transitions run the hooks too
+        machine.transition_to(AgentState::Custom("Reviewing".into())).unwrap();
+        assert_eq!(entered.lock().unwrap().last(), Some(&AgentState::Custom("Reviewing".into())));
+        assert_eq!(left.lock().unwrap().last(), Some(&AgentState::Ready));
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_messages_jump_the_queue() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+
+        // While a message is in progress, the rest queue up by priority
+        machine.transition_to(AgentState::Processing).unwrap();
+        machine.process_messages(&["A", "B"]).await.unwrap();
+        machine.process_message_with_priority("stop", Priority::High).await.unwrap();
+        machine.process_message("C").await.unwrap();
+        machine.process_message_with_priority("urgent", Priority::High).await.unwrap();
+
+        machine.transition_to(AgentState::Ready).unwrap();
+        machine.process_queue().await;
+        assert_eq!(
+            *responses.lock().unwrap(),
+            ["Echo: stop", "Echo: urgent", "Echo: A", "Echo: B", "Echo: C"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_keep_message_order() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| sink.lock().unwrap().push(response));
+
+        // Pausing mid-queue lets the message in progress finish
+        let pause = machine.pause_handle();
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(20)).await;
+            pause.pause();
+        });
+        machine.process_messages(&["A", "B"]).await.unwrap();
+        assert_eq!(*responses.lock().unwrap(), ["Echo: A"]);
+        assert_eq!(machine.current_state(), &AgentState::Paused);
+
+        // While paused, messages only queue up
+        machine.process_message("C").await.unwrap();
+        assert!(matches!(machine.prompt("D").await, Err(MachineError::Paused)));
+        assert_eq!(machine.pending().map(|(_, text)| text).collect::<Vec<_>>(), ["B", "C"]);
+
+        machine.resume().await;
+        assert_eq!(*responses.lock().unwrap(), ["Echo: A", "Echo: B", "Echo: C"]);
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+        assert!(!machine.is_paused());
+
+        // An idle machine pauses at once
+        machine.pause();
+        assert_eq!(machine.current_state(), &AgentState::Paused);
+        machine.process_message("E").await.unwrap();
+        assert_eq!(machine.pending_count(), 1);
+        machine.resume().await;
+        assert_eq!(responses.lock().unwrap().last().map(String::as_str), Some("Echo: E"));
+    }
+
+    #[tokio::test]
+    async fn test_queue_events_follow_each_message() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let mut events = machine.subscribe_to_queue_events();
+        machine.process_messages(&["A", "B"]).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        let kinds: Vec<(&str, MessageId)> = received
+            .iter()
+            .map(|event| {
+                let kind = match event {
+                    QueueEvent::Enqueued { .. } => "enqueued",
+                    QueueEvent::Started { .. } => "started",
+                    QueueEvent::Completed { .. } => "completed",
+                    QueueEvent::Failed { .. } => "failed",
+                    QueueEvent::Removed { .. } => "removed",
+                };
+                (kind, event.id())
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("enqueued", 0),
+                ("enqueued", 1),
+                ("started", 0),
+                ("completed", 0),
+                ("started", 1),
+                ("completed", 1),
+            ]
+        );
+        // MockAgent takes 50ms per message
+        assert!(matches!(received[3], QueueEvent::Completed { duration, .. } if duration >= Duration::from_millis(50)));
+
+        // A failure carries the error
+        let mut machine = ChatAgentStateMachine::new(FailingAgent(Arc::new(Mutex::new(0))));
+        let mut events = machine.subscribe_to_queue_events();
+        machine.process_message("A").await.unwrap();
+        assert_eq!(events.try_recv().unwrap(), QueueEvent::Enqueued { id: 0 });
+        assert_eq!(events.try_recv().unwrap(), QueueEvent::Started { id: 0 });
+        assert!(matches!(events.try_recv().unwrap(), QueueEvent::Failed { id: 0, error } if error.contains("outage")));
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Move {
+        row: usize,
+        col: usize,
+    }
+
+    /// Answers with a typed move instead of text
+    struct MoveAgent;
+
+    impl Respond for MoveAgent {
+        type Response = Move;
+        type Error = PromptError;
+
+        async fn respond(&self, _prompt: &str, history: Vec<Message>) -> Result<Move, PromptError> {
+            Ok(Move { row: history.len(), col: 2 })
+        }
+
+        fn to_text(response: &Move) -> String {
+            format!("{},{}", response.row, response.col)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_responses_are_returned_as_is() {
+        let mut machine = ChatAgentStateMachine::new(MoveAgent);
+        let responses = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&responses);
+        machine.set_response_callback(move |response| {
+            sink.lock().unwrap().push(response);
+        });
+
+        let first = machine.process_single_message("Your move").await.unwrap();
+        assert_eq!(first, Move { row: 0, col: 2 });
+        machine.process_message("Again").await.unwrap();
+
+        // History and the callback get the text form
+        assert_eq!(machine.history()[1].content(), "0,2");
+        assert_eq!(responses.lock().unwrap().as_slice(), ["2,2"]);
+    }
+
+    /// Streams its answer one word at a time
+    struct WordStreamer;
+
+    impl Respond for WordStreamer {
+        type Response = String;
+        type Error = PromptError;
+
+        async fn respond(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            Ok(format!("You said {}", prompt))
+        }
+
+        async fn respond_streaming(
+            &self,
+            prompt: &str,
+            history: Vec<Message>,
+            on_chunk: &(dyn Fn(&str) + Send + Sync),
+        ) -> Result<String, PromptError> {
+            let response = self.respond(prompt, history).await?;
+            for word in response.split_inclusive(' ') {
+                on_chunk(word);
+            }
+            Ok(response)
+        }
+
+        fn to_text(response: &String) -> String {
+            response.clone()
+        }
+    }
+
+    /// Looks something up with a "search" tool before answering
+    struct Researcher;
+
+    impl Respond for Researcher {
+        type Response = String;
+        type Error = PromptError;
+
+        async fn respond(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            Ok(format!("Found: {}", prompt))
+        }
+
+        async fn respond_with_tools(
+            &self,
+            prompt: &str,
+            history: Vec<Message>,
+            on_tool: &(dyn Fn(ToolEvent) + Send + Sync),
+        ) -> Result<String, PromptError> {
+            on_tool(ToolEvent::Started { name: "search".into() });
+            sleep(Duration::from_millis(10)).await;
+            on_tool(ToolEvent::Finished { name: "search".into() });
+            self.respond(prompt, history).await
+        }
+
+        fn to_text(response: &String) -> String {
+            response.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_show_as_calling_tool() {
+        let mut machine = ChatAgentStateMachine::new(Researcher);
+        let mut states = machine.subscribe_to_state_changes();
+        let left = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&left);
+        machine.on_exit(move |state| sink.lock().unwrap().push(state.clone()));
+
+        machine.process_message("rust").await.unwrap();
+        let mut seen = Vec::new();
+        while let Ok(state) = states.try_recv() {
+            seen.push(state);
+        }
+        let calling = AgentState::CallingTool { name: "search".into() };
+        assert_eq!(
+            seen,
+            [
+                AgentState::ProcessingQueue,
+                AgentState::Processing,
+                calling.clone(),
+                AgentState::Processing,
+                AgentState::Ready
+            ]
+        );
+        assert!(left.lock().unwrap().contains(&calling));
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+
+        // Agents without tool events stay in Processing
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let mut states = machine.subscribe_to_state_changes();
+        machine.process_message("Hi").await.unwrap();
+        while let Ok(state) = states.try_recv() {
+            assert!(!matches!(state, AgentState::CallingTool { .. }));
+        }
+    }
+
+    /// Streams a few words, then stalls as if the provider stopped sending
+    struct StallingStreamer;
+
+    impl Respond for StallingStreamer {
+        type Response = String;
+        type Error = PromptError;
+
+        async fn respond(&self, _prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            sleep(Duration::from_secs(5)).await;
+            Ok("The answer is 42".to_string())
+        }
+
+        async fn respond_streaming(
+            &self,
+            _prompt: &str,
+            _history: Vec<Message>,
+            on_chunk: &(dyn Fn(&str) + Send + Sync),
+        ) -> Result<String, PromptError> {
+            for word in ["The ", "answer ", "is "] {
+                on_chunk(word);
+            }
+            sleep(Duration::from_secs(5)).await;
+            on_chunk("42");
+            Ok("The answer is 42".to_string())
+        }
+
+        fn to_text(response: &String) -> String {
+            response.clone()
+        }
+    }
+
+    fn collect_chunks<A: Respond>(machine: &mut ChatAgentStateMachine<A>) -> Arc<Mutex<Vec<String>>> {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&chunks);
+        machine.set_stream_callback(move |chunk| sink.lock().unwrap().push(chunk.to_string()));
+        chunks
+    }
+
+    #[tokio::test]
+    async fn test_streamed_chunks_reach_the_callback() {
+        let mut machine = ChatAgentStateMachine::new(WordStreamer);
+        let chunks = collect_chunks(&mut machine);
+
+        let response = machine.process_message_streaming("hello there").await.unwrap();
+        assert_eq!(*chunks.lock().unwrap(), ["You ", "said ", "hello ", "there"]);
+        assert_eq!(response, "You said hello there");
+        assert_eq!(machine.history()[1].content(), "You said hello there");
+
+        // Other paths don't stream
+        machine.process_single_message("again").await.unwrap();
+        assert_eq!(chunks.lock().unwrap().len(), 4);
+
+        // Agents that can't stream send the whole answer as one chunk
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        let chunks = collect_chunks(&mut machine);
+        machine.process_message_streaming("Hi").await.unwrap();
+        assert_eq!(*chunks.lock().unwrap(), ["Echo: Hi"]);
+    }
+
+    /// Summarizes by counting the messages it was sent
+    struct SummaryAgent;
+
+    impl Chat for SummaryAgent {
+        async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            if prompt == SUMMARY_PROMPT {
+                return Ok(format!("{} messages about the weather", history.len()));
+            }
+            Ok(format!("Echo: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clear_history_keeping_summary() {
+        let mut machine = ChatAgentStateMachine::new(SummaryAgent);
+        machine.process_message("Is it sunny?").await.unwrap();
+        machine.process_message("Will it rain?").await.unwrap();
+
+        machine.clear_history_keeping_summary().await.unwrap();
+
+        assert_eq!(
+            machine.history(),
+            [HistoryEntry::System {
+                content: "Summary of the earlier conversation: 4 messages about the weather".into()
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt_survives_clear_history() {
+        let mut machine = ChatAgentStateMachine::with_system_prompt(RecordingAgent, "Be brief.");
+        assert_eq!(machine.system_prompt(), Some("Be brief."));
+        machine.process_single_message("Hi").await.unwrap();
+        assert_eq!(machine.history().len(), 2);
+
+        machine.clear_history();
+        assert!(machine.history().is_empty());
+        assert_eq!(machine.process_single_message("Again").await.unwrap(), "Be brief.|Again");
+    }
+
+    #[tokio::test]
+    async fn test_undo_last_exchange() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        assert!(!machine.undo_last_exchange());
+
+        machine.process_message("First").await.unwrap();
+        machine.process_message("Second").await.unwrap();
+        assert!(machine.undo_last_exchange());
+        assert_eq!(
+            machine.history(),
+            [HistoryEntry::user("First"), HistoryEntry::assistant("Echo: First")]
+        );
+
+        // Not while a message is in flight
+        machine.transition_to(AgentState::Processing).unwrap();
+        assert!(!machine.undo_last_exchange());
+        assert_eq!(machine.history().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_skips_ahead() {
+        let mut machine = ChatAgentStateMachine::new_with_capacity(MockAgent, 2);
+        let mut states = machine.subscribe_to_state_changes();
+        for phase in ["One", "Two", "Three", "Four", "Five"] {
+            machine.transition_to(AgentState::Custom(phase.into())).unwrap();
+        }
+
+        // Only the last two states are still buffered
+        assert_eq!(states.recv().await, Err(broadcast::error::RecvError::Lagged(3)));
+        assert_eq!(states.recv().await.unwrap(), AgentState::Custom("Four".into()));
+        assert_eq!(states.recv().await.unwrap(), AgentState::Custom("Five".into()));
+        assert_eq!(*machine.current_state_watch().borrow(), AgentState::Custom("Five".into()));
+    }
+
+    /// Summarizes by counting the messages it was sent, or fails if told to
+    struct CountingSummarizer(bool);
+
+    impl Chat for CountingSummarizer {
+        async fn chat(&self, _prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            if self.0 {
+                return Err(rig::completion::CompletionError::ProviderError("down".into()).into());
+            }
+            Ok(format!("{} messages", history.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_summarize_collapses_old_turns() {
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.enable_auto_summarize(CountingSummarizer(false), 4);
+        // One token per entry keeps the arithmetic simple
+        machine.set_token_estimator(|_| 1);
+
+        machine.process_messages(&["A", "B", "C", "D"]).await.unwrap();
+        // Before "D", six entries were over budget: all but the latest turn were summarized
+        assert_eq!(
+            machine.history(),
+            [
+                HistoryEntry::System {
+                    content: format!("{}4 messages", SUMMARY_NOTE_PREFIX)
+                },
+                HistoryEntry::user("C"),
+                HistoryEntry::assistant("Echo: C"),
+                HistoryEntry::user("D"),
+                HistoryEntry::assistant("Echo: D"),
+            ]
+        );
+
+        // Without a summary the old turns are simply dropped
+        let mut machine = ChatAgentStateMachine::new(MockAgent);
+        machine.enable_auto_summarize(CountingSummarizer(true), 4);
+        machine.set_token_estimator(|_| 1);
+        machine.process_messages(&["A", "B", "C", "D"]).await.unwrap();
+        assert_eq!(machine.history().len(), 4);
+        assert_eq!(machine.history()[0], HistoryEntry::user("C"));
+    }
+
     #[tokio::test]
     async fn test_clear_history() {
         let mut machine = ChatAgentStateMachine::new(MockAgent);
@@ -0,0 +1,119 @@
+// src/message.rs
+
+use rig::completion::Message;
+use std::fmt;
+
+/// The role of a chat message author
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    /// The output of a tool, see [`HistoryEntry`](crate::HistoryEntry)
+    Tool,
+}
+
+impl Role {
+    /// The role string the provider APIs expect
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+
+    /// Parse a role string, returning `None` for anything unrecognized
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "system" => Some(Role::System),
+            "user" => Some(Role::User),
+            "assistant" => Some(Role::Assistant),
+            "tool" => Some(Role::Tool),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Typed constructors for rig's [`Message`], so roles can't be misspelled.
+///
+/// ```rust
+/// use agent_state_machine::{MessageExt, Role};
+/// use rig::completion::Message;
+///
+/// let message = Message::user("Hello!");
+/// assert_eq!(message.role(), Some(Role::User));
+/// ```
+pub trait MessageExt {
+    /// Build a message with the given role
+    fn new(role: Role, content: impl Into<String>) -> Self;
+
+    /// A message from the user
+    fn user(content: impl Into<String>) -> Self;
+
+    /// A message from the model
+    fn assistant(content: impl Into<String>) -> Self;
+
+    /// A system instruction
+    fn system(content: impl Into<String>) -> Self;
+
+    /// The message's role, or `None` if it isn't one of the known roles
+    fn role(&self) -> Option<Role>;
+}
+
+impl MessageExt for Message {
+    fn new(role: Role, content: impl Into<String>) -> Self {
+        Message {
+            role: role.as_str().to_string(),
+            content: content.into(),
+        }
+    }
+
+    fn user(content: impl Into<String>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    fn assistant(content: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    fn system(content: impl Into<String>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    fn role(&self) -> Option<Role> {
+        Role::parse(&self.role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_helpers_set_roles() {
+        let history = [
+            Message::system("Be brief."),
+            Message::user("Hi"),
+            Message::assistant("Hello!"),
+        ];
+
+        let roles: Vec<&str> = history.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "user", "assistant"]);
+        assert_eq!(history[1].role(), Some(Role::User));
+        assert_eq!(history[2].content, "Hello!");
+
+        let unknown = Message {
+            role: "assitant".into(),
+            content: String::new(),
+        };
+        assert_eq!(unknown.role(), None);
+    }
+}
@@ -0,0 +1,40 @@
+// src/metrics.rs
+
+use std::time::Duration;
+
+/// Counters a [`ChatAgentStateMachine`](crate::ChatAgentStateMachine) keeps about
+/// the messages it processed, as returned by
+/// [`metrics`](crate::ChatAgentStateMachine::metrics).
+///
+/// Token usage isn't included, since rig's `Chat` interface doesn't report it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    /// Messages the agent answered
+    pub messages_processed: u64,
+    /// Messages that failed, after any retries
+    pub errors: u64,
+    /// How long the agent took to answer the last answered message
+    pub last_latency: Option<Duration>,
+    /// Sum of the latencies of all answered messages
+    total_latency: Duration,
+}
+
+impl Metrics {
+    /// Mean time the agent took to answer, or `None` before the first answer
+    pub fn average_latency(&self) -> Option<Duration> {
+        u32::try_from(self.messages_processed)
+            .ok()
+            .filter(|&count| count > 0)
+            .map(|count| self.total_latency / count)
+    }
+
+    pub(crate) fn record_success(&mut self, latency: Duration) {
+        self.messages_processed += 1;
+        self.last_latency = Some(latency);
+        self.total_latency += latency;
+    }
+
+    pub(crate) fn record_error(&mut self) {
+        self.errors += 1;
+    }
+}
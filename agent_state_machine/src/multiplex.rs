@@ -0,0 +1,115 @@
+// src/multiplex.rs
+
+use crate::state::{AgentState, CustomState};
+use futures::stream::{self, BoxStream, SelectAll, Stream, StreamExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+/// Merges the state changes of several state machines into one stream, tagging each
+/// state with the name of the machine it came from.
+///
+/// ```rust,no_run
+/// # use agent_state_machine::{ChatAgentStateMachine, StateMultiplexer};
+/// # async fn example<A: rig::completion::Chat>(writer: ChatAgentStateMachine<A>, critic: ChatAgentStateMachine<A>) {
+/// let mut states = StateMultiplexer::new();
+/// states.add("writer", writer.subscribe_to_state_changes());
+/// states.add("critic", critic.subscribe_to_state_changes());
+///
+/// tokio::spawn(async move {
+///     while let Some((name, state)) = states.recv().await {
+///         println!("[{}] {}", name, state);
+///     }
+/// });
+/// # }
+/// ```
+///
+/// The stream ends once every machine has been dropped. A receiver that lags behind
+/// skips the states it missed and carries on.
+pub struct StateMultiplexer<S: CustomState = String> {
+    streams: SelectAll<BoxStream<'static, (String, AgentState<S>)>>,
+}
+
+impl<S: CustomState> Default for StateMultiplexer<S> {
+    fn default() -> Self {
+        Self {
+            streams: SelectAll::new(),
+        }
+    }
+}
+
+impl<S: CustomState> StateMultiplexer<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a machine's state receiver under the given name
+    pub fn add(&mut self, name: impl Into<String>, receiver: broadcast::Receiver<AgentState<S>>) {
+        let stream = stream::unfold((name.into(), receiver), |(name, mut receiver)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(state) => return Some(((name.clone(), state), (name, receiver))),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("State stream for {} lagged, skipped {} states", name, skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        self.streams.push(stream.boxed());
+    }
+
+    /// Wait for the next state change from any machine, or `None` once all are gone
+    pub async fn recv(&mut self) -> Option<(String, AgentState<S>)> {
+        self.streams.next().await
+    }
+}
+
+impl<S: CustomState> Stream for StateMultiplexer<S> {
+    type Item = (String, AgentState<S>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.streams).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatAgentStateMachine;
+    use rig::completion::{Chat, Message, PromptError};
+
+    struct Silent;
+
+    impl Chat for Silent {
+        async fn chat(&self, _prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            Ok(String::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_states_are_tagged_by_machine() {
+        let mut writer = ChatAgentStateMachine::new(Silent);
+        let mut critic = ChatAgentStateMachine::new(Silent);
+
+        let mut states = StateMultiplexer::new();
+        states.add("writer", writer.subscribe_to_state_changes());
+        states.add("critic", critic.subscribe_to_state_changes());
+
+        writer.transition_to(AgentState::Processing).unwrap();
+        critic.transition_to(AgentState::Custom("Reviewing".into())).unwrap();
+        drop(writer);
+        drop(critic);
+
+        let mut received: Vec<(String, AgentState)> = states.collect().await;
+        received.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            received,
+            vec![
+                ("critic".to_string(), AgentState::Custom("Reviewing".into())),
+                ("writer".to_string(), AgentState::Processing),
+            ]
+        );
+    }
+}
@@ -0,0 +1,221 @@
+// src/orchestrator.rs
+
+use crate::machine::ChatAgentStateMachine;
+use crate::multiplex::StateMultiplexer;
+use crate::rate_limit::RateLimited;
+use crate::respond::{Respond, ToolEvent};
+use crate::state::AgentState;
+use rig::completion::Message;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex, Semaphore};
+
+/// An agent that waits for a permit from a shared semaphore before each request.
+///
+/// [`Orchestrator`] wraps every agent it is given in one of these, which is how it
+/// limits the requests in flight across all of its machines.
+pub struct Throttled<A> {
+    inner: A,
+    permits: Arc<Semaphore>,
+}
+
+impl<A: Respond> Respond for Throttled<A> {
+    type Response = A::Response;
+    type Error = A::Error;
+
+    async fn respond(&self, prompt: &str, history: Vec<Message>) -> Result<A::Response, A::Error> {
+        // The semaphore is never closed, so acquiring only waits
+        let _permit = self.permits.acquire().await.expect("orchestrator semaphore closed");
+        self.inner.respond(prompt, history).await
+    }
+
+    async fn respond_streaming(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+        on_chunk: &(dyn Fn(&str) + Send + Sync),
+    ) -> Result<A::Response, A::Error> {
+        let _permit = self.permits.acquire().await.expect("orchestrator semaphore closed");
+        self.inner.respond_streaming(prompt, history, on_chunk).await
+    }
+
+    async fn respond_with_tools(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+        on_tool: &(dyn Fn(ToolEvent) + Send + Sync),
+    ) -> Result<A::Response, A::Error> {
+        let _permit = self.permits.acquire().await.expect("orchestrator semaphore closed");
+        self.inner.respond_with_tools(prompt, history, on_tool).await
+    }
+
+    fn to_text(response: &A::Response) -> String {
+        A::to_text(response)
+    }
+
+    fn rate_limited(error: &A::Error) -> Option<RateLimited> {
+        A::rate_limited(error)
+    }
+}
+
+/// A named machine owned by an [`Orchestrator`], shared so several tasks can use it
+pub type OrchestratedMachine<A> = Arc<Mutex<ChatAgentStateMachine<Throttled<A>>>>;
+
+/// Owns several named state machines and caps how many requests they have in flight
+/// at once, across all of them.
+///
+/// Multi-agent apps such as the storytelling example can otherwise fire one request
+/// per agent at the same moment and overload the provider. Here a request that would
+/// go over the limit waits for one of the others to finish, while the machine stays
+/// in its `Processing` state.
+///
+/// ```rust,no_run
+/// use agent_state_machine::{openai_client_from_env, Orchestrator};
+///
+/// # async fn example() {
+/// let client = openai_client_from_env();
+/// let mut orchestrator = Orchestrator::new(2);
+/// orchestrator.add("narrator", client.agent("gpt-4o").preamble("You narrate the story.").build());
+/// orchestrator.add("character", client.agent("gpt-4o").preamble("You play the hero.").build());
+///
+/// let mut events = orchestrator.events();
+/// tokio::spawn(async move {
+///     while let Some((name, state)) = events.recv().await {
+///         println!("[{}] {}", name, state);
+///     }
+/// });
+///
+/// let narrator = orchestrator.machine("narrator").unwrap();
+/// narrator.lock().await.process_message("Begin the story.").await.unwrap();
+/// # }
+/// ```
+pub struct Orchestrator<A: Respond> {
+    permits: Arc<Semaphore>,
+    max_in_flight: usize,
+    machines: HashMap<String, OrchestratedMachine<A>>,
+    /// One receiver per machine, kept only to hand out fresh subscriptions from
+    states: Vec<(String, broadcast::Receiver<AgentState>)>,
+}
+
+impl<A: Respond> Orchestrator<A> {
+    /// Create an orchestrator that lets at most `max_in_flight` requests run at once.
+    ///
+    /// # Panics
+    ///
+    /// If `max_in_flight` is 0, since no request could ever run.
+    pub fn new(max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be at least 1");
+        Self {
+            permits: Arc::new(Semaphore::new(max_in_flight)),
+            max_in_flight,
+            machines: HashMap::new(),
+            states: Vec::new(),
+        }
+    }
+
+    /// Create a machine for `agent` under `name` and return it, e.g. to set a
+    /// callback. A machine already added under the same name is replaced.
+    pub fn add(&mut self, name: &str, agent: A) -> OrchestratedMachine<A> {
+        let machine = ChatAgentStateMachine::new(Throttled {
+            inner: agent,
+            permits: Arc::clone(&self.permits),
+        });
+        self.states.retain(|(existing, _)| existing != name);
+        self.states.push((name.to_string(), machine.subscribe_to_state_changes()));
+
+        let machine = Arc::new(Mutex::new(machine));
+        self.machines.insert(name.to_string(), Arc::clone(&machine));
+        machine
+    }
+
+    /// The machine added under `name`, if any
+    pub fn machine(&self, name: &str) -> Option<OrchestratedMachine<A>> {
+        self.machines.get(name).cloned()
+    }
+
+    /// Names of all machines, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.machines.keys().map(String::as_str)
+    }
+
+    /// Follow the state changes of every machine added so far in one stream, each
+    /// tagged with its machine's name. Only changes made after this call are received.
+    pub fn events(&self) -> StateMultiplexer {
+        let mut events = StateMultiplexer::new();
+        for (name, receiver) in &self.states {
+            events.add(name.clone(), receiver.resubscribe());
+        }
+        events
+    }
+
+    /// How many requests are running right now
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight - self.permits.available_permits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::{Chat, PromptError};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time::{sleep, Duration};
+
+    /// Takes a while to answer and records the most requests it ever saw at once
+    struct DelayedAgent {
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl Chat for DelayedAgent {
+        async fn chat(&self, prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+            let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            sleep(Duration::from_millis(30)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(format!("Done: {}", prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_requests_never_exceed_the_limit() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let mut orchestrator = Orchestrator::new(2);
+        let names = ["a", "b", "c", "d", "e"];
+        for name in names {
+            orchestrator.add(
+                name,
+                DelayedAgent {
+                    running: Arc::clone(&running),
+                    peak: Arc::clone(&peak),
+                },
+            );
+        }
+        let mut events = orchestrator.events();
+
+        let tasks: Vec<_> = names
+            .iter()
+            .map(|name| {
+                let machine = orchestrator.machine(name).unwrap();
+                tokio::spawn(async move {
+                    let mut machine = machine.lock().await;
+                    machine.process_message("Go").await.unwrap();
+                    machine.process_message("Again").await.unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(orchestrator.in_flight(), 0);
+        assert!(orchestrator.machine("f").is_none());
+
+        // Every machine reported its states through the shared stream
+        let (name, state) = events.recv().await.unwrap();
+        assert!(names.contains(&name.as_str()));
+        assert_eq!(state, AgentState::ProcessingQueue);
+    }
+}
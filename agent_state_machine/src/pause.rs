@@ -0,0 +1,31 @@
+// src/pause.rs
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Pauses a [`ChatAgentStateMachine`](crate::ChatAgentStateMachine) from another
+/// task, e.g. to stop a bot mid-queue for maintenance.
+///
+/// Get one from [`pause_handle`](crate::ChatAgentStateMachine::pause_handle). A
+/// machine paused this way finishes the message in progress and leaves the rest
+/// queued until [`resume`](crate::ChatAgentStateMachine::resume) is called.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    /// Pause the machine after the message in progress, if any
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the machine is paused or about to pause
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,93 @@
+// src/rate_limit.rs
+
+use rig::completion::{CompletionError, PromptError};
+use std::time::Duration;
+
+/// How the [`AgentState::RateLimited`](crate::AgentState::RateLimited) state the
+/// machine waits in while rate limited is displayed
+pub const RATE_LIMITED_STATE: &str = "RateLimited";
+
+/// The provider rejected a request because too many were sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    /// How long the provider asked to wait, if it said
+    pub retry_after: Option<Duration>,
+}
+
+/// Recognize a rate-limit error from a rig agent.
+///
+/// rig turns an HTTP 429 into an `HttpError` without the `Retry-After` header, so
+/// those come back without a wait. Providers that answer with an error body, like
+/// OpenAI, usually say how long to wait in the message ("Please try again in 1.5s"),
+/// which is used when present.
+pub fn classify(error: &PromptError) -> Option<RateLimited> {
+    match error {
+        PromptError::CompletionError(CompletionError::HttpError(e)) => {
+            (e.status().map(|status| status.as_u16()) == Some(429)).then_some(RateLimited { retry_after: None })
+        }
+        PromptError::CompletionError(CompletionError::ProviderError(message))
+        | PromptError::CompletionError(CompletionError::ResponseError(message)) => {
+            let lower = message.to_lowercase();
+            (lower.contains("rate limit") || lower.contains("too many requests")).then(|| RateLimited {
+                retry_after: parse_retry_after(&lower),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Find a wait like "try again in 1.5s", "in 20ms" or "in 6m0s" in an error message
+fn parse_retry_after(message: &str) -> Option<Duration> {
+    let start = message.find("try again in ")? + "try again in ".len();
+    let token = message[start..]
+        .split_whitespace()
+        .next()?
+        .trim_end_matches(['.', ',']);
+
+    let mut total = 0.0;
+    let mut rest = token;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let value: f64 = rest[..number_end].parse().ok()?;
+        let unit_end = rest[number_end..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |i| number_end + i);
+        total += value
+            * match &rest[number_end..unit_end] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
+            };
+        rest = &rest[unit_end..];
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_rate_limit_messages() {
+        let error: PromptError = CompletionError::ProviderError(
+            "Rate limit reached for gpt-4 on tokens per min (TPM): Limit 10000. Please try again in 1m30.5s. Visit ...".into(),
+        )
+        .into();
+        assert_eq!(
+            classify(&error),
+            Some(RateLimited {
+                retry_after: Some(Duration::from_secs_f64(90.5))
+            })
+        );
+
+        let error: PromptError = CompletionError::ProviderError("Too Many Requests".into()).into();
+        assert_eq!(classify(&error), Some(RateLimited { retry_after: None }));
+
+        let error: PromptError = CompletionError::ProviderError("Invalid API key".into()).into();
+        assert_eq!(classify(&error), None);
+
+        assert_eq!(parse_retry_after("try again in 20ms."), Some(Duration::from_millis(20)));
+    }
+}
@@ -0,0 +1,132 @@
+// src/respond.rs
+
+use crate::rate_limit::{self, RateLimited};
+use rig::completion::{Chat, Message, PromptError};
+use std::future::Future;
+
+/// A tool an agent runs while answering, as reported to
+/// [`Respond::respond_with_tools`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolEvent {
+    /// The agent started running the tool `name`
+    Started { name: String },
+    /// The tool `name` finished, whether it succeeded or not
+    Finished { name: String },
+}
+
+/// Something the state machine can send a prompt to and get a response back from.
+///
+/// Every rig [`Chat`] agent is a `Respond` with `String` responses, so a plain chat
+/// agent works as-is. Implement it directly for agents that answer with structured
+/// values, such as parsed JSON commands, so the machine hands those values around
+/// without turning them back into strings.
+///
+/// ```rust
+/// use agent_state_machine::{ChatAgentStateMachine, Respond};
+/// use rig::completion::{Message, PromptError};
+///
+/// #[derive(Clone, Debug)]
+/// struct Move { x: usize, y: usize }
+///
+/// struct Player;
+///
+/// impl Respond for Player {
+///     type Response = Move;
+///     type Error = PromptError;
+///
+///     async fn respond(&self, _prompt: &str, _history: Vec<Message>) -> Result<Move, PromptError> {
+///         Ok(Move { x: 1, y: 2 })
+///     }
+///
+///     fn to_text(response: &Move) -> String {
+///         format!("{},{}", response.x, response.y)
+///     }
+/// }
+///
+/// # tokio_test::block_on(async {
+/// let mut machine = ChatAgentStateMachine::new(Player);
+/// let next: Move = machine.process_single_message("Your turn").await.unwrap();
+/// assert_eq!(next.x, 1);
+/// # });
+/// ```
+pub trait Respond: Send + Sync {
+    /// What the agent answers with
+    type Response: Clone + Send + Sync;
+    /// What the agent fails with
+    type Error: std::error::Error + Send;
+
+    /// Answer `prompt` given the conversation so far.
+    ///
+    /// As with [`Chat::chat`], `history` holds the turns *before* `prompt`; the prompt
+    /// itself is not repeated at its end.
+    fn respond(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send;
+
+    /// How a response is recorded in history and shown to text-based hooks such as
+    /// formatters and the response callback
+    fn to_text(response: &Self::Response) -> String;
+
+    /// Answer `prompt` like [`respond`](Self::respond), passing the text to `on_chunk`
+    /// piece by piece as it arrives.
+    ///
+    /// Override this for agents whose provider can stream. By default, as for every
+    /// rig [`Chat`] agent, the whole response is awaited and passed to `on_chunk` once.
+    fn respond_streaming(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+        on_chunk: &(dyn Fn(&str) + Send + Sync),
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        async move {
+            let response = self.respond(prompt, history).await?;
+            on_chunk(&Self::to_text(&response));
+            Ok(response)
+        }
+    }
+
+    /// Answer `prompt` like [`respond`](Self::respond), reporting each tool the agent
+    /// runs to `on_tool`, so the machine can show
+    /// [`AgentState::CallingTool`](crate::AgentState::CallingTool) meanwhile.
+    ///
+    /// Override this for agents that run tools themselves and can tell when. By
+    /// default, as for every rig [`Chat`] agent, no tool events are reported. Streaming
+    /// requests go through [`respond_streaming`](Self::respond_streaming) instead.
+    fn respond_with_tools(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+        _on_tool: &(dyn Fn(ToolEvent) + Send + Sync),
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send {
+        self.respond(prompt, history)
+    }
+
+    /// Whether `error` means the provider is rate limiting requests, so the machine can
+    /// wait and retry instead of failing. By default no error counts as one.
+    fn rate_limited(_error: &Self::Error) -> Option<RateLimited> {
+        None
+    }
+}
+
+impl<A: Chat> Respond for A {
+    type Response = String;
+    type Error = PromptError;
+
+    fn respond(
+        &self,
+        prompt: &str,
+        history: Vec<Message>,
+    ) -> impl Future<Output = Result<String, PromptError>> + Send {
+        self.chat(prompt, history)
+    }
+
+    fn to_text(response: &String) -> String {
+        response.clone()
+    }
+
+    fn rate_limited(error: &PromptError) -> Option<RateLimited> {
+        rate_limit::classify(error)
+    }
+}
@@ -2,34 +2,132 @@
 
 use std::fmt;
 
-/// Represents the possible states of a chat agent
+/// A type of application-specific sub-states, carried by [`AgentState::Custom`].
+///
+/// Implemented for every type with the listed traits, so an enum of your own phases
+/// only needs the usual derives and a `Display` impl:
+///
+/// ```rust
+/// use agent_state_machine::{AgentState, ChatAgentStateMachine};
+/// use rig::completion::{Chat, Message, PromptError};
+/// use std::fmt;
+///
+/// #[derive(Debug, Clone, PartialEq)]
+/// enum NarrativePhase {
+///     GeneratingPlot,
+///     WaitingForChoice,
+/// }
+///
+/// impl fmt::Display for NarrativePhase {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         match self {
+///             NarrativePhase::GeneratingPlot => write!(f, "Generating Plot"),
+///             NarrativePhase::WaitingForChoice => write!(f, "Waiting For Choice"),
+///         }
+///     }
+/// }
+///
+/// # struct Narrator;
+/// # impl Chat for Narrator {
+/// #     async fn chat(&self, _prompt: &str, _history: Vec<Message>) -> Result<String, PromptError> {
+/// #         Ok("Once upon a time".into())
+/// #     }
+/// # }
+/// let mut machine = ChatAgentStateMachine::<_, NarrativePhase>::new_custom(Narrator);
+/// machine.transition_to(AgentState::Custom(NarrativePhase::GeneratingPlot)).unwrap();
+/// assert_eq!(machine.current_state().to_string(), "Generating Plot");
+/// ```
+pub trait CustomState: Clone + PartialEq + fmt::Display + fmt::Debug + Send + Sync + 'static {}
+
+impl<S: Clone + PartialEq + fmt::Display + fmt::Debug + Send + Sync + 'static> CustomState for S {}
+
+/// Represents the possible states of a chat agent.
+///
+/// `S` is the type of the [`Custom`](AgentState::Custom) sub-states, free-form strings
+/// by default. Use an enum of your own, see [`CustomState`], so a misspelled state is
+/// a compile error instead of a new state.
 #[derive(Debug, Clone, PartialEq)]
-pub enum AgentState {
+pub enum AgentState<S = String> {
     /// Ready to receive input
     Ready,
     /// Processing a user message
     Processing,
     /// Processing messages from the queue
     ProcessingQueue,
+    /// Waiting for the human to answer before the queue continues
+    AwaitingInput {
+        /// What the agent asked for
+        prompt: String,
+    },
+    /// Running a tool the agent called, while processing a message
+    CallingTool {
+        /// The tool's name
+        name: String,
+    },
     /// Error state when something goes wrong
     Error(String),
+    /// The agent didn't answer a message in time, so the message was skipped
+    TimedOut,
+    /// Waiting out a provider rate limit before sending the request again. A variant
+    /// of its own, since `Custom` holds the application's state type, which may not
+    /// be a string
+    RateLimited,
+    /// Paused; messages are queued but not processed until the machine is resumed
+    Paused,
     /// Custom state for specific agent actions
-    Custom(String),
+    Custom(S),
 }
 
-impl fmt::Display for AgentState {
+impl<S: fmt::Display> fmt::Display for AgentState<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AgentState::Ready => write!(f, "Ready"),
             AgentState::Processing => write!(f, "Processing"),
             AgentState::ProcessingQueue => write!(f, "Processing Queue"),
+            AgentState::AwaitingInput { prompt } => write!(f, "Awaiting Input: {}", prompt),
+            AgentState::CallingTool { name } => write!(f, "Calling Tool: {}", name),
             AgentState::Error(msg) => write!(f, "Error: {}", msg),
+            AgentState::TimedOut => write!(f, "Timed Out"),
+            AgentState::RateLimited => write!(f, "{}", crate::rate_limit::RATE_LIMITED_STATE),
+            AgentState::Paused => write!(f, "Paused"),
             AgentState::Custom(state) => write!(f, "{}", state),
         }
     }
 }
 
+/// Which variant an [`AgentState`] is, without its data, for rules that apply to
+/// every state of a kind, e.g. to all errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateKind {
+    Ready,
+    Processing,
+    ProcessingQueue,
+    AwaitingInput,
+    CallingTool,
+    Error,
+    TimedOut,
+    RateLimited,
+    Paused,
+    Custom,
+}
 
+impl<S> AgentState<S> {
+    /// The kind of this state
+    pub fn kind(&self) -> StateKind {
+        match self {
+            AgentState::Ready => StateKind::Ready,
+            AgentState::Processing => StateKind::Processing,
+            AgentState::ProcessingQueue => StateKind::ProcessingQueue,
+            AgentState::AwaitingInput { .. } => StateKind::AwaitingInput,
+            AgentState::CallingTool { .. } => StateKind::CallingTool,
+            AgentState::Error(_) => StateKind::Error,
+            AgentState::TimedOut => StateKind::TimedOut,
+            AgentState::RateLimited => StateKind::RateLimited,
+            AgentState::Paused => StateKind::Paused,
+            AgentState::Custom(_) => StateKind::Custom,
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -37,17 +135,47 @@ mod tests {
 
     #[test]
     fn test_state_display() {
-        assert_eq!(AgentState::Ready.to_string(), "Ready");
-        assert_eq!(AgentState::Processing.to_string(), "Processing");
+        assert_eq!(AgentState::<String>::Ready.to_string(), "Ready");
+        assert_eq!(AgentState::<String>::Processing.to_string(), "Processing");
         assert_eq!(
-            AgentState::Error("test error".into()).to_string(),
+            AgentState::<String>::Error("test error".into()).to_string(),
             "Error: test error"
         );
+        assert_eq!(
+            AgentState::<String>::AwaitingInput {
+                prompt: "Pick a door".into()
+            }
+            .to_string(),
+            "Awaiting Input: Pick a door"
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Phase {
+        Plotting,
+    }
+
+    impl fmt::Display for Phase {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    #[test]
+    fn test_custom_state_display() {
+        assert_eq!(AgentState::Custom("Reviewing".to_string()).to_string(), "Reviewing");
+        assert_eq!(AgentState::Custom(Phase::Plotting).to_string(), "Plotting");
+        assert_eq!(AgentState::<Phase>::RateLimited.to_string(), "RateLimited");
+        assert_eq!(
+            AgentState::<Phase>::CallingTool { name: "search".into() }.to_string(),
+            "Calling Tool: search"
+        );
+        assert_eq!(AgentState::Custom(Phase::Plotting).kind(), StateKind::Custom);
     }
 
     #[test]
     fn test_state_clone_and_eq() {
-        let state = AgentState::Ready;
+        let state: AgentState = AgentState::Ready;
         let cloned = state.clone();
         assert_eq!(state, cloned);
     }
@@ -0,0 +1,124 @@
+// src/store.rs
+
+use crate::history::HistoryEntry;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Errors from a [`HistoryStore`]
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("history store I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to encode or decode history: {0}")]
+    Json(#[from] serde_json::Error),
+    /// For backends with their own error types, e.g. a database client
+    #[error("history store failed: {0}")]
+    Backend(String),
+}
+
+/// Where chat histories are persisted, keyed by session id.
+///
+/// Methods return boxed futures so a machine can hold any store behind a trait object.
+/// Implement it for Redis, SQLite or similar by wrapping the body in
+/// `Box::pin(async move { ... })`.
+pub trait HistoryStore: Send + Sync {
+    /// Replace the history saved under `key`
+    fn save<'a>(&'a self, key: &'a str, history: &'a [HistoryEntry]) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// The history saved under `key`, or `None` if there is none
+    fn load<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<HistoryEntry>>, StoreError>>;
+}
+
+/// Keeps histories in memory. Clones share the same sessions, so several machines in
+/// one process can hand conversations to each other.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryHistoryStore {
+    sessions: Arc<Mutex<HashMap<String, Vec<HistoryEntry>>>>,
+}
+
+impl InMemoryHistoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl HistoryStore for InMemoryHistoryStore {
+    fn save<'a>(&'a self, key: &'a str, history: &'a [HistoryEntry]) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.sessions
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), history.to_vec());
+            Ok(())
+        })
+    }
+
+    fn load<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<HistoryEntry>>, StoreError>> {
+        Box::pin(async move { Ok(self.sessions.lock().unwrap().get(key).cloned()) })
+    }
+}
+
+/// Saves each session as a JSON array of [`HistoryEntry`] in its own file,
+/// `<dir>/<session id>.json`
+#[derive(Debug, Clone)]
+pub struct FileHistoryStore {
+    dir: PathBuf,
+}
+
+impl FileHistoryStore {
+    /// Store sessions in `dir`, which is created on the first save
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Session ids may come from users, so anything but letters, digits, `-` and `_`
+    /// is replaced to keep the file inside `dir`
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = key
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+impl HistoryStore for FileHistoryStore {
+    fn save<'a>(&'a self, key: &'a str, history: &'a [HistoryEntry]) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(self.path(key), serde_json::to_string_pretty(history)?).await?;
+            Ok(())
+        })
+    }
+
+    fn load<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<HistoryEntry>>, StoreError>> {
+        Box::pin(async move {
+            match tokio::fs::read_to_string(self.path(key)).await {
+                Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("history-store-test-{}", std::process::id()));
+        let store = FileHistoryStore::new(&dir);
+        let history = vec![HistoryEntry::user("Hi"), HistoryEntry::assistant("Hello!")];
+
+        assert!(store.load("user/42").await.unwrap().is_none());
+        store.save("user/42", &history).await.unwrap();
+        assert!(dir.join("user_42.json").exists());
+        assert_eq!(store.load("user/42").await.unwrap(), Some(history));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
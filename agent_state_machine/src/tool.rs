@@ -0,0 +1,136 @@
+// src/tool.rs
+
+use crate::error::MachineError;
+use crate::machine::ChatAgentStateMachine;
+use crate::respond::Respond;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// What the calling agent sends to a [`StateMachineTool`]
+#[derive(Debug, Deserialize)]
+pub struct StateMachineToolArgs {
+    pub message: String,
+}
+
+/// Exposes a state machine as a rig [`Tool`], so one agent can hand a sub-conversation
+/// to another, e.g. a router agent calling a specialist.
+///
+/// Each call sends the tool's `message` argument through the inner machine and returns
+/// its response as text. The inner machine keeps its own history, so repeated calls
+/// continue the same sub-conversation.
+///
+/// ```rust,no_run
+/// use agent_state_machine::{openai_client_from_env, ChatAgentStateMachine, StateMachineTool};
+///
+/// let client = openai_client_from_env();
+/// let specialist = ChatAgentStateMachine::new(client.agent("gpt-4o").preamble("You are a Rust expert.").build());
+/// let router = client
+///     .agent("gpt-4o")
+///     .tool(StateMachineTool::new(specialist, "ask_rust_expert", "Ask a Rust expert a question"))
+///     .build();
+/// ```
+pub struct StateMachineTool<A: Respond> {
+    name: String,
+    description: String,
+    machine: Arc<Mutex<ChatAgentStateMachine<A>>>,
+}
+
+impl<A: Respond> StateMachineTool<A> {
+    /// Wrap `machine` in a tool called `name`; `description` tells the calling agent
+    /// when to use it
+    pub fn new(machine: ChatAgentStateMachine<A>, name: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            machine: Arc::new(Mutex::new(machine)),
+        }
+    }
+
+    /// The inner machine, e.g. to read its history after the calling agent is done
+    pub fn machine(&self) -> Arc<Mutex<ChatAgentStateMachine<A>>> {
+        Arc::clone(&self.machine)
+    }
+}
+
+impl<A> Tool for StateMachineTool<A>
+where
+    A: Respond + 'static,
+    A::Error: Sync + 'static,
+{
+    const NAME: &'static str = "state_machine";
+    type Error = MachineError<A::Error>;
+    type Args = StateMachineToolArgs;
+    type Output = String;
+
+    /// Each wrapped machine gets its own name, so several can be offered side by side
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "message": { "type": "string", "description": "The message to send" }
+                },
+                "required": ["message"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // rig requires the call future to be `Sync`, which an agent's response future
+        // need not be, so the conversation runs on its own task
+        let machine = Arc::clone(&self.machine);
+        let task = tokio::spawn(async move {
+            let mut machine = machine.lock().await;
+            machine
+                .process_single_message(&args.message)
+                .await
+                .map(|response| A::to_text(&response))
+        });
+        match task.await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::{Chat, Message, PromptError};
+    use rig::tool::ToolSet;
+
+    struct CountingAgent;
+
+    impl Chat for CountingAgent {
+        async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            Ok(format!("{} after {} messages", prompt, history.len()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_continues_the_inner_conversation() {
+        let tool = StateMachineTool::new(ChatAgentStateMachine::new(CountingAgent), "specialist", "Ask the specialist");
+        let machine = tool.machine();
+        let mut toolset = ToolSet::default();
+        toolset.add_tool(tool);
+
+        assert!(toolset.contains("specialist"));
+        let first = toolset.call("specialist", r#"{"message":"Hi"}"#.to_string()).await.unwrap();
+        let second = toolset.call("specialist", r#"{"message":"More"}"#.to_string()).await.unwrap();
+
+        // Outputs are JSON-encoded strings
+        assert_eq!(first, r#""Hi after 0 messages""#);
+        assert_eq!(second, r#""More after 2 messages""#);
+        assert_eq!(machine.lock().await.history().len(), 4);
+    }
+}
@@ -0,0 +1,153 @@
+// src/transcript.rs
+
+use crate::history::HistoryEntry;
+use crate::machine::ChatAgentStateMachine;
+use crate::state::{AgentState, CustomState};
+use crate::respond::Respond;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// One exchange in a transcript file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub user: String,
+    /// The agent's answer, missing if the request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assistant: Option<String>,
+    /// Why the request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Errors from [`ChatAgentStateMachine::run_transcript`]
+#[derive(Debug, thiserror::Error)]
+pub enum TranscriptError {
+    #[error("transcript I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to encode transcript entry: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl<A: Respond, S: CustomState> ChatAgentStateMachine<A, S> {
+    /// Run every line of `path_in` through the machine as a user message and write
+    /// the exchanges to `path_out` as JSONL, one [`TranscriptEntry`] per line.
+    ///
+    /// Blank lines are skipped. Each message is processed until the machine is idle
+    /// before the next one is sent, so history carries over exactly as in a live chat.
+    /// A failed request is recorded with its error and the run goes on. Each entry is
+    /// flushed as soon as it is written, so an interrupted run keeps its progress.
+    /// Returns the number of entries written.
+    pub async fn run_transcript(
+        &mut self,
+        path_in: impl AsRef<Path>,
+        path_out: impl AsRef<Path>,
+    ) -> Result<usize, TranscriptError> {
+        let input = tokio::fs::read_to_string(path_in).await?;
+        let mut output = tokio::fs::File::create(path_out).await?;
+        let mut written = 0;
+
+        for line in input.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let start = self.history().len();
+            // The machine returns to Ready after a failure, so look for it among the transitions
+            let mut states = self.subscribe_to_state_changes();
+            let mut error = self.process_message(line).await.err().map(|e| e.to_string());
+            while let Ok(state) = states.try_recv() {
+                if let AgentState::Error(e) = state {
+                    error = Some(e);
+                }
+            }
+
+            let mut entries = exchanges(&self.history()[start..]);
+            if let Some(error) = error {
+                warn!("Transcript message failed: {}", error);
+                match entries.last_mut() {
+                    Some(entry) if entry.assistant.is_none() => entry.error = Some(error),
+                    _ => entries.push(TranscriptEntry {
+                        user: line.to_string(),
+                        assistant: None,
+                        error: Some(error),
+                    }),
+                }
+            }
+
+            for entry in entries {
+                let mut json = serde_json::to_string(&entry)?;
+                json.push('\n');
+                output.write_all(json.as_bytes()).await?;
+                written += 1;
+            }
+            output.flush().await?;
+        }
+
+        info!("Transcript finished with {} entries", written);
+        Ok(written)
+    }
+}
+
+/// Pair each user message in `history` with the assistant reply that follows it
+fn exchanges(history: &[HistoryEntry]) -> Vec<TranscriptEntry> {
+    let mut entries: Vec<TranscriptEntry> = Vec::new();
+    for turn in history {
+        match turn {
+            HistoryEntry::User { content } => entries.push(TranscriptEntry {
+                user: content.clone(),
+                assistant: None,
+                error: None,
+            }),
+            HistoryEntry::Assistant { content } => {
+                if let Some(entry) = entries.last_mut().filter(|e| e.assistant.is_none()) {
+                    entry.assistant = Some(content.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::{Chat, CompletionError, Message, PromptError};
+
+    struct EchoAgent;
+
+    impl Chat for EchoAgent {
+        async fn chat(&self, prompt: &str, history: Vec<Message>) -> Result<String, PromptError> {
+            if prompt == "fail" {
+                return Err(CompletionError::ProviderError("unavailable".into()).into());
+            }
+            Ok(format!("Echo {}: {}", history.len(), prompt))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_transcript() {
+        let dir = std::env::temp_dir().join(format!("transcript-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path_in = dir.join("in.txt");
+        let path_out = dir.join("out.jsonl");
+        std::fs::write(&path_in, "Hello\n\nfail\nBye\n").unwrap();
+
+        let mut machine = ChatAgentStateMachine::new(EchoAgent);
+        machine.set_response_callback(|_| {});
+        let written = machine.run_transcript(&path_in, &path_out).await.unwrap();
+
+        let entries: Vec<TranscriptEntry> = std::fs::read_to_string(&path_out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(written, 3);
+        assert_eq!(entries[0].assistant.as_deref(), Some("Echo 0: Hello"));
+        assert_eq!(entries[1].user, "fail");
+        assert!(entries[1].assistant.is_none() && entries[1].error.is_some());
+        // History carries over between lines, including the failed message
+        assert_eq!(entries[2].assistant.as_deref(), Some("Echo 3: Bye"));
+        assert_eq!(machine.current_state(), &AgentState::Ready);
+    }
+}
@@ -0,0 +1,70 @@
+// src/transition.rs
+
+use crate::state::StateKind;
+use std::collections::{HashMap, HashSet};
+
+/// Which state changes [`transition_to`](crate::ChatAgentStateMachine::transition_to)
+/// accepts, by [`StateKind`].
+///
+/// The default is permissive and allows every change. A restrictive table starts
+/// from [`TransitionRules::new`], which allows nothing, and lists the allowed targets
+/// for each source kind:
+///
+/// ```rust
+/// use agent_state_machine::{StateKind, TransitionRules};
+///
+/// // Errors must be acknowledged by going back to Ready before new work starts
+/// let rules = TransitionRules::new()
+///     .allow(StateKind::Ready, &[StateKind::Processing, StateKind::Custom])
+///     .allow(StateKind::Custom, &[StateKind::Ready, StateKind::Custom, StateKind::Error])
+///     .allow(StateKind::Processing, &[StateKind::Ready, StateKind::Error])
+///     .allow(StateKind::Error, &[StateKind::Ready]);
+///
+/// assert!(rules.is_allowed(StateKind::Error, StateKind::Ready));
+/// assert!(!rules.is_allowed(StateKind::Error, StateKind::Processing));
+/// ```
+///
+/// The rules apply to changes requested through `transition_to`. The transitions the
+/// machine makes itself while processing messages are always allowed, so a strict
+/// table can't stall the queue.
+#[derive(Debug, Clone)]
+pub struct TransitionRules {
+    /// Allowed targets per source kind, or `None` to allow everything
+    allowed: Option<HashMap<StateKind, HashSet<StateKind>>>,
+}
+
+impl Default for TransitionRules {
+    fn default() -> Self {
+        Self::permissive()
+    }
+}
+
+impl TransitionRules {
+    /// A table that allows no transitions until some are added with [`allow`](Self::allow)
+    pub fn new() -> Self {
+        Self {
+            allowed: Some(HashMap::new()),
+        }
+    }
+
+    /// A table that allows every transition, the default
+    pub fn permissive() -> Self {
+        Self { allowed: None }
+    }
+
+    /// Allow moving from `from` to each of `to`. Has no effect on a permissive table.
+    pub fn allow(mut self, from: StateKind, to: &[StateKind]) -> Self {
+        if let Some(allowed) = &mut self.allowed {
+            allowed.entry(from).or_default().extend(to.iter().copied());
+        }
+        self
+    }
+
+    /// Whether moving from `from` to `to` is allowed
+    pub fn is_allowed(&self, from: StateKind, to: StateKind) -> bool {
+        match &self.allowed {
+            Some(allowed) => allowed.get(&from).is_some_and(|targets| targets.contains(&to)),
+            None => true,
+        }
+    }
+}
@@ -49,21 +49,65 @@ async fn main() -> Result<()> {
             break;
         }
 
-        chat_history.push(Message {
-            role: "user".to_string(),
-            content: question,
-        });
-
         // Get the model's response
-        let response = model.chat(&chat_history.last().unwrap().content, chat_history.clone()).await?;
+        let response = ask(&model, &mut chat_history, question).await?;
 
         println!("Answer: {}", response);
-
-        chat_history.push(Message {
-            role: "assistant".to_string(),
-            content: response,
-        });
     }
 
     Ok(())
+}
+
+/// Ask `question` and record the exchange in `chat_history`.
+///
+/// `Chat::chat` takes the new prompt separately from the history: the history holds
+/// only the earlier turns and the model appends the prompt itself. The question is
+/// added to the history after the call so it isn't sent twice.
+async fn ask(model: &impl Chat, chat_history: &mut Vec<Message>, question: String) -> Result<String> {
+    let response = model.chat(&question, chat_history.clone()).await?;
+
+    chat_history.push(Message {
+        role: "user".to_string(),
+        content: question,
+    });
+    chat_history.push(Message {
+        role: "assistant".to_string(),
+        content: response.clone(),
+    });
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::PromptError;
+    use std::sync::Mutex;
+
+    /// Records the messages it would send to the model, prompt last
+    #[derive(Default)]
+    struct RecordingModel {
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl Chat for RecordingModel {
+        async fn chat(&self, prompt: &str, chat_history: Vec<Message>) -> Result<String, PromptError> {
+            let mut sent: Vec<String> = chat_history.into_iter().map(|m| m.content).collect();
+            sent.push(prompt.to_string());
+            *self.sent.lock().unwrap() = sent;
+            Ok("Answer".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_question_is_sent_once() {
+        let model = RecordingModel::default();
+        let mut chat_history = Vec::new();
+
+        ask(&model, &mut chat_history, "First?".to_string()).await.unwrap();
+        ask(&model, &mut chat_history, "Second?".to_string()).await.unwrap();
+
+        assert_eq!(*model.sent.lock().unwrap(), ["First?", "Answer", "Second?"]);
+        assert_eq!(chat_history.len(), 4);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+// citations.rs
+
+use serde::{Deserialize, Serialize};
+
+/// Sections longer than this are split further at paragraph breaks
+const MAX_CHUNK_CHARS: usize = 1500;
+
+/// Where a chunk of the knowledge base came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkMetadata {
+    /// Document name, e.g. `Rig_guide`
+    pub source: String,
+    /// 1-based position of the chunk within its document
+    pub section: usize,
+    /// Closest markdown heading above the chunk, if any
+    pub heading: Option<String>,
+    /// Byte offset where the chunk starts in the source file
+    pub start: usize,
+    /// Byte offset just past the end of the chunk
+    pub end: usize,
+}
+
+impl ChunkMetadata {
+    /// Unique id used for the chunk in the vector store
+    pub fn id(&self) -> String {
+        format!("{}#{}", self.source, self.section)
+    }
+
+    /// Short human-readable reference, e.g. `[Rig_guide §2]`
+    pub fn citation(&self) -> String {
+        format!("[{} §{}]", self.source, self.section)
+    }
+}
+
+/// A piece of a document, stored in the vector store together with its metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub metadata: ChunkMetadata,
+    pub text: String,
+}
+
+fn heading_text(line: &str) -> Option<&str> {
+    let hashes = line.chars().take_while(|c| *c == '#').count();
+    if hashes == 0 || !line[hashes..].starts_with(' ') {
+        return None;
+    }
+    Some(line[hashes..].trim())
+}
+
+/// Split a markdown document into chunks at headings, and inside long sections at
+/// blank lines. Code blocks are never split, and `#` lines inside them are not
+/// mistaken for headings.
+pub fn chunk_markdown(source: &str, content: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut chunk_heading: Option<String> = None;
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_code_block = false;
+
+    let push = |chunks: &mut Vec<Chunk>, start: usize, end: usize, heading: &Option<String>| {
+        let text = content[start..end].trim();
+        if text.is_empty() {
+            return;
+        }
+        chunks.push(Chunk {
+            metadata: ChunkMetadata {
+                source: source.to_string(),
+                section: chunks.len() + 1,
+                heading: heading.clone(),
+                start,
+                end,
+            },
+            text: text.to_string(),
+        });
+    };
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        let new_heading = if in_code_block { None } else { heading_text(trimmed) };
+        if new_heading.is_some() && offset > start {
+            push(&mut chunks, start, offset, &chunk_heading);
+            start = offset;
+        }
+        if let Some(text) = new_heading {
+            heading = Some(text.to_string());
+            chunk_heading = heading.clone();
+        }
+
+        offset += line.len();
+
+        if !in_code_block && trimmed.is_empty() && offset - start >= MAX_CHUNK_CHARS {
+            push(&mut chunks, start, offset, &chunk_heading);
+            start = offset;
+            chunk_heading = heading.clone();
+        }
+    }
+    push(&mut chunks, start, content.len(), &chunk_heading);
+
+    chunks
+}
+
+/// Append a list of sources to an answer.
+///
+/// Only the retrieved chunks the answer actually cites inline are listed; if it cites
+/// none of them, every retrieved chunk is listed since they all made up the context.
+pub fn append_citations(answer: &str, retrieved: &[ChunkMetadata]) -> String {
+    if retrieved.is_empty() {
+        return answer.to_string();
+    }
+
+    let mut cited: Vec<&ChunkMetadata> = retrieved
+        .iter()
+        .filter(|chunk| answer.contains(&chunk.citation()))
+        .collect();
+    if cited.is_empty() {
+        cited = retrieved.iter().collect();
+    }
+
+    let sources = cited
+        .iter()
+        .map(|chunk| match &chunk.heading {
+            Some(heading) => format!("- {} {}", chunk.citation(), heading),
+            None => format!("- {}", chunk.citation()),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("{}\n\nSources:\n{}", answer.trim_end(), sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_markdown_splits_on_headings_outside_code() {
+        let doc = "# Guide\nIntro\n\n## 1. Setup\nInstall it.\n```rust\n#[tokio::main]\n# not a heading\n```\n## 2. Usage\nCall it.\n";
+        let chunks = chunk_markdown("Rig_guide", doc);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].metadata.heading.as_deref(), Some("1. Setup"));
+        assert!(chunks[1].text.contains("# not a heading"));
+        assert_eq!(chunks[2].metadata.citation(), "[Rig_guide §3]");
+
+        let meta = &chunks[2].metadata;
+        assert_eq!(doc[meta.start..meta.end].trim(), chunks[2].text);
+    }
+
+    #[test]
+    fn test_append_citations_lists_cited_chunks() {
+        let chunks = chunk_markdown("Rig_faq", "## Agents\nAgents...\n## Tools\nTools...\n");
+        let retrieved: Vec<ChunkMetadata> = chunks.into_iter().map(|c| c.metadata).collect();
+
+        let answer = append_citations("Use a tool [Rig_faq §2].", &retrieved);
+        assert!(answer.ends_with("Sources:\n- [Rig_faq §2] Tools"));
+
+        let answer = append_citations("No inline citations.", &retrieved);
+        assert!(answer.contains("- [Rig_faq §1] Agents\n- [Rig_faq §2] Tools"));
+    }
+}
@@ -0,0 +1,115 @@
+// dedup.rs
+
+use std::collections::HashSet;
+use tracing::{debug, warn};
+
+/// Overlap at which a lower-ranked chunk counts as a near-duplicate
+const DEFAULT_OVERLAP_THRESHOLD: f64 = 0.8;
+/// Number of consecutive words compared when measuring overlap
+const SHINGLE_WORDS: usize = 3;
+
+/// The distinct word runs of a text
+type Shingles = HashSet<Vec<String>>;
+
+/// Drops retrieved chunks that mostly repeat text from a better-ranked chunk
+#[derive(Debug, Clone, Copy)]
+pub struct OverlapFilter {
+    threshold: f64,
+}
+
+impl Default for OverlapFilter {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_OVERLAP_THRESHOLD,
+        }
+    }
+}
+
+impl OverlapFilter {
+    /// Treat chunks whose overlap is at least `threshold` (between 0 and 1) as duplicates
+    pub fn new(threshold: f64) -> Self {
+        Self { threshold }
+    }
+
+    /// Read `CHUNK_OVERLAP_THRESHOLD` from the environment, e.g. `0.6`.
+    ///
+    /// Uses the default of 0.8 if it is unset or not a number in `0..=1`. A
+    /// threshold of 1 only drops chunks fully contained in another.
+    pub fn from_env() -> Self {
+        match std::env::var("CHUNK_OVERLAP_THRESHOLD") {
+            Ok(value) => match value.trim().parse::<f64>() {
+                Ok(threshold) if (0.0..=1.0).contains(&threshold) => Self::new(threshold),
+                _ => {
+                    warn!("Ignoring invalid CHUNK_OVERLAP_THRESHOLD: {:?}", value);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Keep ranked items in order, skipping any whose text overlaps an already kept
+    /// item by at least the threshold. Stops once `n` items are kept.
+    pub fn dedup<T>(&self, ranked: Vec<(f64, T)>, n: usize, text: impl Fn(&T) -> &str) -> Vec<(f64, T)> {
+        let mut kept: Vec<((f64, T), Shingles)> = Vec::new();
+        for (score, item) in ranked {
+            if kept.len() == n {
+                break;
+            }
+            let shingles = shingles(text(&item));
+            let duplicate = kept
+                .iter()
+                .any(|(_, other)| overlap(&shingles, other) >= self.threshold);
+            if duplicate {
+                debug!("Dropping near-duplicate chunk with score {:.3}", score);
+                continue;
+            }
+            kept.push(((score, item), shingles));
+        }
+        kept.into_iter().map(|(item, _)| item).collect()
+    }
+}
+
+/// Runs of `SHINGLE_WORDS` consecutive words, lowercased, so that overlap ignores
+/// case and whitespace differences. Short texts become a single shingle.
+fn shingles(text: &str) -> Shingles {
+    let words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+    if words.len() <= SHINGLE_WORDS {
+        return std::iter::once(words).filter(|words| !words.is_empty()).collect();
+    }
+    words.windows(SHINGLE_WORDS).map(<[String]>::to_vec).collect()
+}
+
+/// Share of the smaller text that also appears in the other one, from 0 to 1.
+///
+/// Measuring against the smaller side means a chunk that is entirely contained in a
+/// longer one counts as a full duplicate.
+fn overlap(a: &Shingles, b: &Shingles) -> f64 {
+    let smaller = a.len().min(b.len());
+    if smaller == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / smaller as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_drops_overlapping_chunks() {
+        let ranked = vec![
+            (0.9, "Agents combine a model with a preamble and tools to answer prompts."),
+            (0.8, "agents combine a model with a preamble and tools to answer prompts. They can also use context."),
+            (0.7, "Vector stores keep embeddings for retrieval."),
+            (0.6, "Extractors turn text into structured data."),
+        ];
+
+        let kept = OverlapFilter::default().dedup(ranked.clone(), 2, |text| text);
+        assert_eq!(kept, vec![ranked[0], ranked[2]]);
+
+        // A threshold above any real overlap keeps everything
+        let kept = OverlapFilter::new(1.1).dedup(ranked.clone(), 4, |text| text);
+        assert_eq!(kept, ranked);
+    }
+}
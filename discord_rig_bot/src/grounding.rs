@@ -0,0 +1,67 @@
+// grounding.rs
+
+/// Reply the verifier is asked to give when every claim is supported
+const SUPPORTED: &str = "NONE";
+
+/// Preamble for the agent that checks answers against the retrieved context
+pub const VERIFIER_PREAMBLE: &str = "You are a strict fact checker. You compare an answer with the source context it was based on and report any claim the context does not support. General Rust knowledge needed to follow the context counts as supported; specific facts about Rig must come from the context.";
+
+/// Ask whether every claim in `answer` is supported by `context`
+pub fn verification_prompt(context: &str, answer: &str) -> String {
+    format!(
+        "Context:\n{}\n\nAnswer:\n{}\n\nIs every claim in the answer supported by the context? List each unsupported claim on its own line starting with \"- \". If every claim is supported, reply with just {}.",
+        context, answer, SUPPORTED
+    )
+}
+
+/// Extract the unsupported claims from the verifier's reply; empty if there are none
+pub fn parse_unsupported(reply: &str) -> Vec<String> {
+    if reply.trim().trim_end_matches('.').eq_ignore_ascii_case(SUPPORTED) {
+        return Vec::new();
+    }
+    reply
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("- ").or_else(|| line.trim().strip_prefix("* ")))
+        .map(|claim| claim.trim().to_string())
+        .filter(|claim| !claim.is_empty() && !claim.eq_ignore_ascii_case(SUPPORTED))
+        .collect()
+}
+
+/// Re-ask the question, telling the model which claims went beyond the context
+pub fn stricter_prompt(prompt: &str, unsupported: &[String]) -> String {
+    format!(
+        "{}\n\nA previous answer made claims the sources above don't support:\n{}\nAnswer again using only information from the sources. If they don't cover part of the question, say so instead of guessing.",
+        prompt,
+        bullet_list(unsupported)
+    )
+}
+
+/// Append a note listing claims that could not be verified
+pub fn append_caveat(answer: &str, unsupported: &[String]) -> String {
+    format!(
+        "{}\n\n⚠️ Some of this could not be verified against the documentation:\n{}",
+        answer.trim_end(),
+        bullet_list(unsupported)
+    )
+}
+
+fn bullet_list(items: &[String]) -> String {
+    items.iter().map(|item| format!("- {}", item)).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unsupported() {
+        assert!(parse_unsupported(" none. ").is_empty());
+
+        let reply = "Unsupported claims:\n- Rig supports Python.\n* Agents run on GPUs\n-\n";
+        let claims = parse_unsupported(reply);
+        assert_eq!(claims, vec!["Rig supports Python.", "Agents run on GPUs"]);
+
+        let answer = append_caveat("Rig is a Rust library.\n", &claims);
+        assert!(answer.ends_with("documentation:\n- Rig supports Python.\n- Agents run on GPUs"));
+    }
+}
@@ -1,7 +1,14 @@
 // main.rs
 
+mod citations;
+mod dedup;
+mod grounding;
+mod ranking;
 mod rig_agent;
+mod sanitize;
 
+use agent_state_machine::{DiscordFormatter, ResponseFormatter};
+use examples_common::logging::init_tracing;
 use anyhow::Result;
 use serenity::async_trait;
 use serenity::model::application::command::Command;
@@ -25,6 +32,8 @@ impl TypeMapKey for BotUserId {
 
 struct Handler {
     rig_agent: Arc<RigAgent>,
+    // Splits replies to fit Discord's message limit, keeping code blocks intact
+    formatter: DiscordFormatter,
 }
 
 #[async_trait]
@@ -57,18 +66,29 @@ impl EventHandler for Handler {
 
             debug!("Sending response: {}", content);
 
+            // The first part answers the interaction, the rest follow as plain messages
+            let mut parts = self.formatter.format(&content).into_iter();
+            let first = parts.next().unwrap_or_default();
+
             if let Err(why) = command
                 .create_interaction_response(&ctx.http, |response| {
                     response
                         .kind(InteractionResponseType::ChannelMessageWithSource)
-                        .interaction_response_data(|message| message.content(content))
+                        .interaction_response_data(|message| message.content(first))
                 })
                 .await
             {
                 error!("Cannot respond to slash command: {}", why);
-            } else {
-                debug!("Response sent successfully");
+                return;
+            }
+
+            for part in parts {
+                if let Err(why) = command.channel_id.say(&ctx.http, part).await {
+                    error!("Error sending message: {:?}", why);
+                    return;
+                }
             }
+            debug!("Response sent successfully");
         }
     }
 
@@ -87,10 +107,25 @@ impl EventHandler for Handler {
 
                 debug!("Processed content after removing mention: {}", content);
 
+                // A bare mention has no question to answer, so don't spend a request on it
+                if content.is_empty() {
+                    if let Err(why) = msg
+                        .channel_id
+                        .say(&ctx.http, "Mention me with a question, e.g. `@bot How do I build an agent?`")
+                        .await
+                    {
+                        error!("Error sending message: {:?}", why);
+                    }
+                    return;
+                }
+
                 match self.rig_agent.process_message(&content).await {
                     Ok(response) => {
-                        if let Err(why) = msg.channel_id.say(&ctx.http, response).await {
-                            error!("Error sending message: {:?}", why);
+                        for part in self.formatter.format(&response) {
+                            if let Err(why) = msg.channel_id.say(&ctx.http, part).await {
+                                error!("Error sending message: {:?}", why);
+                                break;
+                            }
                         }
                     }
                     Err(e) => {
@@ -140,7 +175,7 @@ impl EventHandler for Handler {
         })
         .await;
 
-        println!("Created the following global commands: {:#?}", commands);
+        info!("Created the following global commands: {:#?}", commands);
     }
 }
 
@@ -148,13 +183,15 @@ impl EventHandler for Handler {
 async fn main() -> Result<()> {
     dotenv().ok();
 
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    init_tracing(None);
 
     let token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment");
 
-    let rig_agent = Arc::new(RigAgent::new().await?);
+    let mut rig_agent = RigAgent::new().await?;
+    rig_agent.set_groundedness_check(
+        env::var("GROUNDEDNESS_CHECK").is_ok_and(|value| matches!(value.trim(), "1" | "true" | "yes")),
+    );
+    let rig_agent = Arc::new(rig_agent);
 
     let intents = GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::DIRECT_MESSAGES
@@ -163,6 +200,7 @@ async fn main() -> Result<()> {
     let mut client = Client::builder(&token, intents)
         .event_handler(Handler {
             rig_agent: Arc::clone(&rig_agent),
+            formatter: DiscordFormatter::default(),
         })
         .await
         .expect("Err creating client");
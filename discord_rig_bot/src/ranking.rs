@@ -0,0 +1,78 @@
+// ranking.rs
+
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Per-document multipliers applied to similarity scores when ranking chunks
+#[derive(Debug, Clone, Default)]
+pub struct DocumentWeights {
+    weights: HashMap<String, f64>,
+}
+
+impl DocumentWeights {
+    /// Parse weights written as `source=weight` pairs separated by `;`,
+    /// e.g. `Rig_guide=1.5;Rig_examples=0.8`. Malformed entries are skipped.
+    pub fn parse(spec: &str) -> Self {
+        let weights = spec
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let parsed = entry
+                    .split_once('=')
+                    .and_then(|(source, weight)| Some((source.trim().to_string(), weight.trim().parse().ok()?)))
+                    .filter(|(_, weight): &(String, f64)| weight.is_finite() && *weight >= 0.0);
+                if parsed.is_none() {
+                    warn!("Ignoring invalid document weight: {:?}", entry);
+                }
+                parsed
+            })
+            .collect();
+        Self { weights }
+    }
+
+    /// Read `DOCUMENT_WEIGHTS` from the environment; no weights if it is unset
+    pub fn from_env() -> Self {
+        std::env::var("DOCUMENT_WEIGHTS")
+            .map(|spec| Self::parse(&spec))
+            .unwrap_or_default()
+    }
+
+    /// Weight of a source document, 1.0 if it has none
+    pub fn weight(&self, source: &str) -> f64 {
+        self.weights.get(source).copied().unwrap_or(1.0)
+    }
+
+    /// Re-rank scored candidates by `score * weight` and keep the best `n`.
+    ///
+    /// `source` maps a candidate to the document it came from.
+    pub fn rerank<T>(&self, candidates: Vec<(f64, T)>, n: usize, source: impl Fn(&T) -> &str) -> Vec<(f64, T)> {
+        let mut ranked: Vec<(f64, T)> = candidates
+            .into_iter()
+            .map(|(score, item)| (score * self.weight(source(&item)), item))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranked.truncate(n);
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rerank_with_weights() {
+        let weights = DocumentWeights::parse("Rig_guide=2; Rig_examples=0.5; broken; Rig_faq=abc");
+        assert_eq!(weights.weight("Rig_guide"), 2.0);
+        assert_eq!(weights.weight("Rig_faq"), 1.0);
+
+        let candidates = vec![
+            (0.9, "Rig_examples"),
+            (0.6, "Rig_faq"),
+            (0.5, "Rig_guide"),
+        ];
+        let ranked = weights.rerank(candidates, 2, |source| source);
+        assert_eq!(ranked, vec![(1.0, "Rig_guide"), (0.6, "Rig_faq")]);
+    }
+}
@@ -2,17 +2,35 @@
 
 use anyhow::{Context, Result};
 use rig::providers::openai;
-use rig::vector_store::in_memory_store::InMemoryVectorStore;
-use rig::vector_store::VectorStore;
+use rig::vector_store::in_memory_store::{InMemoryVectorIndex, InMemoryVectorStore};
+use rig::vector_store::{VectorStore, VectorStoreIndex};
 use rig::embeddings::EmbeddingsBuilder;
 use rig::agent::Agent;
 use rig::completion::Prompt;
 use std::path::Path;
 use std::fs;
 use std::sync::Arc;
+use crate::citations::{append_citations, chunk_markdown, Chunk, ChunkMetadata};
+use crate::dedup::OverlapFilter;
+use crate::grounding;
+use crate::ranking::DocumentWeights;
+use crate::sanitize::{InjectionFilter, REJECTION_REPLY};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+// Number of chunks retrieved as context for each question
+const CONTEXT_CHUNKS: usize = 4;
+// Number of candidates fetched from the index before re-ranking and dedup
+const CANDIDATE_CHUNKS: usize = 12;
 
 pub struct RigAgent {
     agent: Arc<Agent<openai::CompletionModel>>,
+    /// Checks answers against the retrieved context when the groundedness check is on
+    verifier: Arc<Agent<openai::CompletionModel>>,
+    index: Arc<InMemoryVectorIndex<openai::EmbeddingModel>>,
+    injection_filter: InjectionFilter,
+    document_weights: DocumentWeights,
+    overlap_filter: OverlapFilter,
+    groundedness_check: bool,
 }
 
 impl RigAgent {
@@ -37,18 +55,32 @@ impl RigAgent {
         let md2_content = Self::load_md_content(&md2_path)?;
         let md3_content = Self::load_md_content(&md3_path)?;
 
+        // Split documents into chunks that remember where they came from
+        let chunks: Vec<Chunk> = [
+            ("Rig_guide", &md1_content),
+            ("Rig_faq", &md2_content),
+            ("Rig_examples", &md3_content),
+        ]
+        .into_iter()
+        .flat_map(|(source, content)| chunk_markdown(source, content))
+        .collect();
+
         // Create embeddings and add to vector store
         let embeddings = EmbeddingsBuilder::new(embedding_model.clone())
-            .simple_document("Rig_guide", &md1_content)
-            .simple_document("Rig_faq", &md2_content)
-            .simple_document("Rig_examples", &md3_content)
+            .documents(
+                chunks
+                    .into_iter()
+                    .map(|chunk| (chunk.metadata.id(), chunk.clone(), vec![chunk.text]))
+                    .collect(),
+            )
             .build()
             .await?;
 
         vector_store.add_documents(embeddings).await?;
+        info!("Indexed the knowledge base");
 
         // Create index
-        let index = vector_store.index(embedding_model);
+        let index = Arc::new(vector_store.index(embedding_model));
 
         // Create Agent
         let agent = Arc::new(openai_client.agent(openai::GPT_4O)
@@ -64,11 +96,36 @@ impl RigAgent {
                         println!(\"{}\", example_code);
                         ```
                     5. Keep your responses short and concise. If the user needs more information, they can ask follow-up questions.
+                    6. Citations: Each retrieved source is tagged with a citation such as [Rig_guide §2]. When you use information from a source, cite it inline with its exact tag.
                     ")
-            .dynamic_context(2, index)
             .build());
 
-        Ok(Self { agent })
+        let verifier = Arc::new(
+            openai_client
+                .agent(openai::GPT_4O)
+                .preamble(grounding::VERIFIER_PREAMBLE)
+                .build(),
+        );
+
+        Ok(Self {
+            agent,
+            verifier,
+            index,
+            injection_filter: InjectionFilter::from_env(),
+            document_weights: DocumentWeights::from_env(),
+            overlap_filter: OverlapFilter::from_env(),
+            groundedness_check: false,
+        })
+    }
+
+    /// Check each answer against the retrieved context before sending it.
+    ///
+    /// Off by default since it costs extra model calls. When on, the model is asked to
+    /// list claims the context doesn't support. If there are any, the answer is
+    /// regenerated with a stricter instruction and checked again; claims that are still
+    /// unsupported are listed in a caveat under the answer.
+    pub fn set_groundedness_check(&mut self, enabled: bool) {
+        self.groundedness_check = enabled;
     }
 
     fn load_md_content<P: AsRef<Path>>(file_path: P) -> Result<String> {
@@ -77,6 +134,83 @@ impl RigAgent {
     }
 
     pub async fn process_message(&self, message: &str) -> Result<String> {
-        self.agent.prompt(message).await.map_err(anyhow::Error::from)
+        self.answer(message)
+            .instrument(info_span!("question", len = message.len()))
+            .await
     }
-}
\ No newline at end of file
+
+    async fn answer(&self, message: &str) -> Result<String> {
+        // Retrieval uses the raw message, but the model only sees the filtered one
+        let Some(question) = self.injection_filter.apply(message) else {
+            return Ok(REJECTION_REPLY.to_string());
+        };
+
+        // Fetch more candidates than needed so boosted documents can move up and
+        // near-duplicates can be replaced
+        let candidates: Vec<(f64, Chunk)> = self
+            .index
+            .top_n_documents_from_query(message, CANDIDATE_CHUNKS)
+            .await?;
+        let ranked = self
+            .document_weights
+            .rerank(candidates, CANDIDATE_CHUNKS, |chunk| &chunk.metadata.source);
+        let retrieved: Vec<Chunk> = self
+            .overlap_filter
+            .dedup(ranked, CONTEXT_CHUNKS, |chunk| &chunk.text)
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .collect();
+        debug!(
+            "Retrieved chunks: {:?}",
+            retrieved.iter().map(|chunk| chunk.metadata.id()).collect::<Vec<_>>()
+        );
+
+        let context = retrieved
+            .iter()
+            .map(|chunk| format!("Source {}:\n{}", chunk.metadata.citation(), chunk.text))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let prompt = format!("{}\n\nQuestion: {}", context, question);
+        let mut answer = self.agent.prompt(&prompt).await.map_err(anyhow::Error::from)?;
+
+        if self.groundedness_check {
+            answer = self.ground(&context, &prompt, answer).await?;
+        }
+
+        let sources: Vec<ChunkMetadata> = retrieved.into_iter().map(|chunk| chunk.metadata).collect();
+        Ok(append_citations(&answer, &sources))
+    }
+
+    /// Verify `answer` against `context`, regenerating it once if it makes claims the
+    /// context doesn't support and adding a caveat if the retry still does
+    async fn ground(&self, context: &str, prompt: &str, answer: String) -> Result<String> {
+        let unsupported = self.unsupported_claims(context, &answer).await?;
+        if unsupported.is_empty() {
+            return Ok(answer);
+        }
+        info!("Answer had {} unsupported claim(s), regenerating", unsupported.len());
+
+        let retry = self
+            .agent
+            .prompt(&grounding::stricter_prompt(prompt, &unsupported))
+            .await
+            .map_err(anyhow::Error::from)?;
+        let unsupported = self.unsupported_claims(context, &retry).await?;
+        if unsupported.is_empty() {
+            return Ok(retry);
+        }
+        warn!("Regenerated answer still has {} unsupported claim(s)", unsupported.len());
+        Ok(grounding::append_caveat(&retry, &unsupported))
+    }
+
+    async fn unsupported_claims(&self, context: &str, answer: &str) -> Result<Vec<String>> {
+        let reply = self
+            .verifier
+            .prompt(&grounding::verification_prompt(context, answer))
+            .await
+            .map_err(anyhow::Error::from)?;
+        debug!("Groundedness check: {}", reply);
+        Ok(grounding::parse_unsupported(&reply))
+    }
+}
@@ -1,3 +1,5 @@
+use examples_common::eval::{eval_from_args, EvalCase};
+use examples_common::guard::LimitedExtractor;
 use rig::providers::openai;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -38,18 +40,67 @@ fn pretty_print_entities(extracted: &ExtractedEntities) {
     }
 }
 
+// True if an entity of the given type has a name containing `name`
+fn has_entity(extracted: &ExtractedEntities, name: &str, is_type: fn(&EntityType) -> bool) -> bool {
+    extracted
+        .entities
+        .iter()
+        .any(|entity| is_type(&entity.entity_type) && entity.name.contains(name))
+}
+
+// Expected extractions for `--eval`
+fn eval_cases() -> Vec<EvalCase<ExtractedEntities>> {
+    vec![
+        EvalCase::new("On July 20, 1969, Neil Armstrong and Buzz Aldrin, astronauts from NASA, \
+                       became the first humans to land on the Moon as part of the Apollo 11 mission.")
+            .expect("Neil Armstrong is a Person", |e: &ExtractedEntities| {
+                has_entity(e, "Neil Armstrong", |t| matches!(t, EntityType::Person))
+            })
+            .expect("NASA is an Organization", |e: &ExtractedEntities| {
+                has_entity(e, "NASA", |t| matches!(t, EntityType::Organization))
+            })
+            .expect("at least one Date", |e: &ExtractedEntities| {
+                e.entities.iter().any(|entity| matches!(entity.entity_type, EntityType::Date))
+            }),
+        EvalCase::new("Satya Nadella announced that Microsoft will open a new office in Nairobi.")
+            .expect("Microsoft is an Organization", |e: &ExtractedEntities| {
+                has_entity(e, "Microsoft", |t| matches!(t, EntityType::Organization))
+            })
+            .expect("Nairobi is a Location", |e: &ExtractedEntities| {
+                has_entity(e, "Nairobi", |t| matches!(t, EntityType::Location))
+            })
+            .expect("total_count matches the entity list", |e: &ExtractedEntities| {
+                e.total_count == e.entities.len()
+            })
+            .expect("every confidence is between 0 and 1", |e: &ExtractedEntities| {
+                e.entities.iter().all(|entity| (0.0..=1.0).contains(&entity.confidence))
+            }),
+    ]
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize the OpenAI client
     let openai_client = openai::Client::from_env();
 
     // Create the extractor
-    let extractor = openai_client
-        .extractor::<ExtractedEntities>("gpt-4")
-        .preamble("You are an AI assistant specialized in extracting named entities from text. \
-                   Your task is to identify and categorize entities such as persons, organizations, \
-                   locations, and dates. Provide a confidence score for each entity identified.")
-        .build();
+    let extractor = LimitedExtractor::<_, ExtractedEntities>::new(
+        openai_client.completion_model("gpt-4"),
+        "You are an AI assistant specialized in extracting named entities from text. \
+         Your task is to identify and categorize entities such as persons, organizations, \
+         locations, and dates. Provide a confidence score for each entity identified.",
+    );
+
+    // `--eval` checks the extractor against expected entities instead of running the sample
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let extractor = &extractor;
+    let evaluated = eval_from_args(&args, &eval_cases(), |text| async move {
+        extractor.extract(&text).await
+    })
+    .await?;
+    if evaluated {
+        return Ok(());
+    }
 
     // Sample text for entity extraction
     let sample_text = "On July 20, 1969, Neil Armstrong and Buzz Aldrin, astronauts from NASA, \
@@ -0,0 +1,26 @@
+// cli.rs
+//
+// Command-line options shared by the binaries that take arguments: the flight
+// assistant, rag_system and the RSS summarizer. Each binary flattens `CommonArgs`
+// into its own `clap` parser next to its specific options.
+
+use clap::Args;
+
+/// Options every binary accepts
+#[derive(Debug, Clone, Args)]
+pub struct CommonArgs {
+    /// Completion model to use, e.g. gpt-4o (each binary has its own default)
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Log filter such as `debug` or `info,rig=warn`; overrides RUST_LOG
+    #[arg(long)]
+    pub log_level: Option<String>,
+}
+
+impl CommonArgs {
+    /// The chosen model, or `default` if none was given
+    pub fn model_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.model.as_deref().unwrap_or(default)
+    }
+}
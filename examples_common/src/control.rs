@@ -0,0 +1,54 @@
+// control.rs
+//
+// Step-response metrics for the PID controller tuner examples.
+
+/// Fraction of the setpoint the response must stay within to count as settled
+const SETTLING_BAND: f64 = 0.02;
+
+/// Smallest band, so a response can still settle around a setpoint of 0
+const MIN_SETTLING_BAND: f64 = 1e-3;
+
+/// Time after which `response`, sampled every `dt`, stays within ±2% of `setpoint`
+/// (or ±[`MIN_SETTLING_BAND`] near 0): the end of the last sample outside the band.
+/// 0 if it never leaves the band, the full duration if it never settles.
+pub fn settling_time(response: &[f64], setpoint: f64, dt: f64) -> f64 {
+    let band = (SETTLING_BAND * setpoint.abs()).max(MIN_SETTLING_BAND);
+    match response.iter().rposition(|&value| (value - setpoint).abs() > band) {
+        Some(last_outside) => (last_outside + 1) as f64 * dt,
+        None => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settling_time_of_step_responses() {
+        let dt = 0.1;
+
+        // Overshoots to 1.05, then stays within 2% from the fifth sample on
+        let response = [0.0, 0.5, 0.9, 1.05, 0.99, 1.01, 1.0, 1.0];
+        assert!((settling_time(&response, 1.0, dt) - 0.4).abs() < 1e-9);
+
+        // The band scales with the setpoint: 9.85 is within 2% of 10, 0.975 isn't of 1
+        assert!((settling_time(&[5.0, 9.85, 10.1], 10.0, dt) - 0.1).abs() < 1e-9);
+        assert!((settling_time(&[0.5, 0.975, 1.01], 1.0, dt) - 0.2).abs() < 1e-9);
+
+        // A response that is still outside the band at the end never settled
+        let slow = [0.0, 0.3, 0.6, 0.8, 0.9];
+        assert!((settling_time(&slow, 1.0, dt) - slow.len() as f64 * dt).abs() < 1e-9);
+
+        // Already at the setpoint
+        assert_eq!(settling_time(&[1.0, 1.0], 1.0, dt), 0.0);
+    }
+
+    #[test]
+    fn test_settling_time_around_zero() {
+        let dt = 0.1;
+
+        // Decays towards 0 and stays within the minimum band from the third sample on
+        assert!((settling_time(&[0.5, -0.1, 0.0005, 0.0], 0.0, dt) - 0.2).abs() < 1e-9);
+        assert_eq!(settling_time(&[0.0, 0.0], 0.0, dt), 0.0);
+    }
+}
@@ -0,0 +1,258 @@
+// eval.rs
+//
+// Assertion-based checks for example outputs, so changes to prompts or models can be
+// verified against declared expectations. Used by the classification and entity
+// extraction examples.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::path::PathBuf;
+
+/// Where the outputs under evaluation come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalMode {
+    /// Call the model for every case
+    Live,
+    /// Call the model and save its outputs to a file for later replays
+    Record(PathBuf),
+    /// Read outputs saved by an earlier `Record` run instead of calling the model
+    Replay(PathBuf),
+}
+
+impl EvalMode {
+    /// Parse `--record <file>` or `--replay <file>` from the command line, `Live` otherwise
+    pub fn from_args(args: &[String]) -> Self {
+        let value_of = |flag: &str| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| args.get(i + 1))
+                .map(PathBuf::from)
+        };
+        if let Some(path) = value_of("--replay") {
+            EvalMode::Replay(path)
+        } else if let Some(path) = value_of("--record") {
+            EvalMode::Record(path)
+        } else {
+            EvalMode::Live
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EvalError {
+    #[error("failed to access recorded outputs: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid recorded outputs: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A named check on an output
+pub struct Expectation<T> {
+    description: String,
+    check: Box<dyn Fn(&T) -> bool>,
+}
+
+/// An input and everything its output is expected to satisfy
+pub struct EvalCase<T> {
+    pub input: String,
+    expectations: Vec<Expectation<T>>,
+}
+
+impl<T> EvalCase<T> {
+    pub fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+            expectations: Vec::new(),
+        }
+    }
+
+    /// Add an expectation, described the way it should read in the report
+    pub fn expect(mut self, description: impl Into<String>, check: impl Fn(&T) -> bool + 'static) -> Self {
+        self.expectations.push(Expectation {
+            description: description.into(),
+            check: Box::new(check),
+        });
+        self
+    }
+}
+
+/// Outcome of one case
+#[derive(Debug)]
+pub struct CaseResult {
+    pub input: String,
+    /// Descriptions of the expectations that did not hold
+    pub failures: Vec<String>,
+    /// Set if no output could be obtained at all
+    pub error: Option<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.failures.is_empty()
+    }
+}
+
+/// Pass/fail report for a whole run
+#[derive(Debug)]
+pub struct EvalReport {
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed()).count()
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.passed() == self.cases.len()
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            let status = if case.passed() { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {}", status, case.input)?;
+            if let Some(error) = &case.error {
+                writeln!(f, "    error: {}", error)?;
+            }
+            for failure in &case.failures {
+                writeln!(f, "    expected: {}", failure)?;
+            }
+        }
+        write!(f, "{}/{} cases passed", self.passed(), self.cases.len())
+    }
+}
+
+/// Run every case and check its output against the expectations.
+///
+/// `run` produces the output for an input; it is not called in `Replay` mode. Inputs
+/// missing from a replay file fail with an error rather than calling the model.
+pub async fn run_eval<T, E, F, Fut>(cases: &[EvalCase<T>], mode: &EvalMode, run: F) -> Result<EvalReport, EvalError>
+where
+    T: Serialize + DeserializeOwned,
+    E: fmt::Display,
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut recorded: BTreeMap<String, serde_json::Value> = match mode {
+        EvalMode::Replay(path) => serde_json::from_str(&tokio::fs::read_to_string(path).await?)?,
+        _ => BTreeMap::new(),
+    };
+
+    let mut results = Vec::new();
+    for case in cases {
+        let output = match mode {
+            EvalMode::Replay(_) => match recorded.get(&case.input) {
+                Some(value) => T::deserialize(value).map_err(|e| e.to_string()),
+                None => Err("no recorded output for this input".to_string()),
+            },
+            _ => run(case.input.clone()).await.map_err(|e| e.to_string()),
+        };
+
+        let result = match output {
+            Ok(output) => {
+                if let EvalMode::Record(_) = mode {
+                    recorded.insert(case.input.clone(), serde_json::to_value(&output)?);
+                }
+                CaseResult {
+                    input: case.input.clone(),
+                    failures: case
+                        .expectations
+                        .iter()
+                        .filter(|expectation| !(expectation.check)(&output))
+                        .map(|expectation| expectation.description.clone())
+                        .collect(),
+                    error: None,
+                }
+            }
+            Err(error) => CaseResult {
+                input: case.input.clone(),
+                failures: Vec::new(),
+                error: Some(error),
+            },
+        };
+        results.push(result);
+    }
+
+    if let EvalMode::Record(path) = mode {
+        tokio::fs::write(path, serde_json::to_string_pretty(&recorded)?).await?;
+    }
+
+    Ok(EvalReport { cases: results })
+}
+
+/// Handle `--eval` for an example binary.
+///
+/// Returns `false` straight away if `--eval` isn't among `args`. Otherwise runs `cases`
+/// in the mode `--record` or `--replay` selects, prints the report, and exits the
+/// process with status 1 if any case failed.
+pub async fn eval_from_args<T, E, F, Fut>(
+    args: &[String],
+    cases: &[EvalCase<T>],
+    run: F,
+) -> Result<bool, EvalError>
+where
+    T: Serialize + DeserializeOwned,
+    E: fmt::Display,
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    if !args.iter().any(|arg| arg == "--eval") {
+        return Ok(false);
+    }
+    let report = run_eval(cases, &EvalMode::from_args(args), run).await?;
+    println!("{}", report);
+    if !report.all_passed() {
+        std::process::exit(1);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("eval-test-{}.json", std::process::id()));
+        let cases = vec![
+            EvalCase::new("short").expect("length is at least 5", |len: &usize| *len >= 5),
+            EvalCase::new("longer input").expect("length is at least 5", |len: &usize| *len >= 5),
+        ];
+
+        let live = run_eval(&cases, &EvalMode::Record(path.clone()), |input| async move {
+            Ok::<_, std::io::Error>(input.len())
+        })
+        .await
+        .unwrap();
+        assert_eq!(live.passed(), 2);
+
+        // Replays never call the model, so a failing `run` doesn't matter
+        let replayed = run_eval(&cases, &EvalMode::Replay(path.clone()), |_| async {
+            Err::<usize, _>("offline")
+        })
+        .await
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(replayed.all_passed());
+
+        let failing = run_eval(&cases, &EvalMode::Live, |_| async { Ok::<usize, String>(1) })
+            .await
+            .unwrap();
+        assert_eq!(failing.passed(), 0);
+        assert!(failing.to_string().contains("expected: length is at least 5"));
+    }
+
+    #[tokio::test]
+    async fn test_eval_only_runs_when_requested() {
+        let cases = vec![EvalCase::new("input").expect("is 1", |n: &usize| *n == 1)];
+        let run = |_| async { Ok::<usize, String>(1) };
+
+        assert!(!eval_from_args(&["--record".to_string()], &cases, run).await.unwrap());
+        assert!(eval_from_args(&["--eval".to_string()], &cases, run).await.unwrap());
+    }
+}
@@ -0,0 +1,228 @@
+// guard.rs
+//
+// Extraction with a cap on how much the model may generate. Rig's `Extractor` can't
+// limit its output, so `LimitedExtractor` builds the same kind of agent (a preamble
+// and a `submit` tool taking the target type) with `max_tokens` set on the request.
+// The provider stops generating at the cap, so an oversized answer is never produced
+// or buffered in full. Used by the classification, entity extraction and RSS
+// summarizer examples.
+
+use rig::agent::AgentBuilder;
+use rig::completion::{Chat, CompletionError, CompletionModel, PromptError, ToolDefinition};
+use rig::tool::Tool;
+use schemars::{schema_for, JsonSchema};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+/// Default cap on the model's output, in tokens
+pub const DEFAULT_MAX_OUTPUT_TOKENS: u64 = 4096;
+
+/// Instructions every extraction starts with, before the caller's preamble
+const EXTRACTION_PREAMBLE: &str = "You are an AI assistant whose purpose is to extract \
+    structured data from the provided text. You will have access to a `submit` function \
+    that defines the structure of the data to extract. Always call the `submit` function \
+    with the structured data, filling out every field.";
+
+/// Error from [`LimitedExtractor`]
+#[derive(Debug, thiserror::Error)]
+pub enum GuardedExtractError {
+    /// The output reached the token cap and was cut off before it was complete
+    #[error("extractor output exceeds the limit of {max_tokens} tokens")]
+    TooLarge { max_tokens: u64 },
+    /// The request to the model failed
+    #[error("{0}")]
+    Prompt(PromptError),
+    /// The model answered with something that isn't the requested type
+    #[error("extractor output is not valid: {0}")]
+    InvalidOutput(serde_json::Error),
+}
+
+/// The tool the model calls with the extracted data. Its arguments are passed through
+/// as they are and parsed into the target type afterwards.
+struct SubmitTool {
+    parameters: serde_json::Value,
+}
+
+impl Tool for SubmitTool {
+    const NAME: &'static str = "submit";
+
+    type Error = Infallible;
+    type Args = serde_json::Value;
+    type Output = serde_json::Value;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Submit the structured data you extracted from the provided text."
+                .to_string(),
+            parameters: self.parameters.clone(),
+        }
+    }
+
+    async fn call(&self, data: Self::Args) -> Result<Self::Output, Self::Error> {
+        Ok(data)
+    }
+}
+
+/// Extracts a `T` from text like rig's `Extractor`, but never lets the model generate
+/// more than a set number of tokens.
+///
+/// ```ignore
+/// let classifier = LimitedExtractor::<_, ClassificationResult>::new(
+///     openai_client.completion_model("gpt-4"),
+///     "Classify the text into one of these categories: ...",
+/// );
+/// let result = classifier.extract(text).await?;
+/// // Or with a different cap for one call
+/// let result = classifier.extract_with_limit(long_text, 16_384).await?;
+/// ```
+pub struct LimitedExtractor<M, T> {
+    model: M,
+    preamble: String,
+    max_tokens: u64,
+    _output: PhantomData<fn() -> T>,
+}
+
+impl<M, T> LimitedExtractor<M, T>
+where
+    M: CompletionModel,
+    T: JsonSchema + DeserializeOwned,
+{
+    /// An extractor using `model`, with extra instructions in `preamble` and the
+    /// default cap of [`DEFAULT_MAX_OUTPUT_TOKENS`]
+    pub fn new(model: M, preamble: &str) -> Self {
+        Self {
+            model,
+            preamble: preamble.to_string(),
+            max_tokens: DEFAULT_MAX_OUTPUT_TOKENS,
+            _output: PhantomData,
+        }
+    }
+
+    /// Change the cap used by [`extract`](Self::extract)
+    pub fn max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Extract a `T` from `text`, capped at this extractor's token limit
+    pub async fn extract(&self, text: &str) -> Result<T, GuardedExtractError> {
+        self.extract_with_limit(text, self.max_tokens).await
+    }
+
+    /// Extract a `T` from `text`, letting the model generate at most `max_tokens`
+    pub async fn extract_with_limit(
+        &self,
+        text: &str,
+        max_tokens: u64,
+    ) -> Result<T, GuardedExtractError> {
+        let agent = AgentBuilder::new(self.model.clone())
+            .preamble(&format!("{}\n\n{}", EXTRACTION_PREAMBLE, self.preamble))
+            .tool(SubmitTool {
+                parameters: serde_json::to_value(schema_for!(T))
+                    .map_err(GuardedExtractError::InvalidOutput)?,
+            })
+            .additional_params(json!({ "max_tokens": max_tokens }))
+            .build();
+
+        let output = match agent.chat(text, vec![]).await {
+            Ok(output) => output,
+            // Arguments cut off at the cap don't parse as JSON
+            Err(PromptError::CompletionError(CompletionError::JsonError(e))) if e.is_eof() => {
+                return Err(GuardedExtractError::TooLarge { max_tokens })
+            }
+            Err(e) => return Err(GuardedExtractError::Prompt(e)),
+        };
+        parse_output(&output, max_tokens)
+    }
+}
+
+/// Parse what the model submitted, reporting JSON that stops early as cut off at the cap
+fn parse_output<T: DeserializeOwned>(
+    output: &str,
+    max_tokens: u64,
+) -> Result<T, GuardedExtractError> {
+    serde_json::from_str(output).map_err(|e| {
+        if e.is_eof() {
+            GuardedExtractError::TooLarge { max_tokens }
+        } else {
+            GuardedExtractError::InvalidOutput(e)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::{CompletionRequest, CompletionResponse, ModelChoice};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+    struct Label {
+        label: String,
+    }
+
+    /// Answers every request by calling `submit` with `arguments`, and checks the
+    /// request carries the token cap
+    #[derive(Clone)]
+    struct SubmittingModel {
+        arguments: serde_json::Value,
+        expected_max_tokens: u64,
+    }
+
+    impl CompletionModel for SubmittingModel {
+        type Response = ();
+
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<()>, CompletionError> {
+            assert_eq!(
+                request.additional_params,
+                Some(json!({ "max_tokens": self.expected_max_tokens }))
+            );
+            Ok(CompletionResponse {
+                choice: ModelChoice::ToolCall("submit".to_string(), self.arguments.clone()),
+                raw_response: (),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_tokens_is_sent_with_each_request() {
+        let model = SubmittingModel {
+            arguments: json!({ "label": "Technology" }),
+            expected_max_tokens: 256,
+        };
+        let extractor =
+            LimitedExtractor::<_, Label>::new(model.clone(), "Label the text.").max_tokens(256);
+        assert_eq!(
+            extractor.extract("M2 chips").await.unwrap().label,
+            "Technology"
+        );
+
+        // A per-call limit overrides the extractor's own
+        let extractor = LimitedExtractor::<_, Label>::new(
+            SubmittingModel {
+                expected_max_tokens: 32,
+                ..model
+            },
+            "Label the text.",
+        );
+        assert!(extractor.extract_with_limit("M2 chips", 32).await.is_ok());
+    }
+
+    #[test]
+    fn test_cut_off_output_is_too_large() {
+        assert!(matches!(
+            parse_output::<Label>(r#"{"label": "Technol"#, 8),
+            Err(GuardedExtractError::TooLarge { max_tokens: 8 })
+        ));
+        assert!(matches!(
+            parse_output::<Label>(r#"{"name": "Technology"}"#, 8),
+            Err(GuardedExtractError::InvalidOutput(_))
+        ));
+    }
+}
@@ -0,0 +1,27 @@
+//! Helpers shared by the example binaries in this repository. Each one sits behind a
+//! feature, so an example only pulls in the dependencies of the helpers it uses:
+//!
+//! ```toml
+//! examples_common = { path = "../examples_common", features = ["guard"] }
+//! ```
+
+#[cfg(feature = "cli")]
+pub mod cli;
+
+#[cfg(feature = "control")]
+pub mod control;
+
+#[cfg(feature = "eval")]
+pub mod eval;
+
+#[cfg(feature = "guard")]
+pub mod guard;
+
+#[cfg(feature = "logging")]
+pub mod logging;
+
+#[cfg(feature = "openai")]
+pub mod openai;
+
+#[cfg(feature = "shutdown")]
+pub mod shutdown;
@@ -0,0 +1,43 @@
+// logging.rs
+//
+// Tracing setup for the example binaries: a level filter taken from a flag such as
+// `--log-level` or from `RUST_LOG`, with logs written to stderr or to a file.
+
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+
+/// Filter used when neither a filter nor `RUST_LOG` is given
+const DEFAULT_FILTER: &str = "info";
+
+/// Install the global tracing subscriber.
+///
+/// Levels come from `filter` if given (e.g. a `--log-level` flag), otherwise from
+/// `RUST_LOG` (e.g. `debug` or `info,rig=warn`), and default to `info`. An invalid
+/// filter falls back to the default. Logs go to stderr, keeping stdout for the
+/// program's output, unless `RIG_LOG_FILE` names a file to append them to instead.
+/// If that file can't be opened, logging falls back to stderr with a warning.
+pub fn init_tracing(filter: Option<&str>) {
+    let filter = match filter {
+        Some(filter) => EnvFilter::try_new(filter).ok(),
+        None => EnvFilter::try_from_default_env().ok(),
+    }
+    .unwrap_or_else(|| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let path = match std::env::var("RIG_LOG_FILE") {
+        Ok(path) if !path.trim().is_empty() => path,
+        _ => {
+            builder.with_writer(std::io::stderr).init();
+            return;
+        }
+    };
+
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(file) => builder.with_ansi(false).with_writer(Mutex::new(file)).init(),
+        Err(e) => {
+            builder.with_writer(std::io::stderr).init();
+            tracing::warn!("Could not open log file {}: {}, logging to stderr", path, e);
+        }
+    }
+}
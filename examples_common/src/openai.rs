@@ -0,0 +1,71 @@
+// openai.rs
+//
+// OpenAI client setup that can point at any OpenAI-compatible server. The examples are
+// on different rig-core versions, so the client's constructors are passed in rather
+// than named here.
+
+/// Build an OpenAI client from the environment, honoring `OPENAI_BASE_URL`.
+///
+/// When `OPENAI_BASE_URL` is unset this calls `from_env`, i.e. the same as
+/// `openai::Client::from_env()`. When it is set, requests go to that server instead (a
+/// LiteLLM proxy, Ollama, llama.cpp, ...). The URL is the server root without `/v1`,
+/// e.g. `http://localhost:11434`. `OPENAI_API_KEY` is optional in that case since most
+/// local servers ignore it.
+///
+/// ```ignore
+/// use rig::providers::openai;
+///
+/// let client = openai_client_from_env(openai::Client::from_env, openai::Client::from_url);
+/// ```
+pub fn openai_client_from_env<C>(
+    from_env: impl FnOnce() -> C,
+    from_url: impl FnOnce(&str, &str) -> C,
+) -> C {
+    client_for(
+        std::env::var("OPENAI_BASE_URL").ok(),
+        std::env::var("OPENAI_API_KEY").ok(),
+        from_env,
+        from_url,
+    )
+}
+
+fn client_for<C>(
+    base_url: Option<String>,
+    api_key: Option<String>,
+    from_env: impl FnOnce() -> C,
+    from_url: impl FnOnce(&str, &str) -> C,
+) -> C {
+    match base_url {
+        Some(base_url) if !base_url.trim().is_empty() => {
+            from_url(&api_key.unwrap_or_default(), base_url.trim_end_matches('/'))
+        }
+        _ => from_env(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_url_overrides_the_default_client() {
+        let from_env = || "default".to_string();
+        let from_url = |key: &str, url: &str| format!("{} at {}", key, url);
+
+        assert_eq!(client_for(None, None, from_env, from_url), "default");
+        assert_eq!(client_for(Some(" ".to_string()), None, from_env, from_url), "default");
+        assert_eq!(
+            client_for(Some("http://localhost:11434/".to_string()), None, from_env, from_url),
+            " at http://localhost:11434"
+        );
+        assert_eq!(
+            client_for(
+                Some("http://proxy:4000".to_string()),
+                Some("sk-test".to_string()),
+                from_env,
+                from_url
+            ),
+            "sk-test at http://proxy:4000"
+        );
+    }
+}
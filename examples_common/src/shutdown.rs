@@ -0,0 +1,36 @@
+// shutdown.rs
+//
+// Clean exits for the interactive and long-running examples: rag_system, the RSS
+// summarizer and agent_state_machine, which re-exports both helpers.
+
+use std::io::BufRead;
+use tokio::sync::mpsc;
+use tracing::error;
+
+/// Resolves once the user presses Ctrl-C.
+///
+/// `select!` this against a long-running loop so the program can flush its output and
+/// exit cleanly instead of being killed mid-write. If the signal handler can't be
+/// installed, the future never resolves rather than shutting down immediately.
+pub async fn shutdown_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Lines typed on stdin, read on a plain thread so a pending read never keeps the
+/// runtime from exiting.
+///
+/// The channel closes at the end of input.
+pub fn stdin_lines() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
@@ -1,26 +1,48 @@
+use crate::provider::{FlightProvider, TripAdvisorProvider};
+use crate::ranking::{ranker_for, FlightRanker};
 use chrono::Utc;
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct FlightSearchArgs {
-    source: String,
-    destination: String,
-    date: Option<String>,
-    sort: Option<String>,
-    service: Option<String>,
-    itinerary_type: Option<String>,
-    adults: Option<u8>,
-    seniors: Option<u8>,
-    currency: Option<String>,
-    nearby: Option<String>,
-    nonstop: Option<String>,
+    pub source: String,
+    pub destination: String,
+    pub date: Option<String>,
+    pub sort: Option<String>,
+    pub service: Option<String>,
+    pub itinerary_type: Option<String>,
+    pub adults: Option<u8>,
+    pub seniors: Option<u8>,
+    pub children: Option<u8>,
+    pub infants: Option<u8>,
+    pub currency: Option<String>,
+    pub nearby: Option<String>,
+    pub nonstop: Option<String>,
+    pub rank_by: Option<String>,
+    pub max_results: Option<usize>,
 }
 
+/// How many options are returned when `max_results` is not given
+pub const DEFAULT_MAX_RESULTS: usize = 5;
+/// The most options returned, whatever `max_results` asks for
+pub const MAX_RESULTS_CAP: usize = 25;
+/// Most passengers one search may book for, as airlines allow
+pub const MAX_PASSENGERS: u8 = 9;
+/// How many times a transient API failure is retried by default, for 3 attempts in all
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+/// Delay before the first retry; it doubles on each one after
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Longest wait between attempts, even if the API's `Retry-After` asks for more
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// How long cached results are reused, see [`FlightSearchTool::with_cache`]
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Debug, thiserror::Error)]
 pub enum FlightSearchError {
     #[error("HTTP request failed: {0}")]
@@ -29,30 +51,447 @@ pub enum FlightSearchError {
     InvalidResponse,
     #[error("API error: {0}")]
     ApiError(String),
+    /// The search was rejected before any request was made
+    #[error("Invalid arguments: {0}")]
+    InvalidArgs(String),
+    /// A rate limit (429) or server error (5xx) that may pass if the search is retried,
+    /// with the delay the API asked for in `Retry-After`, if any
+    #[error("API returned {status}, try again later")]
+    Transient {
+        status: u16,
+        retry_after: Option<Duration>,
+    },
     #[error("Missing API key")]
     MissingApiKey,
 }
 
-#[derive(Serialize)]
+/// A connection between two legs of the same segment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Layover {
+    /// Airport code where the connection happens
+    pub airport: String,
+    /// When the inbound leg lands, as given by the API
+    pub arrival: String,
+    /// When the outbound leg leaves, as given by the API
+    pub departure: String,
+    /// Time on the ground, e.g. "2h15m"
+    pub duration: String,
+    pub duration_minutes: i64,
+    /// Whether the departure is on a later (local) day than the arrival
+    pub overnight: bool,
+}
+
+/// Who is travelling, with the defaults applied: one adult and nobody else
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Passengers {
+    pub adults: u8,
+    pub seniors: u8,
+    pub children: u8,
+    pub infants: u8,
+}
+
+impl Passengers {
+    /// Read the passenger counts from `args`, rejecting more than [`MAX_PASSENGERS`]
+    /// in all or more infants than adults and seniors to travel with them
+    pub fn from_args(args: &FlightSearchArgs) -> Result<Self, FlightSearchError> {
+        let passengers = Self {
+            adults: args.adults.unwrap_or(1),
+            seniors: args.seniors.unwrap_or(0),
+            children: args.children.unwrap_or(0),
+            infants: args.infants.unwrap_or(0),
+        };
+        let total = passengers.adults as u32
+            + passengers.seniors as u32
+            + passengers.children as u32
+            + passengers.infants as u32;
+        if total > MAX_PASSENGERS as u32 {
+            return Err(FlightSearchError::InvalidArgs(format!(
+                "too many passengers: {} (at most {})",
+                total, MAX_PASSENGERS
+            )));
+        }
+        if passengers.infants as u32 > passengers.adults as u32 + passengers.seniors as u32 {
+            return Err(FlightSearchError::InvalidArgs(format!(
+                "each infant needs an adult or senior, got {} infants",
+                passengers.infants
+            )));
+        }
+        Ok(passengers)
+    }
+}
+
+impl std::fmt::Display for Passengers {
+    /// E.g. "2 adults, 1 child"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counts = [
+            (self.adults, "adult", "adults"),
+            (self.seniors, "senior", "seniors"),
+            (self.children, "child", "children"),
+            (self.infants, "infant", "infants"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _, _)| *count > 0)
+            .map(|(count, one, many)| format!("{} {}", count, if *count == 1 { one } else { many }))
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// One flight found by the search, as returned to the agent in JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlightOption {
-    airline: String,
-    flight_number: String,
-    departure: String,
-    arrival: String,
-    duration: String,
-    stops: usize,
-    price: f64,
-    currency: String,
-    booking_url: String,
+    pub airline: String,
+    pub flight_number: String,
+    pub departure: String,
+    pub arrival: String,
+    pub duration: String,
+    pub duration_minutes: i64,
+    pub stops: usize,
+    pub layovers: Vec<Layover>,
+    pub price: f64,
+    pub currency: String,
+    pub booking_url: String,
+}
+
+/// Searches flights through a [`FlightProvider`], then ranks and limits the results.
+///
+/// Transient failures (HTTP 429 and 5xx) are retried with exponential backoff, or
+/// after the API's `Retry-After` delay when it sends one; other errors fail at once.
+pub struct FlightSearchTool {
+    provider: Box<dyn FlightProvider>,
+    max_retries: u32,
+    retry_delay: Duration,
+    cache: Option<SearchCache>,
+}
+
+/// Provider results by [`cache_key`], with when they were fetched
+struct SearchCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Vec<FlightOption>)>>,
+}
+
+/// The search parameters sent to the provider, in a fixed (sorted) order. Ranking
+/// and `max_results` are left out since they are applied to the cached results.
+fn cache_key(args: &FlightSearchArgs) -> String {
+    let value = serde_json::to_value(args).unwrap_or_default();
+    let params: BTreeMap<&String, &Value> = value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| !matches!(name.as_str(), "rank_by" | "max_results"))
+        .collect();
+    serde_json::to_string(&params).unwrap_or_default()
+}
+
+impl FlightSearchTool {
+    pub fn new(provider: impl FlightProvider + 'static) -> Self {
+        Self {
+            provider: Box::new(provider),
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            cache: None,
+        }
+    }
+
+    /// Reuse the results of an identical search made less than `ttl` ago instead of
+    /// calling the provider again, e.g. [`DEFAULT_CACHE_TTL`]. Only successful
+    /// searches are cached, in memory.
+    pub fn with_cache(mut self, ttl: Duration) -> Self {
+        self.cache = Some(SearchCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// Search through the cache if there is one, otherwise straight to the provider
+    async fn search_cached(
+        &self,
+        args: &FlightSearchArgs,
+    ) -> Result<Vec<FlightOption>, FlightSearchError> {
+        let Some(cache) = &self.cache else {
+            return self.search_with_retry(args).await;
+        };
+
+        let key = cache_key(args);
+        {
+            let mut entries = cache.entries.lock().unwrap();
+            entries.retain(|_, (fetched, _)| fetched.elapsed() < cache.ttl);
+            if let Some((_, options)) = entries.get(&key) {
+                tracing::debug!("Using cached flight results for {}", key);
+                return Ok(options.clone());
+            }
+        }
+
+        let options = self.search_with_retry(args).await?;
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), options.clone()));
+        Ok(options)
+    }
+
+    /// Retry a transient failure up to `max_retries` times; 0 disables retries
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Search, retrying transient failures until `max_retries` is used up
+    async fn search_with_retry(
+        &self,
+        args: &FlightSearchArgs,
+    ) -> Result<Vec<FlightOption>, FlightSearchError> {
+        let mut attempt = 0;
+        loop {
+            match self.provider.search(args).await {
+                Err(FlightSearchError::Transient {
+                    status,
+                    retry_after,
+                }) if attempt < self.max_retries => {
+                    let backoff = self.retry_delay.saturating_mul(2u32.saturating_pow(attempt));
+                    let delay = retry_after.unwrap_or(backoff).min(MAX_RETRY_DELAY);
+                    attempt += 1;
+                    tracing::warn!(
+                        "Flight API returned {}, retry {}/{} in {:?}",
+                        status,
+                        attempt,
+                        self.max_retries,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for FlightSearchTool {
+    /// Search the TripAdvisor API on RapidAPI
+    fn default() -> Self {
+        Self::new(TripAdvisorProvider::new())
+    }
+}
+
+/// Parse an RFC 3339 timestamp, falling back to the current UTC time
+fn parse_time(value: &str) -> chrono::DateTime<Utc> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| chrono::Utc::now())
+}
+
+fn leg_str<'a>(leg: &'a Value, key: &str) -> &'a str {
+    leg.get(key).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+/// Format minutes compactly, e.g. 135 as "2h15m"
+fn format_minutes(minutes: i64) -> String {
+    format!("{}h{:02}m", minutes / 60, minutes % 60)
+}
+
+/// Layovers between consecutive legs of one segment.
+///
+/// Durations come from the inbound leg's arrival and the outbound leg's departure, so
+/// layovers past midnight are measured correctly. Connections with unparseable times
+/// are skipped.
+fn parse_layovers(legs: &[Value]) -> Vec<Layover> {
+    legs.windows(2)
+        .filter_map(|pair| {
+            let (inbound, outbound) = (&pair[0], &pair[1]);
+            let arrival = leg_str(inbound, "arrivalDateTime");
+            let departure = leg_str(outbound, "departureDateTime");
+            let arrived = chrono::DateTime::parse_from_rfc3339(arrival).ok()?;
+            let departs = chrono::DateTime::parse_from_rfc3339(departure).ok()?;
+
+            let airport = match leg_str(inbound, "destinationStationCode") {
+                "" => leg_str(outbound, "originStationCode"),
+                code => code,
+            };
+            let duration_minutes = (departs - arrived).num_minutes();
+
+            Some(Layover {
+                airport: airport.to_string(),
+                arrival: arrival.to_string(),
+                departure: departure.to_string(),
+                duration: format_minutes(duration_minutes),
+                duration_minutes,
+                overnight: departs.date_naive() > arrived.date_naive(),
+            })
+        })
+        .collect()
+}
+
+/// Extract flight options from the `data.flights` array of an API response
+pub fn parse_flights(data: &Value, currency: &str) -> Result<Vec<FlightOption>, FlightSearchError> {
+    let flights = data
+        .get("data")
+        .and_then(|d| d.get("flights"))
+        .and_then(|f| f.as_array())
+        .ok_or(FlightSearchError::InvalidResponse)?;
+
+    Ok(flights
+        .iter()
+        .filter_map(|flight| parse_flight_option(flight, currency))
+        .collect())
 }
 
-pub struct FlightSearchTool;
+/// Build a `FlightOption` from one flight entry.
+///
+/// An itinerary can be split into several segments (e.g. outbound and return), each
+/// made of one or more legs. Stops and travel time are summed over every segment, so
+/// time spent at the destination between segments is not counted as travel.
+/// Returns `None` for entries without legs or without a usable price.
+fn parse_flight_option(flight: &Value, currency: &str) -> Option<FlightOption> {
+    let segments: Vec<&Vec<Value>> = flight
+        .get("segments")
+        .and_then(|s| s.as_array())?
+        .iter()
+        .filter_map(|segment| segment.get("legs").and_then(|l| l.as_array()))
+        .filter(|legs| !legs.is_empty())
+        .collect();
+
+    let first_leg = segments.first()?.first()?;
+    let last_leg = segments.last()?.last()?;
+
+    // Extract airline name
+    let airline = first_leg
+        .get("marketingCarrier")
+        .and_then(|mc| mc.get("displayName"))
+        .and_then(|dn| dn.as_str())
+        .unwrap_or("Unknown")
+        .to_string();
+
+    // Extract flight number
+    let flight_number = format!(
+        "{}{}",
+        leg_str(first_leg, "marketingCarrierCode"),
+        leg_str(first_leg, "flightNumber")
+    );
+
+    // Overall departure and arrival times
+    let departure = leg_str(first_leg, "departureDateTime").to_string();
+    let arrival = leg_str(last_leg, "arrivalDateTime").to_string();
+
+    // Each segment contributes its own stops and in-air plus layover time
+    let mut stops = 0;
+    let mut layovers = Vec::new();
+    let mut duration = chrono::Duration::zero();
+    for legs in &segments {
+        stops += legs.len() - 1;
+        layovers.extend(parse_layovers(legs));
+        let segment_departure = parse_time(leg_str(&legs[0], "departureDateTime"));
+        let segment_arrival = parse_time(leg_str(&legs[legs.len() - 1], "arrivalDateTime"));
+        duration += segment_arrival - segment_departure;
+    }
+    let hours = duration.num_hours();
+    let minutes = duration.num_minutes() % 60;
+    let duration_str = format!("{} hours {} minutes", hours, minutes);
+
+    // Extract purchase links array for price information
+    let purchase_links = flight
+        .get("purchaseLinks")
+        .and_then(|pl| pl.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or(&[]);
+
+    // Find the best price from purchase links
+    let best_price = purchase_links.iter().min_by_key(|p| {
+        p.get("totalPrice")
+            .and_then(|tp| tp.as_f64())
+            .unwrap_or(f64::MAX) as u64
+    })?;
+
+    let total_price = best_price
+        .get("totalPrice")
+        .and_then(|tp| tp.as_f64())
+        .unwrap_or(0.0);
+    let booking_url = best_price
+        .get("url")
+        .and_then(|u| u.as_str())
+        .unwrap_or("")
+        .to_string();
+
+    // Skip flights with price 0.0
+    if total_price == 0.0 {
+        return None;
+    }
+
+    Some(FlightOption {
+        airline,
+        flight_number,
+        departure,
+        arrival,
+        duration: duration_str,
+        duration_minutes: duration.num_minutes(),
+        stops,
+        layovers,
+        price: total_price,
+        currency: currency.to_string(),
+        booking_url,
+    })
+}
+
+/// Main airports of common cities, so a city name the model passes still resolves
+const CITY_AIRPORTS: &[(&str, &str)] = &[
+    ("london", "LHR"),
+    ("new york", "JFK"),
+    ("paris", "CDG"),
+    ("tokyo", "HND"),
+    ("los angeles", "LAX"),
+    ("chicago", "ORD"),
+    ("san francisco", "SFO"),
+    ("san antonio", "SAT"),
+    ("dubai", "DXB"),
+    ("singapore", "SIN"),
+    ("mumbai", "BOM"),
+    ("delhi", "DEL"),
+    ("frankfurt", "FRA"),
+    ("amsterdam", "AMS"),
+    ("sydney", "SYD"),
+];
+
+/// Turn `input` into an uppercase 3-letter IATA code, looking up common city names.
+/// Anything else is rejected so the agent can correct itself instead of the API
+/// answering with unrelated flights.
+pub fn normalize_airport_code(input: &str) -> Result<String, FlightSearchError> {
+    let trimmed = input.trim();
+    if trimmed.len() == 3 && trimmed.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Ok(trimmed.to_ascii_uppercase());
+    }
+    let city = trimmed.to_lowercase();
+    CITY_AIRPORTS
+        .iter()
+        .find(|(name, _)| *name == city)
+        .map(|(_, code)| code.to_string())
+        .ok_or_else(|| FlightSearchError::ApiError(format!("invalid airport code: {}", input)))
+}
+
+/// Rank the options and cut them down to `max_results`, clamped to
+/// `1..=MAX_RESULTS_CAP` so an out-of-range request still gets an answer
+pub fn limit_flights(
+    mut flight_options: Vec<FlightOption>,
+    ranker: Option<&dyn FlightRanker>,
+    max_results: Option<usize>,
+) -> Vec<FlightOption> {
+    // Rank with the requested strategy, otherwise keep the provider's order
+    if let Some(ranker) = ranker {
+        ranker.rank(&mut flight_options);
+    }
+    let limit = max_results
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .clamp(1, MAX_RESULTS_CAP);
+    flight_options.truncate(limit);
+    flight_options
+}
 
 impl Tool for FlightSearchTool {
     const NAME: &'static str = "search_flights";
 
     type Args = FlightSearchArgs;
-    type Output = String; 
+    type Output = Vec<FlightOption>;
     type Error = FlightSearchError;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
@@ -62,273 +501,544 @@ impl Tool for FlightSearchTool {
             parameters: json!({
                 "type": "object",
                 "properties": {
-                    "source": { "type": "string", "description": "Source airport code (e.g., 'BOM')" },
-                    "destination": { "type": "string", "description": "Destination airport code (e.g., 'DEL')" },
+                    "source": { "type": "string", "description": "3-letter IATA code of the source airport (e.g., 'BOM')" },
+                    "destination": { "type": "string", "description": "3-letter IATA code of the destination airport (e.g., 'DEL')" },
                     "date": { "type": "string", "description": "Flight date in 'YYYY-MM-DD' format" },
                     "sort": { "type": "string", "description": "Sort order for results", "enum": ["ML_BEST_VALUE", "PRICE", "DURATION", "EARLIEST_OUTBOUND_DEPARTURE", "EARLIEST_OUTBOUND_ARRIVAL", "LATEST_OUTBOUND_DEPARTURE", "LATEST_OUTBOUND_ARRIVAL"] },
                     "service": { "type": "string", "description": "Class of service", "enum": ["ECONOMY", "PREMIUM_ECONOMY", "BUSINESS", "FIRST"] },
                     "itinerary_type": { "type": "string", "description": "Itinerary type", "enum": ["ONE_WAY", "ROUND_TRIP"] },
                     "adults": { "type": "integer", "description": "Number of adults" },
                     "seniors": { "type": "integer", "description": "Number of seniors" },
+                    "children": { "type": "integer", "description": "Number of children (default 0)" },
+                    "infants": { "type": "integer", "description": "Number of infants with their own seat (default 0); at most one per adult or senior. All passengers together may be at most 9." },
                     "currency": { "type": "string", "description": "Currency code (e.g., 'USD')" },
                     "nearby": { "type": "string", "description": "Include nearby airports", "enum": ["yes", "no"] },
                     "nonstop": { "type": "string", "description": "Show only nonstop flights", "enum": ["yes", "no"] },
+                    "rank_by": { "type": "string", "description": "How to pick the best flights: cheapest (price, then duration), fastest (duration, then stops and price) or balanced (weighted price, duration and stops). Omit to keep the API's sort order.", "enum": ["cheapest", "fastest", "balanced"] },
+                    "max_results": { "type": "integer", "description": "How many flight options to return (default 5, at most 25)", "minimum": 1, "maximum": 25 },
                 },
                 "required": ["source", "destination"]
             }),
         }
     }
 
-    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        // Use the RapidAPI key from an environment variable
-        let api_key = env::var("RAPIDAPI_KEY").map_err(|_| FlightSearchError::MissingApiKey)?;
+    async fn call(&self, mut args: Self::Args) -> Result<Self::Output, Self::Error> {
+        args.source = normalize_airport_code(&args.source)?;
+        args.destination = normalize_airport_code(&args.destination)?;
+        Passengers::from_args(&args)?;
 
-        // Set default values if not provided
-        let date = args.date.unwrap_or_else(|| {
-            let date = chrono::Utc::now() + chrono::Duration::days(30);
-            date.format("%Y-%m-%d").to_string()
-        });
+        let ranker = match args.rank_by.as_deref() {
+            Some(name) => Some(ranker_for(name).ok_or_else(|| {
+                FlightSearchError::InvalidArgs(format!("unknown rank_by value: {}", name))
+            })?),
+            None => None,
+        };
 
-        let sort = args.sort.unwrap_or_else(|| "ML_BEST_VALUE".to_string());
-        let service = args.service.unwrap_or_else(|| "ECONOMY".to_string());
-        let itinerary_type = args.itinerary_type.unwrap_or_else(|| "ONE_WAY".to_string());
-        let adults = args.adults.unwrap_or(1);
-        let seniors = args.seniors.unwrap_or(0);
-        let currency = args.currency.unwrap_or_else(|| "USD".to_string());
-        let nearby = args.nearby.unwrap_or_else(|| "no".to_string());
-        let nonstop = args.nonstop.unwrap_or_else(|| "no".to_string());
-
-        // Build the query parameters
-        let mut query_params = HashMap::new();
-        query_params.insert("sourceAirportCode", args.source);
-        query_params.insert("destinationAirportCode", args.destination);
-        query_params.insert("date", date);
-        query_params.insert("itineraryType", itinerary_type);
-        query_params.insert("sortOrder", sort);
-        query_params.insert("numAdults", adults.to_string());
-        query_params.insert("numSeniors", seniors.to_string());
-        query_params.insert("classOfService", service);
-        query_params.insert("pageNumber", "1".to_string());
-        query_params.insert("currencyCode", currency.clone());
-        query_params.insert("nearby", nearby);
-        query_params.insert("nonstop", nonstop);
-
-        // Make the API request
-        let client = reqwest::Client::new();
-        let response = client
-            .get("https://tripadvisor16.p.rapidapi.com/api/v1/flights/searchFlights")
-            .headers({
-                let mut headers = reqwest::header::HeaderMap::new();
-                headers.insert(
-                    "X-RapidAPI-Host",
-                    "tripadvisor16.p.rapidapi.com".parse().unwrap(),
-                );
-                headers.insert("X-RapidAPI-Key", api_key.parse().unwrap());
-                headers
-            })
-            .query(&query_params)
-            .send()
-            .await
-            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+        let flight_options = self.search_cached(&args).await?;
+        Ok(limit_flights(flight_options, ranker.as_deref(), args.max_results))
+    }
+}
 
-        // Get the status code before consuming `response`
-        let status = response.status();
+/// Format flight options as a numbered markdown list for showing to a user, with
+/// the passengers the prices are for in the header
+pub fn format_flights(flight_options: &[FlightOption], passengers: &Passengers) -> String {
+    if flight_options.is_empty() {
+        return "No flights found for the given criteria.".to_string();
+    }
 
-        // Read the response text (this consumes `response`)
-        let text = response
-            .text()
-            .await
-            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+    let mut output = String::new();
+    output.push_str(&format!(
+        "Here are some flight options for {}:\n\n",
+        passengers
+    ));
 
-        // Print the raw API response for debugging
-        // println!("Raw API response:\n{}", text);
+    // Iterate over each flight option and format the details
+    for (i, option) in flight_options.iter().enumerate() {
+        output.push_str(&format!("{}. **Airline**: {}\n", i + 1, option.airline));
+        output.push_str(&format!(
+            "   - **Flight Number**: {}\n",
+            option.flight_number
+        ));
+        output.push_str(&format!("   - **Departure**: {}\n", option.departure));
+        output.push_str(&format!("   - **Arrival**: {}\n", option.arrival));
+        output.push_str(&format!("   - **Duration**: {}\n", option.duration));
+        output.push_str(&format!(
+            "   - **Stops**: {}\n",
+            if option.stops == 0 {
+                "Non-stop".to_string()
+            } else {
+                format!("{} stop(s)", option.stops)
+            }
+        ));
+        for layover in &option.layovers {
+            output.push_str(&format!(
+                "   - **Layover**: {}, {}{}\n",
+                layover.airport,
+                layover.duration,
+                if layover.overnight { " (overnight)" } else { "" }
+            ));
+        }
+        output.push_str(&format!(
+            "   - **Price**: {:.2} {}\n",
+            option.price, option.currency
+        ));
+        output.push_str(&format!("   - **Booking URL**: {}\n\n", option.booking_url));
+    }
 
-        // Check if the response is an error
-        if !status.is_success() {
-            return Err(FlightSearchError::ApiError(format!(
-                "Status: {}, Response: {}",
-                status, text
-            )));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::SearchFuture;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Returns the same canned options for every search
+    struct MockProvider {
+        prices: Vec<f64>,
+    }
+
+    impl MockProvider {
+        fn options(&self) -> Result<Vec<FlightOption>, FlightSearchError> {
+            Ok(self
+                .prices
+                .iter()
+                .enumerate()
+                .map(|(i, &price)| FlightOption {
+                    airline: "Mock Air".to_string(),
+                    flight_number: format!("MK{}", i + 1),
+                    departure: "2024-11-15T08:00:00+00:00".to_string(),
+                    arrival: "2024-11-15T16:00:00+00:00".to_string(),
+                    duration: "8 hours 0 minutes".to_string(),
+                    duration_minutes: 480,
+                    stops: i,
+                    layovers: Vec::new(),
+                    price,
+                    currency: "USD".to_string(),
+                    booking_url: format!("https://example.com/{}", i + 1),
+                })
+                .collect())
         }
+    }
 
-        // Parse the response JSON
-        let data: Value = serde_json::from_str(&text)
-            .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
-
-        // Check for API errors in the JSON response
-        if let Some(error) = data.get("error") {
-            let error_message = error
-                .get("message")
-                .and_then(|m| m.as_str())
-                .unwrap_or("Unknown error");
-            return Err(FlightSearchError::ApiError(error_message.to_string()));
+    impl FlightProvider for MockProvider {
+        fn search<'a>(&'a self, _args: &'a FlightSearchArgs) -> SearchFuture<'a> {
+            let options = self.options();
+            Box::pin(async move { options })
         }
+    }
 
-        let empty_leg = json!({});
+    #[test]
+    fn test_multi_segment_itinerary() {
+        let data = json!({
+            "data": {
+                "flights": [{
+                    "segments": [
+                        {
+                            "legs": [
+                                {
+                                    "departureDateTime": "2024-11-15T08:00:00-06:00",
+                                    "arrivalDateTime": "2024-11-15T11:00:00-05:00",
+                                    "marketingCarrierCode": "AA",
+                                    "flightNumber": "100",
+                                    "marketingCarrier": { "displayName": "American Airlines" }
+                                },
+                                {
+                                    "departureDateTime": "2024-11-15T13:00:00-05:00",
+                                    "arrivalDateTime": "2024-11-16T01:00:00+00:00"
+                                }
+                            ]
+                        },
+                        {
+                            "legs": [
+                                {
+                                    "departureDateTime": "2024-11-22T10:00:00+00:00",
+                                    "arrivalDateTime": "2024-11-22T20:00:00+00:00"
+                                }
+                            ]
+                        }
+                    ],
+                    "purchaseLinks": [
+                        { "totalPrice": 950.0, "url": "https://example.com/b" },
+                        { "totalPrice": 900.0, "url": "https://example.com/a" }
+                    ]
+                }]
+            }
+        });
 
-        // Extract flight options
-        let mut flight_options = Vec::new();
+        let options = parse_flights(&data, "USD").unwrap();
+        assert_eq!(options.len(), 1);
 
-        // Check if 'data' contains 'flights' array
-        if let Some(flights) = data
-            .get("data")
-            .and_then(|d| d.get("flights"))
-            .and_then(|f| f.as_array())
-        {
-            // Iterate over flight entries, taking the first 5
-            for flight in flights.iter().take(5) {
-                // Extract flight segments
-                if let Some(segments) = flight
-                    .get("segments")
-                    .and_then(|s| s.as_array())
-                    .and_then(|s| s.get(0))
-                {
-                    // Extract legs from the first segment
-                    if let Some(legs) = segments.get("legs").and_then(|l| l.as_array()) {
-                        let first_leg = legs.get(0).unwrap_or(&empty_leg);
-                        let last_leg = legs.last().unwrap_or(&empty_leg); 
-                        
-                        // Extract airline name
-                        let airline = first_leg
-                            .get("marketingCarrier")
-                            .and_then(|mc| mc.get("displayName"))
-                            .and_then(|dn| dn.as_str())
-                            .unwrap_or("Unknown")
-                            .to_string();
-                        
-                        // Extract flight number
-                        let flight_number = format!(
-                            "{}{}",
-                            first_leg
-                                .get("marketingCarrierCode")
-                                .and_then(|c| c.as_str())
-                                .unwrap_or(""),
-                            first_leg
-                                .get("flightNumber")
-                                .and_then(|n| n.as_str())
-                                .unwrap_or("")
-                        );
-                        
-                        // Extract departure and arrival times
-                        let departure = first_leg
-                            .get("departureDateTime")
-                            .and_then(|dt| dt.as_str())
-                            .unwrap_or("")
-                            .to_string();
-                        
-                        let arrival = last_leg
-                            .get("arrivalDateTime")
-                            .and_then(|dt| dt.as_str())
-                            .unwrap_or("")
-                            .to_string();
-
-                        // Parse departure time or fallback to current UTC time
-                        let departure_time = chrono::DateTime::parse_from_rfc3339(&departure)
-                            .map(|dt| dt.with_timezone(&Utc))
-                            .unwrap_or_else(|_| chrono::Utc::now());
-
-                        // Parse arrival time or fallback to current UTC time
-                        let arrival_time = chrono::DateTime::parse_from_rfc3339(&arrival)
-                            .map(|dt| dt.with_timezone(&Utc))
-                            .unwrap_or_else(|_| chrono::Utc::now());
-
-                        // Calculate flight duration
-                        let duration = arrival_time - departure_time;
-                        let hours = duration.num_hours();
-                        let minutes = duration.num_minutes() % 60;
-                        let duration_str = format!("{} hours {} minutes", hours, minutes);
-
-                        // Determine number of stops
-                        let stops = if legs.len() > 1 { legs.len() - 1 } else { 0 };
-
-                        // Extract purchase links array for price information
-                        let purchase_links = flight
-                            .get("purchaseLinks")
-                            .and_then(|pl| pl.as_array())
-                            .map(|v| v.as_slice())
-                            .unwrap_or(&[]);
-
-                        // Find the best price from purchase links
-                        let best_price = purchase_links.iter().min_by_key(|p| {
-                            p.get("totalPrice")
-                                .and_then(|tp| tp.as_f64())
-                                .unwrap_or(f64::MAX) as u64
-                        });
-
-                        // Extract pricing and booking URL if available
-                        if let Some(best_price) = best_price {
-                            let total_price = best_price
-                                .get("totalPrice")
-                                .and_then(|tp| tp.as_f64())
-                                .unwrap_or(0.0);
-                            let booking_url = best_price
-                                .get("url")
-                                .and_then(|u| u.as_str())
-                                .unwrap_or("")
-                                .to_string();
-
-                            // Skip flights with price 0.0
-                            if total_price == 0.0 {
-                                continue;
+        let option = &options[0];
+        // One stop on the outbound segment, none on the return
+        assert_eq!(option.stops, 1);
+        // Outbound 08:00 CST -> 01:00 UTC is 11h, return is 10h
+        assert_eq!(option.duration_minutes, 21 * 60);
+        assert_eq!(option.duration, "21 hours 0 minutes");
+        assert_eq!(option.departure, "2024-11-15T08:00:00-06:00");
+        assert_eq!(option.arrival, "2024-11-22T20:00:00+00:00");
+        assert_eq!(option.flight_number, "AA100");
+        assert_eq!(option.price, 900.0);
+    }
+
+    #[test]
+    fn test_two_stop_layovers() {
+        let data = json!({
+            "data": {
+                "flights": [{
+                    "segments": [{
+                        "legs": [
+                            {
+                                "originStationCode": "BOM",
+                                "destinationStationCode": "DXB",
+                                "departureDateTime": "2024-11-15T09:00:00+05:30",
+                                "arrivalDateTime": "2024-11-15T10:45:00+04:00"
+                            },
+                            {
+                                "originStationCode": "DXB",
+                                "destinationStationCode": "LHR",
+                                "departureDateTime": "2024-11-15T13:00:00+04:00",
+                                "arrivalDateTime": "2024-11-15T17:30:00+00:00"
+                            },
+                            {
+                                "originStationCode": "LHR",
+                                "destinationStationCode": "SAT",
+                                "departureDateTime": "2024-11-16T08:10:00+00:00",
+                                "arrivalDateTime": "2024-11-16T14:00:00-06:00"
                             }
+                        ]
+                    }],
+                    "purchaseLinks": [{ "totalPrice": 1200.0, "url": "https://example.com" }]
+                }]
+            }
+        });
 
-                            // Append extracted flight options to flight_options vector
-                            flight_options.push(FlightOption {
-                                airline,
-                                flight_number,
-                                departure,
-                                arrival,
-                                duration: duration_str,
-                                stops,
-                                price: total_price,
-                                currency: currency.clone(),
-                                booking_url,
-                            });
-                        }
-                    }
-                }
+        let options = parse_flights(&data, "USD").unwrap();
+        let layovers = &options[0].layovers;
+        assert_eq!(options[0].stops, 2);
+        assert_eq!(layovers.len(), 2);
+
+        assert_eq!(layovers[0].airport, "DXB");
+        assert_eq!(layovers[0].duration, "2h15m");
+        assert!(!layovers[0].overnight);
+
+        // 17:30 to 08:10 the next morning
+        assert_eq!(layovers[1].airport, "LHR");
+        assert_eq!(layovers[1].duration_minutes, 14 * 60 + 40);
+        assert_eq!(layovers[1].arrival, "2024-11-15T17:30:00+00:00");
+        assert_eq!(layovers[1].departure, "2024-11-16T08:10:00+00:00");
+        assert!(layovers[1].overnight);
+    }
+
+    #[test]
+    fn test_malformed_leg_times_skip_the_layover() {
+        let data = json!({
+            "data": {
+                "flights": [{
+                    "segments": [{
+                        "legs": [
+                            {
+                                "destinationStationCode": "ORD",
+                                "departureDateTime": "2024-11-15T08:00:00-06:00",
+                                "arrivalDateTime": "not a time"
+                            },
+                            {
+                                "originStationCode": "ORD",
+                                "destinationStationCode": "FRA",
+                                "departureDateTime": "2024-11-15T12:00:00-06:00",
+                                "arrivalDateTime": "2024-11-16T03:00:00+01:00"
+                            },
+                            {
+                                "originStationCode": "FRA",
+                                "departureDateTime": "2024-11-16T05:30:00+01:00",
+                                "arrivalDateTime": "2024-11-16T07:00:00+00:00"
+                            }
+                        ]
+                    }],
+                    "purchaseLinks": [{ "totalPrice": 700.0, "url": "https://example.com" }]
+                }]
             }
-        } else {
-            // Return an error if response structure is invalid
-            return Err(FlightSearchError::InvalidResponse);
+        });
+
+        // Both stops count, but only the connection with readable times is listed
+        let options = parse_flights(&data, "USD").unwrap();
+        assert_eq!(options[0].stops, 2);
+        assert_eq!(options[0].layovers.len(), 1);
+        assert_eq!(options[0].layovers[0].airport, "FRA");
+
+        let formatted = format_flights(&options, &Passengers::from_args(&sat_to_lhr()).unwrap());
+        assert!(formatted.contains("   - **Layover**: FRA, 2h30m\n"));
+    }
+
+    #[test]
+    fn test_tool_output_round_trips_as_json() {
+        let data = json!({
+            "data": {
+                "flights": [{
+                    "segments": [{
+                        "legs": [{
+                            "originStationCode": "SAT",
+                            "destinationStationCode": "LHR",
+                            "departureDateTime": "2024-11-15T17:00:00-06:00",
+                            "arrivalDateTime": "2024-11-16T08:30:00+00:00",
+                            "marketingCarrierCode": "BA",
+                            "flightNumber": "196",
+                            "marketingCarrier": { "displayName": "British Airways" }
+                        }]
+                    }],
+                    "purchaseLinks": [{ "totalPrice": 640.5, "url": "https://example.com/ba" }]
+                }]
+            }
+        });
+
+        // What the agent receives is the JSON of the parsed options
+        let output = serde_json::to_string(&parse_flights(&data, "USD").unwrap()).unwrap();
+        let options: Vec<FlightOption> = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].airline, "British Airways");
+        assert_eq!(options[0].flight_number, "BA196");
+        assert_eq!(options[0].stops, 0);
+        assert!(options[0].layovers.is_empty());
+        assert_eq!(options[0].price, 640.5);
+
+        let passengers = Passengers::from_args(&sat_to_lhr()).unwrap();
+        let formatted = format_flights(&options, &passengers);
+        assert!(formatted.starts_with("Here are some flight options for 1 adult:"));
+        assert!(formatted.contains("1. **Airline**: British Airways"));
+        assert!(formatted.contains("**Stops**: Non-stop"));
+        assert!(formatted.contains("**Price**: 640.50 USD"));
+        assert_eq!(format_flights(&[], &passengers), "No flights found for the given criteria.");
+    }
+
+    #[test]
+    fn test_max_results_is_clamped() {
+        let flights: Vec<Value> = (0..30)
+            .map(|i| {
+                json!({
+                    "segments": [{
+                        "legs": [{
+                            "departureDateTime": "2024-11-15T08:00:00+00:00",
+                            "arrivalDateTime": "2024-11-15T16:00:00+00:00"
+                        }]
+                    }],
+                    "purchaseLinks": [{ "totalPrice": 500.0 + i as f64, "url": "https://example.com" }]
+                })
+            })
+            .collect();
+        let data = json!({ "data": { "flights": flights } });
+
+        let options = || parse_flights(&data, "USD").unwrap();
+        let count = |max_results| limit_flights(options(), None, max_results).len();
+        assert_eq!(count(None), DEFAULT_MAX_RESULTS);
+        assert_eq!(count(Some(12)), 12);
+        assert_eq!(count(Some(100)), MAX_RESULTS_CAP);
+        assert_eq!(count(Some(0)), 1);
+    }
+
+    #[tokio::test]
+    async fn test_tool_with_mock_provider() {
+        let tool = FlightSearchTool::new(MockProvider {
+            prices: vec![820.0, 450.0, 600.0],
+        });
+        let args: FlightSearchArgs = serde_json::from_value(json!({
+            "source": "SAT",
+            "destination": "LHR",
+            "rank_by": "cheapest",
+            "max_results": 2
+        }))
+        .unwrap();
+
+        let passengers = Passengers::from_args(&args).unwrap();
+        let options = tool.call(args).await.unwrap();
+        let formatted = format_flights(&options, &passengers);
+        assert_eq!(
+            formatted,
+            "Here are some flight options for 1 adult:\n\n\
+             1. **Airline**: Mock Air\n\
+             \x20  - **Flight Number**: MK2\n\
+             \x20  - **Departure**: 2024-11-15T08:00:00+00:00\n\
+             \x20  - **Arrival**: 2024-11-15T16:00:00+00:00\n\
+             \x20  - **Duration**: 8 hours 0 minutes\n\
+             \x20  - **Stops**: 1 stop(s)\n\
+             \x20  - **Price**: 450.00 USD\n\
+             \x20  - **Booking URL**: https://example.com/2\n\n\
+             2. **Airline**: Mock Air\n\
+             \x20  - **Flight Number**: MK3\n\
+             \x20  - **Departure**: 2024-11-15T08:00:00+00:00\n\
+             \x20  - **Arrival**: 2024-11-15T16:00:00+00:00\n\
+             \x20  - **Duration**: 8 hours 0 minutes\n\
+             \x20  - **Stops**: 2 stop(s)\n\
+             \x20  - **Price**: 600.00 USD\n\
+             \x20  - **Booking URL**: https://example.com/3\n\n"
+        );
+    }
+
+    /// Answers with each status in turn, then with one option once they run out
+    struct FlakyProvider {
+        statuses: Vec<u16>,
+        calls: AtomicU32,
+    }
+
+    impl FlightProvider for FlakyProvider {
+        fn search<'a>(&'a self, _args: &'a FlightSearchArgs) -> SearchFuture<'a> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) as usize;
+            let result = match self.statuses.get(call) {
+                Some(&404) => Err(FlightSearchError::ApiError("Status: 404".to_string())),
+                Some(&status) => Err(FlightSearchError::Transient {
+                    status,
+                    retry_after: None,
+                }),
+                None => MockProvider { prices: vec![300.0] }.options(),
+            };
+            Box::pin(async move { result })
         }
+    }
 
-        // Format flight_options into a readable string
-        // Check if there are any flight options
-        if flight_options.is_empty() {
-            return Ok("No flights found for the given criteria.".to_string());
+    fn flaky_tool(statuses: Vec<u16>, max_retries: u32) -> FlightSearchTool {
+        let tool = FlightSearchTool::new(FlakyProvider {
+            statuses,
+            calls: AtomicU32::new(0),
+        });
+        FlightSearchTool {
+            retry_delay: Duration::from_millis(1),
+            ..tool.with_max_retries(max_retries)
         }
+    }
 
-        // Initialize the output string
-        let mut output = String::new();
-        output.push_str("Here are some flight options:\n\n");
+    fn sat_to_lhr() -> FlightSearchArgs {
+        serde_json::from_value(json!({ "source": "SAT", "destination": "LHR" })).unwrap()
+    }
 
-        // Iterate over each flight option and format the details
-        for (i, option) in flight_options.iter().enumerate() {
-            output.push_str(&format!("{}. **Airline**: {}\n", i + 1, option.airline));
-            output.push_str(&format!(
-                "   - **Flight Number**: {}\n",
-                option.flight_number
-            ));
-            output.push_str(&format!("   - **Departure**: {}\n", option.departure));
-            output.push_str(&format!("   - **Arrival**: {}\n", option.arrival));
-            output.push_str(&format!("   - **Duration**: {}\n", option.duration));
-            output.push_str(&format!(
-                "   - **Stops**: {}\n",
-                if option.stops == 0 {
-                    "Non-stop".to_string()
-                } else {
-                    format!("{} stop(s)", option.stops)
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        // A rate limit then a server error, then the search goes through
+        let options = flaky_tool(vec![429, 503], 2).call(sat_to_lhr()).await.unwrap();
+        assert_eq!(options.len(), 1);
+
+        // Out of retries, the last transient error is returned
+        let error = flaky_tool(vec![503, 503, 503], 2).call(sat_to_lhr()).await;
+        assert!(matches!(error, Err(FlightSearchError::Transient { status: 503, .. })));
+
+        // A 404 isn't retried even with retries left
+        let error = flaky_tool(vec![404], 2).call(sat_to_lhr()).await;
+        assert!(matches!(error, Err(FlightSearchError::ApiError(_))));
+    }
+
+    #[test]
+    fn test_normalize_airport_code() {
+        // Valid codes, with case and whitespace normalized
+        assert_eq!(normalize_airport_code("LHR").unwrap(), "LHR");
+        assert_eq!(normalize_airport_code(" sat ").unwrap(), "SAT");
+        assert_eq!(normalize_airport_code("jFk").unwrap(), "JFK");
+
+        // Common cities resolve to their main airport
+        assert_eq!(normalize_airport_code("London").unwrap(), "LHR");
+        assert_eq!(normalize_airport_code("new york").unwrap(), "JFK");
+
+        for input in ["Londres", "LH", "LHRX", "L1R", ""] {
+            match normalize_airport_code(input) {
+                Err(FlightSearchError::ApiError(message)) => {
+                    assert_eq!(message, format!("invalid airport code: {}", input))
                 }
-            ));
-            output.push_str(&format!(
-                "   - **Price**: {:.2} {}\n",
-                option.price, option.currency
-            ));
-            output.push_str(&format!("   - **Booking URL**: {}\n\n", option.booking_url));
+                other => panic!("{:?} was accepted: {:?}", input, other.ok()),
+            }
         }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_airport_is_rejected_before_searching() {
+        let tool = flaky_tool(vec![], 0);
+        let args = serde_json::from_value(json!({ "source": "Gotham", "destination": "LHR" }));
+        let error = tool.call(args.unwrap()).await;
+        assert!(matches!(error, Err(FlightSearchError::ApiError(_))));
+    }
+
+    #[test]
+    fn test_passengers_default_and_validation() {
+        // Without counts, one adult travels alone
+        let passengers = Passengers::from_args(&sat_to_lhr()).unwrap();
+        assert_eq!(
+            passengers,
+            Passengers {
+                adults: 1,
+                seniors: 0,
+                children: 0,
+                infants: 0
+            }
+        );
+
+        let with = |counts: Value| {
+            let mut args = json!({ "source": "SAT", "destination": "LHR" });
+            args.as_object_mut()
+                .unwrap()
+                .extend(counts.as_object().unwrap().clone());
+            Passengers::from_args(&serde_json::from_value(args).unwrap())
+        };
+        let family = with(json!({ "adults": 2, "children": 2, "infants": 1 })).unwrap();
+        assert_eq!(family.to_string(), "2 adults, 2 children, 1 infant");
+
+        // A senior can travel with an infant too
+        assert!(with(json!({ "adults": 0, "seniors": 1, "infants": 1 })).is_ok());
+        assert!(matches!(
+            with(json!({ "adults": 1, "infants": 2 })),
+            Err(FlightSearchError::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            with(json!({ "adults": 6, "children": 4 })),
+            Err(FlightSearchError::InvalidArgs(_))
+        ));
+        assert!(with(json!({ "adults": 5, "seniors": 2, "children": 2 })).is_ok());
+    }
+
+    /// Counts the searches that reach it
+    struct CountingProvider {
+        calls: Arc<AtomicU32>,
+    }
+
+    impl FlightProvider for CountingProvider {
+        fn search<'a>(&'a self, _args: &'a FlightSearchArgs) -> SearchFuture<'a> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let options = MockProvider { prices: vec![300.0, 200.0] }.options();
+            Box::pin(async move { options })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_identical_searches_hit_the_provider_once() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let tool = FlightSearchTool::new(CountingProvider {
+            calls: Arc::clone(&calls),
+        })
+        .with_cache(DEFAULT_CACHE_TTL);
+        let search = |args: Value| tool.call(serde_json::from_value(args).unwrap());
+
+        search(json!({ "source": "SAT", "destination": "LHR", "adults": 2 })).await.unwrap();
+        // Same search with the fields in another order, a lowercase code and another
+        // ranking, which is applied to the cached results
+        let cheapest = search(json!({ "adults": 2, "rank_by": "cheapest", "destination": "LHR", "source": "sat" }))
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cheapest[0].price, 200.0);
+
+        search(json!({ "source": "SAT", "destination": "JFK", "adults": 2 })).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // Invalid arguments are rejected without a search
+        let error = search(json!({ "source": "SAT", "destination": "LHR", "rank_by": "shortest" })).await;
+        assert!(matches!(error, Err(FlightSearchError::InvalidArgs(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
 
-        // Return the formatted flight options
-        Ok(output)
+        // Expired results are fetched again
+        let tool = FlightSearchTool::new(CountingProvider {
+            calls: Arc::clone(&calls),
+        })
+        .with_cache(Duration::ZERO);
+        tool.call(sat_to_lhr()).await.unwrap();
+        tool.call(sat_to_lhr()).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
     }
 }
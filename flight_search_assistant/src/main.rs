@@ -1,30 +1,84 @@
 mod flight_search_tool;
+mod provider;
+mod ranking;
 
-use crate::flight_search_tool::FlightSearchTool;
+use crate::flight_search_tool::{
+    format_flights, FlightSearchArgs, FlightSearchTool, Passengers, DEFAULT_CACHE_TTL,
+    DEFAULT_MAX_RETRIES,
+};
+use clap::Parser;
+use examples_common::cli::CommonArgs;
+use examples_common::logging::init_tracing;
 use rig::completion::Prompt;
 use rig::providers::openai;
+use rig::tool::Tool;
+use serde_json::json;
+
+const DEFAULT_MODEL: &str = "gpt-4";
+
+/// Ask a travel assistant to find flights
+#[derive(Debug, Parser)]
+struct Cli {
+    /// What to ask the assistant
+    #[arg(
+        long,
+        default_value = "Find me flights from San Antonio (SAT) to London (LHR) on November 15th 2024."
+    )]
+    query: String,
+
+    /// Search from this airport code directly and print the results, without the agent
+    #[arg(long, requires = "to")]
+    from: Option<String>,
+
+    /// Destination airport code for a direct search
+    #[arg(long, requires = "from")]
+    to: Option<String>,
+
+    /// How often to retry a rate-limited or failed flight API request
+    #[arg(long, default_value_t = DEFAULT_MAX_RETRIES)]
+    max_retries: u32,
+
+    /// Reuse the results of identical flight searches for 10 minutes
+    #[arg(long)]
+    cache: bool,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    init_tracing(cli.common.log_level.as_deref());
+
+    let mut tool = FlightSearchTool::default().with_max_retries(cli.max_retries);
+    if cli.cache {
+        tool = tool.with_cache(DEFAULT_CACHE_TTL);
+    }
+
+    if let (Some(from), Some(to)) = (&cli.from, &cli.to) {
+        let args: FlightSearchArgs =
+            serde_json::from_value(json!({ "source": from, "destination": to }))?;
+        let passengers = Passengers::from_args(&args)?;
+        println!("{}", format_flights(&tool.call(args).await?, &passengers));
+        return Ok(());
+    }
+
     // Initialize the OpenAI client
     let openai_client = openai::Client::from_env();
 
     // Build the agent with the FlightSearchTool
     let agent = openai_client
-        .agent("gpt-4")
+        .agent(cli.common.model_or(DEFAULT_MODEL))
         .preamble("You are a travel assistant that can help users find flights between airports.")
-        .tool(FlightSearchTool)
+        .tool(tool)
         .build();
 
     // query
-    let response = agent
-        .prompt("Find me flights from San Antonio (SAT) to London (LHR) on November 15th 2024.")
-        .await?;
-
-    // Deserialize the response to get the formatted string
-    let formatted_response: String = serde_json::from_str(&response)?;
+    let response = agent.prompt(&cli.query).await?;
 
-    println!("Agent response:\n{}", formatted_response);
+    // The tool hands the agent structured options; the agent answers in prose
+    println!("Agent response:\n{}", response);
 
     Ok(())
 }
\ No newline at end of file
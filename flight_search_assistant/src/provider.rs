@@ -0,0 +1,287 @@
+// provider.rs
+//
+// Where flight options come from. `FlightSearchTool` only ranks and limits what a
+// `FlightProvider` returns, so another API (or a mock in tests) can be plugged in
+// without touching the tool.
+
+use crate::flight_search_tool::{parse_flights, FlightOption, FlightSearchArgs, FlightSearchError};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// The future returned by [`FlightProvider::search`]. rig needs tool calls to be
+/// `Send + Sync`, so the boxed future is too.
+pub type SearchFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<FlightOption>, FlightSearchError>> + Send + Sync + 'a>>;
+
+/// A flight search backend
+pub trait FlightProvider: Send + Sync {
+    /// Find flights matching `args`, in the backend's own order. Ranking and
+    /// `max_results` are applied by the tool afterwards.
+    fn search<'a>(&'a self, args: &'a FlightSearchArgs) -> SearchFuture<'a>;
+}
+
+/// The TripAdvisor flight search endpoint on RapidAPI
+const TRIPADVISOR_SEARCH_URL: &str =
+    "https://tripadvisor16.p.rapidapi.com/api/v1/flights/searchFlights";
+
+/// Searches the TripAdvisor flights API on RapidAPI, using the key in `RAPIDAPI_KEY`
+pub struct TripAdvisorProvider {
+    /// Where searches are sent: the real API unless set with `with_url`
+    url: String,
+}
+
+impl TripAdvisorProvider {
+    pub fn new() -> Self {
+        Self::with_url(TRIPADVISOR_SEARCH_URL)
+    }
+
+    /// Send searches to `url` instead of the real API, e.g. to a local server in tests
+    pub fn with_url(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+impl Default for TripAdvisorProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The delay a `Retry-After` header asks for. It is either a number of seconds or an
+/// HTTP date such as `Wed, 21 Oct 2015 07:28:00 GMT`, counted from `now`; a date that
+/// already passed means no delay. Values in neither form are ignored.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+impl FlightProvider for TripAdvisorProvider {
+    fn search<'a>(&'a self, args: &'a FlightSearchArgs) -> SearchFuture<'a> {
+        Box::pin(async move {
+            // Use the RapidAPI key from an environment variable
+            let api_key = env::var("RAPIDAPI_KEY").map_err(|_| FlightSearchError::MissingApiKey)?;
+
+            // Set default values if not provided
+            let date = args.date.clone().unwrap_or_else(|| {
+                let date = chrono::Utc::now() + chrono::Duration::days(30);
+                date.format("%Y-%m-%d").to_string()
+            });
+
+            let sort = args
+                .sort
+                .clone()
+                .unwrap_or_else(|| "ML_BEST_VALUE".to_string());
+            let service = args
+                .service
+                .clone()
+                .unwrap_or_else(|| "ECONOMY".to_string());
+            let itinerary_type = args
+                .itinerary_type
+                .clone()
+                .unwrap_or_else(|| "ONE_WAY".to_string());
+            let adults = args.adults.unwrap_or(1);
+            let seniors = args.seniors.unwrap_or(0);
+            let children = args.children.unwrap_or(0);
+            let infants = args.infants.unwrap_or(0);
+            let currency = args.currency.clone().unwrap_or_else(|| "USD".to_string());
+            let nearby = args.nearby.clone().unwrap_or_else(|| "no".to_string());
+            let nonstop = args.nonstop.clone().unwrap_or_else(|| "no".to_string());
+
+            // Build the query parameters
+            let mut query_params = HashMap::new();
+            query_params.insert("sourceAirportCode", args.source.clone());
+            query_params.insert("destinationAirportCode", args.destination.clone());
+            query_params.insert("date", date);
+            query_params.insert("itineraryType", itinerary_type);
+            query_params.insert("sortOrder", sort);
+            query_params.insert("numAdults", adults.to_string());
+            query_params.insert("numSeniors", seniors.to_string());
+            query_params.insert("numChildren", children.to_string());
+            query_params.insert("numInfantsInSeat", infants.to_string());
+            query_params.insert("classOfService", service);
+            query_params.insert("pageNumber", "1".to_string());
+            query_params.insert("currencyCode", currency.clone());
+            query_params.insert("nearby", nearby);
+            query_params.insert("nonstop", nonstop);
+
+            // Make the API request
+            let client = reqwest::Client::new();
+            let response = client
+                .get(&self.url)
+                .headers({
+                    let mut headers = reqwest::header::HeaderMap::new();
+                    headers.insert(
+                        "X-RapidAPI-Host",
+                        "tripadvisor16.p.rapidapi.com".parse().unwrap(),
+                    );
+                    headers.insert("X-RapidAPI-Key", api_key.parse().unwrap());
+                    headers
+                })
+                .query(&query_params)
+                .send()
+                .await
+                .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+
+            // Get the status code and any Retry-After before consuming `response`
+            let status = response.status();
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| parse_retry_after(v, Utc::now()));
+
+            // Read the response text (this consumes `response`)
+            let text = response
+                .text()
+                .await
+                .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+
+            // Print the raw API response for debugging
+            // println!("Raw API response:\n{}", text);
+
+            // Rate limits and server errors may pass, so the tool can retry them
+            if status.as_u16() == 429 || status.is_server_error() {
+                return Err(FlightSearchError::Transient {
+                    status: status.as_u16(),
+                    retry_after,
+                });
+            }
+
+            // Any other error status fails fast
+            if !status.is_success() {
+                return Err(FlightSearchError::ApiError(format!(
+                    "Status: {}, Response: {}",
+                    status, text
+                )));
+            }
+
+            // Parse the response JSON
+            let data: Value = serde_json::from_str(&text)
+                .map_err(|e| FlightSearchError::HttpRequestFailed(e.to_string()))?;
+
+            // Check for API errors in the JSON response
+            if let Some(error) = data.get("error") {
+                let error_message = error
+                    .get("message")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or("Unknown error");
+                return Err(FlightSearchError::ApiError(error_message.to_string()));
+            }
+
+            // Extract flight options
+            parse_flights(&data, &currency)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flight_search_tool::FlightSearchTool;
+    use rig::tool::Tool;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves one canned reply per connection, in order, and counts the requests
+    async fn serve(replies: Vec<String>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/searchFlights", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for reply in replies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = socket.write_all(reply.as_bytes()).await;
+            }
+        });
+        (url, requests)
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            body.len(),
+            body
+        )
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_and_server_errors_are_retried_over_http() {
+        std::env::set_var("RAPIDAPI_KEY", "test-key");
+        let flights = json!({
+            "data": {
+                "flights": [{
+                    "segments": [{
+                        "legs": [{
+                            "departureDateTime": "2024-11-15T08:00:00-06:00",
+                            "arrivalDateTime": "2024-11-16T01:00:00+00:00"
+                        }]
+                    }],
+                    "purchaseLinks": [{ "totalPrice": 640.0, "url": "https://example.com/a" }]
+                }]
+            }
+        })
+        .to_string();
+        let (url, requests) = serve(vec![
+            response("429 Too Many Requests", "Retry-After: 0\r\n", ""),
+            // An HTTP date that already passed, so the retry goes out at once
+            response(
+                "503 Service Unavailable",
+                "Retry-After: Wed, 21 Oct 2015 07:28:00 GMT\r\n",
+                "",
+            ),
+            response("200 OK", "Content-Type: application/json\r\n", &flights),
+        ])
+        .await;
+
+        let tool = FlightSearchTool::new(TripAdvisorProvider::with_url(url));
+        let args =
+            serde_json::from_value(json!({ "source": "SAT", "destination": "LHR" })).unwrap();
+        let options = tool.call(args).await.unwrap();
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].price, 640.0);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc3339("2015-10-21T07:28:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        // A date in the past means retrying right away
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::flight_search_tool::FlightOption;
+use std::cmp::Ordering;
+
+/// Decides which flight options are "best"
+pub trait FlightRanker: Send + Sync {
+    /// Compare two options; `Ordering::Less` means `a` should be listed before `b`
+    fn compare(&self, a: &FlightOption, b: &FlightOption) -> Ordering;
+
+    /// Sort options from best to worst
+    fn rank(&self, options: &mut [FlightOption]) {
+        options.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+/// Lowest price first, ties broken by shorter duration
+pub struct CheapestRanker;
+
+impl FlightRanker for CheapestRanker {
+    fn compare(&self, a: &FlightOption, b: &FlightOption) -> Ordering {
+        a.price
+            .total_cmp(&b.price)
+            .then(a.duration_minutes.cmp(&b.duration_minutes))
+    }
+}
+
+/// Shortest total travel time first, ties broken by fewer stops then price
+pub struct FastestRanker;
+
+impl FlightRanker for FastestRanker {
+    fn compare(&self, a: &FlightOption, b: &FlightOption) -> Ordering {
+        a.duration_minutes
+            .cmp(&b.duration_minutes)
+            .then(a.stops.cmp(&b.stops))
+            .then(a.price.total_cmp(&b.price))
+    }
+}
+
+/// Weighted combination of price, duration and stops; lower scores rank first
+pub struct BalancedRanker {
+    /// Weight per unit of currency
+    pub price_weight: f64,
+    /// Weight per minute of travel time
+    pub duration_weight: f64,
+    /// Flat penalty per stop
+    pub stop_penalty: f64,
+}
+
+impl Default for BalancedRanker {
+    /// Treats an hour of travel as worth 30 units of currency and each stop as 50
+    fn default() -> Self {
+        Self {
+            price_weight: 1.0,
+            duration_weight: 0.5,
+            stop_penalty: 50.0,
+        }
+    }
+}
+
+impl BalancedRanker {
+    pub fn score(&self, option: &FlightOption) -> f64 {
+        option.price * self.price_weight
+            + option.duration_minutes as f64 * self.duration_weight
+            + option.stops as f64 * self.stop_penalty
+    }
+}
+
+impl FlightRanker for BalancedRanker {
+    fn compare(&self, a: &FlightOption, b: &FlightOption) -> Ordering {
+        self.score(a).total_cmp(&self.score(b))
+    }
+}
+
+/// Look up a ranker by the name the agent passes in `rank_by`
+pub fn ranker_for(name: &str) -> Option<Box<dyn FlightRanker>> {
+    match name.to_lowercase().as_str() {
+        "cheapest" => Some(Box::new(CheapestRanker)),
+        "fastest" => Some(Box::new(FastestRanker)),
+        "balanced" => Some(Box::new(BalancedRanker::default())),
+        _ => None,
+    }
+}
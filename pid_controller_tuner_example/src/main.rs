@@ -1,3 +1,4 @@
+use examples_common::control::settling_time;
 use rig::providers::openai;
 use rig::completion::Prompt;
 use serde::{Deserialize, Serialize};
@@ -66,7 +67,7 @@ fn calculate_performance_metrics(response: &[f64], setpoint: f64, dt: f64) -> (f
         }
     }
     
-    let settling_time = response.len() as f64 * dt;  // Simplified
+    let settling_time = settling_time(response, setpoint, dt);
 
     (settling_time, max_overshoot, steady_state_error)
 }
@@ -128,4 +129,4 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,19 +1,129 @@
+use clap::Parser;
+use examples_common::cli::CommonArgs;
+use examples_common::logging::init_tracing;
+use examples_common::shutdown::{shutdown_signal, stdin_lines};
 use rig::providers::openai;
 use rig::vector_store::in_memory_store::InMemoryVectorStore;
-use rig::vector_store::VectorStore;
+use rig::vector_store::{VectorStore, VectorStoreIndex};
 use rig::embeddings::EmbeddingsBuilder;
-use rig::cli_chatbot::cli_chatbot;  // Import the cli_chatbot function
-use std::path::Path;
+use rig::completion::{Chat, Message};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 use pdf_extract::extract_text;
+use tracing::{info, info_span, Instrument};
+
+// Number of documents retrieved as context for each question
+const CONTEXT_DOCUMENTS: usize = 2;
+const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+
+/// Chat with an agent that answers from a folder of PDF documents
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Folder whose PDF files make up the knowledge base
+    #[arg(long, default_value = "documents")]
+    documents_dir: PathBuf,
+
+    /// Print the retrieved documents and their scores before each answer
+    #[arg(long)]
+    debug_retrieval: bool,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
 
 fn load_pdf_content<P: AsRef<Path>>(file_path: P) -> Result<String> {
     extract_text(file_path.as_ref())
         .with_context(|| format!("Failed to extract text from PDF: {:?}", file_path.as_ref()))
 }
 
+// Every PDF in `dir`, sorted by file name, so the documents get stable ids
+fn pdf_paths(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read documents directory: {:?}", dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+        })
+        .collect();
+    paths.sort();
+    anyhow::ensure!(!paths.is_empty(), "No PDF files found in {:?}", dir);
+    Ok(paths)
+}
+
+// Print what retrieval returns for a query: the document ids, their similarity scores
+// and the context the agent will see. This runs the same top-n lookup as the agent's
+// dynamic context, so a wrong answer can be traced to retrieval or to generation.
+async fn explain_retrieval(index: &impl VectorStoreIndex, query: &str) -> Result<()> {
+    let results = index.top_n_from_query(query, CONTEXT_DOCUMENTS).await?;
+
+    println!("========================== Retrieval ===========================");
+    for (score, doc) in &results {
+        println!("{:>10.6}  {}", score, doc.id);
+    }
+    println!("--------------------------- Context ----------------------------");
+    for (_, doc) in &results {
+        let text = match &doc.document {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        println!("<file id: {}>\n{}\n</file>", doc.id, text);
+    }
+    println!("================================================================\n");
+
+    Ok(())
+}
+
+// Same REPL as `rig::cli_chatbot`, but reading stdin without blocking the runtime so
+// Ctrl-C can interrupt it. With a debug index, retrieval is explained before each answer.
+async fn chat_loop(chatbot: impl Chat, debug_index: Option<impl VectorStoreIndex>) -> Result<()> {
+    let mut lines = stdin_lines();
+    let mut chat_log = vec![];
+
+    println!("Welcome to the chatbot! Type 'exit' to quit.");
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+
+        let Some(input) = lines.recv().await else {
+            break;
+        };
+        let input = input.trim();
+        if input == "exit" {
+            break;
+        }
+
+        if let Some(index) = &debug_index {
+            explain_retrieval(index, input).await?;
+        }
+
+        let response = chatbot
+            .chat(input, chat_log.clone())
+            .instrument(info_span!("query", turn = chat_log.len() / 2 + 1))
+            .await?;
+        chat_log.push(Message {
+            role: "user".into(),
+            content: input.into(),
+        });
+        chat_log.push(Message {
+            role: "assistant".into(),
+            content: response.clone(),
+        });
+
+        println!("========================== Response ============================");
+        println!("{}", response);
+        println!("================================================================\n\n");
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    init_tracing(cli.common.log_level.as_deref());
+
     // Initialize OpenAI client
     let openai_client = openai::Client::from_env();
     let embedding_model = openai_client.embedding_model("text-embedding-ada-002");
@@ -21,34 +131,36 @@ async fn main() -> Result<()> {
     // Create vector store
     let mut vector_store = InMemoryVectorStore::default();
 
-    // Get the current directory and construct paths to PDF files
-    let current_dir = std::env::current_dir()?;
-    let documents_dir = current_dir.join("documents");
-
-    let pdf1_path = documents_dir.join("Moores_Law_for_Everything.pdf");
-    let pdf2_path = documents_dir.join("The_Last_Question.pdf");
-
-    // Load PDF documents
-    let pdf1_content = load_pdf_content(&pdf1_path)?;
-    let pdf2_content = load_pdf_content(&pdf2_path)?;
+    // Load PDF documents, using each file name as the document id
+    info!("Loading PDF documents from {:?}", cli.documents_dir);
+    let mut builder = EmbeddingsBuilder::new(embedding_model.clone());
+    for path in pdf_paths(&cli.documents_dir)? {
+        let id = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let content = load_pdf_content(&path)?;
+        builder = builder.simple_document(&id, &content);
+    }
 
     // Create embeddings and add to vector store
-    let embeddings = EmbeddingsBuilder::new(embedding_model.clone())
-        .simple_document("Moores_Law_for_Everything", &pdf1_content)
-        .simple_document("The_Last_Question", &pdf2_content)
-        .build()
-        .await?;
+    let embeddings = builder.build().await?;
 
     vector_store.add_documents(embeddings).await?;
+    info!("Embedded and indexed the documents");
+
+    // A second index over the same documents lets the debug mode inspect retrieval
+    let debug_index = cli.debug_retrieval.then(|| vector_store.clone().index(embedding_model.clone()));
 
     // Create RAG agent
-    let rag_agent = openai_client.context_rag_agent("gpt-3.5-turbo")
+    let rag_agent = openai_client.context_rag_agent(cli.common.model_or(DEFAULT_MODEL))
         .preamble("You are a helpful assistant that answers questions based on the given context from PDF documents.")
-        .dynamic_context(2, vector_store.index(embedding_model))
+        .dynamic_context(CONTEXT_DOCUMENTS, vector_store.index(embedding_model))
         .build();
 
-    // Use the cli_chatbot function to create the CLI interface
-    cli_chatbot(rag_agent).await?;
+    // Run the CLI until the user exits or presses Ctrl-C
+    tokio::select! {
+        result = chat_loop(rag_agent, debug_index) => result?,
+        _ = shutdown_signal() => info!("Interrupted"),
+    }
 
+    std::io::stdout().flush()?;
     Ok(())
 }
\ No newline at end of file
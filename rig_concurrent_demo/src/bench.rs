@@ -0,0 +1,209 @@
+// Benchmark mode: throughput vs concurrency
+
+use rig::completion::Prompt;  // Import Prompt trait for LLM interactions
+use std::num::NonZeroUsize;  // The number of runs, which can't be 0
+use std::sync::Arc;  // For sharing the model and limiter between tasks
+use std::time::{Duration, Instant};  // For measuring execution time
+use tokio::sync::Semaphore;  // Limits how many requests are in flight at once
+use tokio::task;  // Import Tokio's task spawning functionality
+
+/// Concurrency levels measured by the benchmark
+pub const CONCURRENCY_LEVELS: [usize; 5] = [1, 2, 4, 8, 16];
+
+/// Runs per level when `--repeats` isn't given
+pub const DEFAULT_REPEATS: usize = 3;
+
+/// Read `--repeats N` from the command line, [`DEFAULT_REPEATS`] if it's absent.
+/// A missing value or one below 1 is an error.
+pub fn parse_repeats(args: &[String]) -> Result<NonZeroUsize, String> {
+    let Some(i) = args.iter().position(|arg| arg == "--repeats") else {
+        return Ok(NonZeroUsize::new(DEFAULT_REPEATS).unwrap());
+    };
+    let value = args.get(i + 1).ok_or("--repeats needs a value")?;
+    value
+        .parse::<NonZeroUsize>()
+        .map_err(|e| format!("--repeats must be at least 1: {}", e))
+}
+
+/// Aggregated results for one concurrency level
+pub struct LevelStats {
+    pub concurrency: usize,
+    pub requests: usize,
+    pub errors: usize,
+    /// Wall-clock time of each pass over the prompt set
+    pub run_times: Vec<Duration>,
+}
+
+impl LevelStats {
+    pub fn total_time(&self) -> Duration {
+        self.run_times.iter().sum()
+    }
+
+    /// Average wall-clock time for one pass over the prompt set
+    pub fn time_per_run(&self) -> Duration {
+        self.total_time() / self.run_times.len().max(1) as u32
+    }
+
+    pub fn fastest_run(&self) -> Duration {
+        self.run_times.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn slowest_run(&self) -> Duration {
+        self.run_times.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Requests completed per second, errors included. 0 if no time was measured,
+    /// rather than infinity.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.total_time().as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.requests as f64 / secs
+    }
+
+    /// Fraction of requests that failed
+    pub fn error_rate(&self) -> f64 {
+        self.errors as f64 / self.requests.max(1) as f64
+    }
+}
+
+/// Send every prompt once, with at most `concurrency` requests in flight.
+/// Returns the number of failed requests.
+async fn run_once<M>(model: &Arc<M>, prompts: &[String], concurrency: usize) -> usize
+where
+    M: Prompt + Send + Sync + 'static,
+{
+    let limiter = Arc::new(Semaphore::new(concurrency));
+
+    let handles: Vec<_> = prompts
+        .iter()
+        .cloned()
+        .map(|prompt| {
+            let model = Arc::clone(model);
+            let limiter = Arc::clone(&limiter);
+            task::spawn(async move {
+                // Wait for a free slot before sending the request
+                let _permit = limiter.acquire_owned().await.expect("limiter closed");
+                model.prompt(&prompt).await
+            })
+        })
+        .collect();
+
+    let mut errors = 0;
+    for handle in handles {
+        // A panicked task counts as a failed request
+        if !matches!(handle.await, Ok(Ok(_))) {
+            errors += 1;
+        }
+    }
+    errors
+}
+
+/// Run the prompt set `repeats` times at each level in [`CONCURRENCY_LEVELS`]. At
+/// least one run is needed to measure anything.
+pub async fn run_benchmark<M>(model: Arc<M>, prompts: &[String], repeats: NonZeroUsize) -> Vec<LevelStats>
+where
+    M: Prompt + Send + Sync + 'static,
+{
+    let repeats = repeats.get();
+    let mut results = Vec::new();
+
+    for concurrency in CONCURRENCY_LEVELS {
+        let mut stats = LevelStats {
+            concurrency,
+            requests: 0,
+            errors: 0,
+            run_times: Vec::with_capacity(repeats),
+        };
+
+        for run in 1..=repeats {
+            println!("Concurrency {:>2}: run {}/{}", concurrency, run, repeats);
+            let start = Instant::now();
+            stats.errors += run_once(&model, prompts, concurrency).await;
+            stats.run_times.push(start.elapsed());
+            stats.requests += prompts.len();
+        }
+
+        results.push(stats);
+    }
+
+    results
+}
+
+/// Print the results as a table
+pub fn print_table(results: &[LevelStats]) {
+    println!(
+        "\n{:>11} | {:>14} | {:>12} | {:>12} | {:>12} | {:>10}",
+        "Concurrency", "Time per run", "Fastest", "Slowest", "Req/s", "Error rate"
+    );
+    println!("{}", "-".repeat(86));
+    for stats in results {
+        println!(
+            "{:>11} | {:>14.2?} | {:>12.2?} | {:>12.2?} | {:>12.2} | {:>9.1}%",
+            stats.concurrency,
+            stats.time_per_run(),
+            stats.fastest_run(),
+            stats.slowest_run(),
+            stats.throughput(),
+            stats.error_rate() * 100.0
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_level_stats() {
+        let stats = LevelStats {
+            concurrency: 4,
+            requests: 48,
+            errors: 6,
+            run_times: [2, 6, 4].map(Duration::from_secs).to_vec(),
+        };
+        assert_eq!(stats.total_time(), Duration::from_secs(12));
+        assert_eq!(stats.time_per_run(), Duration::from_secs(4));
+        assert_eq!(stats.fastest_run(), Duration::from_secs(2));
+        assert_eq!(stats.slowest_run(), Duration::from_secs(6));
+        assert_eq!(stats.throughput(), 4.0);
+        assert_eq!(stats.error_rate(), 0.125);
+    }
+
+    #[test]
+    fn test_level_stats_without_measured_time() {
+        let stats = LevelStats {
+            concurrency: 1,
+            requests: 16,
+            errors: 0,
+            run_times: vec![Duration::ZERO],
+        };
+        assert_eq!(stats.throughput(), 0.0);
+
+        let empty = LevelStats {
+            concurrency: 1,
+            requests: 0,
+            errors: 0,
+            run_times: Vec::new(),
+        };
+        assert_eq!(empty.time_per_run(), Duration::ZERO);
+        assert_eq!(empty.slowest_run(), Duration::ZERO);
+        assert_eq!(empty.throughput(), 0.0);
+        assert_eq!(empty.error_rate(), 0.0);
+    }
+
+    #[test]
+    fn test_parse_repeats() {
+        assert_eq!(parse_repeats(&args(&["demo", "--bench"])).unwrap().get(), DEFAULT_REPEATS);
+        assert_eq!(parse_repeats(&args(&["demo", "--bench", "--repeats", "5"])).unwrap().get(), 5);
+
+        assert!(parse_repeats(&args(&["demo", "--bench", "--repeats", "0"])).is_err());
+        assert!(parse_repeats(&args(&["demo", "--bench", "--repeats"])).is_err());
+        assert!(parse_repeats(&args(&["demo", "--repeats", "--bench"])).is_err());
+    }
+}
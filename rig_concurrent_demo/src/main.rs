@@ -1,3 +1,5 @@
+mod bench;
+
 use rig::providers::openai;  // Import OpenAI provider from Rig
 use rig::completion::Prompt;  // Import Prompt trait for LLM interactions
 use tokio::task;  // Import Tokio's task spawning functionality
@@ -12,6 +14,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create a GPT-3.5-turbo model instance and wrap it in an Arc for thread-safe sharing
     let model = Arc::new(openai_client.model("gpt-3.5-turbo").build());
 
+    // `cargo run -- --bench [--repeats N]` measures throughput at several concurrency levels
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--bench") {
+        let repeats = bench::parse_repeats(&args)?;
+
+        // The same prompt set is used at every level
+        let prompts: Vec<String> = (0..16)
+            .map(|i| format!("Generate a random fact about the number {}", i))
+            .collect();
+
+        let results = bench::run_benchmark(model, &prompts, repeats).await;
+        bench::print_table(&results);
+        return Ok(());
+    }
+
     // Start timing the execution
     let start = Instant::now();
     
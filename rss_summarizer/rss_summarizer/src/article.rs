@@ -0,0 +1,112 @@
+// article.rs
+//
+// Full article text for `--fetch-full`: downloads the page behind each item's link and
+// pulls out the main body, so the model summarizes the article rather than the teaser.
+
+use futures::stream::{self, StreamExt};
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Paragraphs shorter than this are usually captions, bylines or buttons
+const MIN_PARAGRAPH_CHARS: usize = 25;
+/// Pages with less article text than this are treated as failed extractions
+const MIN_ARTICLE_CHARS: usize = 200;
+/// Article text is cut to this length to keep the prompt bounded
+const MAX_ARTICLE_CHARS: usize = 4000;
+
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Extract the main article text from a page, readability-style.
+///
+/// Every paragraph adds its length to its parent element's score; the parent with the
+/// highest score is taken to be the article body and its paragraphs are returned.
+/// Returns `None` when the page doesn't contain enough paragraph text.
+pub fn extract_article_text(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let paragraphs = Selector::parse("p").unwrap();
+
+    let mut scores = HashMap::new();
+    for paragraph in document.select(&paragraphs) {
+        let length = element_text(paragraph).len();
+        if length < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        if let Some(parent) = paragraph.parent() {
+            *scores.entry(parent.id()).or_insert(0) += length;
+        }
+    }
+
+    let (best, _) = scores.into_iter().max_by_key(|(_, score)| *score)?;
+    let container = ElementRef::wrap(document.tree.get(best)?)?;
+
+    let mut text = container
+        .select(&paragraphs)
+        .map(element_text)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    if text.len() < MIN_ARTICLE_CHARS {
+        return None;
+    }
+    if let Some((cut, _)) = text.char_indices().nth(MAX_ARTICLE_CHARS) {
+        text.truncate(cut);
+    }
+    Some(text)
+}
+
+async fn fetch_article_text(client: &reqwest::Client, url: &str, timeout: Duration) -> Option<String> {
+    if url.is_empty() {
+        return None;
+    }
+    let response = client.get(url).timeout(timeout).send().await.ok()?;
+    let html = response.error_for_status().ok()?.text().await.ok()?;
+    extract_article_text(&html)
+}
+
+/// Download and extract the article behind each link, at most `concurrency` at a time.
+///
+/// The result lines up with `links`; entries are `None` where the page couldn't be
+/// fetched within `timeout` or no article text was found.
+pub async fn fetch_articles(
+    client: &reqwest::Client,
+    links: &[String],
+    concurrency: usize,
+    timeout: Duration,
+) -> Vec<Option<String>> {
+    stream::iter(links)
+        .map(|link| fetch_article_text(client, link, timeout))
+        .buffered(concurrency)
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_article_text_picks_densest_block() {
+        let body = "The quick brown fox jumps over the lazy dog again and again. ".repeat(3);
+        let html = format!(
+            "<html><body><nav><p>Home</p><p>About</p></nav>\
+             <div class=\"sidebar\"><p>Subscribe to our newsletter for weekly updates!</p></div>\
+             <article><p>{}</p><p>{}</p></article></body></html>",
+            body, body
+        );
+
+        let text = extract_article_text(&html).unwrap();
+        assert!(text.starts_with("The quick brown fox"));
+        assert!(!text.contains("newsletter"));
+        assert_eq!(text.matches("\n\n").count(), 1);
+
+        assert!(extract_article_text("<p>Too short</p>").is_none());
+    }
+}
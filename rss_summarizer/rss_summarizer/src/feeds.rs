@@ -0,0 +1,75 @@
+// feeds.rs
+//
+// Polls several feeds at once and merges their items into one list, so a single
+// summary covers every feed. Each feed keeps its own `FeedCache`; stories posted to
+// more than one feed are only summarized once.
+
+use crate::fetch::{fetch_rss_feed, FeedCache, FetchConfig, FetchError, FetchOutcome};
+use crate::item::FeedItem;
+use crate::sink::OutputSink;
+use futures::stream::{self, StreamExt};
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// How many feeds are fetched at the same time
+pub const FEED_CONCURRENCY: usize = 4;
+
+/// Which feeds to summarize, how often, and where the summaries go
+#[derive(Debug, Clone)]
+pub struct SummarizerConfig {
+    pub feeds: Vec<String>,
+    pub interval: Duration,
+    pub output: OutputSink,
+    /// Items scored below this are left out of the summary; 0.0 keeps them all
+    pub min_relevance: f32,
+}
+
+/// Fetch every feed, at most `concurrency` at a time.
+///
+/// `caches` lines up with `feeds`, one per feed, and so does the result.
+pub async fn fetch_feeds(
+    client: &reqwest::Client,
+    feeds: &[String],
+    caches: &[FeedCache],
+    config: &FetchConfig,
+    concurrency: usize,
+) -> Vec<Result<FetchOutcome, FetchError>> {
+    stream::iter(feeds.iter().zip(caches))
+        .map(|(url, cache)| fetch_rss_feed(client, url, cache, config))
+        .buffered(concurrency)
+        .collect()
+        .await
+}
+
+/// Combine the items of several feeds, in order, dropping any item whose link
+/// already came up in an earlier one. Items without a link are always kept.
+pub fn merge_feeds(feeds: Vec<Vec<FeedItem>>) -> Vec<FeedItem> {
+    let mut links = HashSet::new();
+    feeds
+        .into_iter()
+        .flatten()
+        .filter(|item| match item.link() {
+            Some(link) => links.insert(link.to_string()),
+            None => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_feeds_drops_duplicate_links() {
+        let hacker_news = ["https://a", "https://b"].map(FeedItem::with_link).to_vec();
+        let lobsters = ["https://b", "https://c"].map(FeedItem::with_link).to_vec();
+        let untitled = vec![FeedItem::default(), FeedItem::default()];
+
+        let merged = merge_feeds(vec![hacker_news, lobsters, untitled]);
+        let links: Vec<_> = merged.iter().map(FeedItem::link).collect();
+        assert_eq!(
+            links,
+            [Some("https://a"), Some("https://b"), Some("https://c"), None, None]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+// fetch.rs
+//
+// Fetches a single feed over HTTP with conditional requests, a timeout and retries.
+// The caller decides when a fetched version of the feed counts as handled: the new
+// `FeedCache` comes back with the items and is only stored once they were summarized.
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use crate::item::{parse_feed, FeedItem, FeedParseError};
+use reqwest::StatusCode;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use tracing::warn;
+
+/// Timeout and retry settings for feed requests
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Limit for a whole request, body included
+    pub timeout: Duration,
+    /// Extra attempts after a timeout or transport failure
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each one after
+    pub backoff: Duration,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl FetchConfig {
+    /// Build an HTTP client that applies `timeout` to every request
+    pub fn client(&self) -> Result<reqwest::Client, FetchError> {
+        reqwest::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(FetchError::Transport)
+    }
+}
+
+/// Why fetching a feed failed
+#[derive(Debug)]
+pub enum FetchError {
+    /// The server didn't answer within the configured timeout
+    Timeout,
+    /// Connection problems or an error status from the server
+    Transport(reqwest::Error),
+    /// The response was neither a valid RSS nor a valid Atom document
+    Parse(FeedParseError),
+}
+
+impl FetchError {
+    /// Timeouts, connection failures and server errors may go away on their own;
+    /// client errors and malformed feeds won't
+    fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Timeout => true,
+            FetchError::Transport(e) => !e.status().is_some_and(|s| s.is_client_error()),
+            FetchError::Parse(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Timeout => write!(f, "request timed out"),
+            FetchError::Transport(e) => write!(f, "transport error: {}", e),
+            FetchError::Parse(e) => write!(f, "invalid feed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Timeout => None,
+            FetchError::Transport(e) => Some(e),
+            FetchError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            FetchError::Timeout
+        } else {
+            FetchError::Transport(e)
+        }
+    }
+}
+
+/// What we remember about a feed between polls, so unchanged feeds can be skipped
+#[derive(Debug, Default, Clone)]
+pub struct FeedCache {
+    /// `ETag` from the last successful fetch, sent back as `If-None-Match`
+    etag: Option<String>,
+    /// `Last-Modified` from the last successful fetch, sent back as `If-Modified-Since`
+    last_modified: Option<String>,
+    /// Hash of the last body, for servers that ignore conditional requests
+    body_hash: Option<u64>,
+}
+
+/// Result of polling a feed
+pub enum FetchOutcome {
+    /// The feed hasn't changed since the previous poll
+    NotModified,
+    /// The feed has new content; these are all of its items. `cache` describes this
+    /// version of the feed. Store it once the items were handled; until then the
+    /// next poll fetches them again.
+    Updated { items: Vec<FeedItem>, cache: FeedCache },
+}
+
+/// Fetch a feed using a conditional GET, retrying transient failures.
+///
+/// A `304 Not Modified` reply, or a full reply whose body is byte-for-byte the same as
+/// the one `cache` was made from (for servers without `ETag`/`Last-Modified`
+/// support), both yield [`FetchOutcome::NotModified`]. `cache` isn't changed; a new
+/// one comes back with [`FetchOutcome::Updated`]. Timeouts and transport errors are
+/// retried up to `config.retries` times with exponential backoff; the timeout itself
+/// comes from the client, see [`FetchConfig::client`].
+pub async fn fetch_rss_feed(
+    client: &reqwest::Client,
+    url: &str,
+    cache: &FeedCache,
+    config: &FetchConfig,
+) -> Result<FetchOutcome, FetchError> {
+    let mut attempt = 0;
+    loop {
+        match fetch_once(client, url, cache).await {
+            Err(e) if e.is_retryable() && attempt < config.retries => {
+                let delay = config.backoff * 2u32.pow(attempt);
+                attempt += 1;
+                warn!("Fetching {} failed ({}), retrying in {:?}", url, e, delay);
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn fetch_once(
+    client: &reqwest::Client,
+    url: &str,
+    cache: &FeedCache,
+) -> Result<FetchOutcome, FetchError> {
+    let mut request = client.get(url);
+    if let Some(etag) = &cache.etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &cache.last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(FetchOutcome::NotModified);
+    }
+    let response = response.error_for_status()?;
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+
+    let body = response.text().await?;
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let body_hash = hasher.finish();
+
+    if cache.body_hash == Some(body_hash) {
+        return Ok(FetchOutcome::NotModified);
+    }
+
+    let items = parse_feed(&body).map_err(FetchError::Parse)?;
+    let cache = FeedCache {
+        etag,
+        last_modified,
+        body_hash: Some(body_hash),
+    };
+    Ok(FetchOutcome::Updated { items, cache })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const FEED: &str = "<rss version=\"2.0\"><channel><title>Test</title><link>https://example.com</link>\
+                        <description>Test</description><item><link>https://example.com/a</link></item>\
+                        </channel></rss>";
+
+    /// Serves one canned reply per connection, in order, and counts the requests.
+    /// A `None` reply drops the connection without answering, like a network blip.
+    async fn serve(replies: Vec<Option<String>>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/rss", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for reply in replies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                if let Some(reply) = reply {
+                    let _ = socket.write_all(reply.as_bytes()).await;
+                }
+            }
+        });
+        (url, requests)
+    }
+
+    fn response(status: &str, body: &str) -> Option<String> {
+        Some(format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        ))
+    }
+
+    fn config() -> FetchConfig {
+        FetchConfig {
+            backoff: Duration::from_millis(1),
+            ..FetchConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_after_a_dropped_connection() {
+        let (url, requests) = serve(vec![None, response("200 OK", FEED)]).await;
+        let config = config();
+        let client = config.client().unwrap();
+
+        let outcome = fetch_rss_feed(&client, &url, &FeedCache::default(), &config).await;
+        match outcome {
+            Ok(FetchOutcome::Updated { items, .. }) => assert_eq!(items[0].link(), Some("https://example.com/a")),
+            _ => panic!("expected the feed on the second attempt"),
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, requests) = serve(vec![response("404 Not Found", ""), response("200 OK", FEED)]).await;
+        let config = config();
+        let client = config.client().unwrap();
+
+        let outcome = fetch_rss_feed(&client, &url, &FeedCache::default(), &config).await;
+        assert!(matches!(outcome, Err(FetchError::Transport(_))));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_feed_is_fetched_again_until_its_cache_is_stored() {
+        let (url, requests) = serve(vec![response("200 OK", FEED); 3]).await;
+        let config = config();
+        let client = config.client().unwrap();
+        let mut cache = FeedCache::default();
+
+        // The first poll's summary fails, so its cache is dropped
+        let outcome = fetch_rss_feed(&client, &url, &cache, &config).await;
+        assert!(matches!(outcome, Ok(FetchOutcome::Updated { .. })));
+
+        // The retry gets the same items again, and this time they are summarized
+        match fetch_rss_feed(&client, &url, &cache, &config).await {
+            Ok(FetchOutcome::Updated { items, cache: new_cache }) => {
+                assert_eq!(items.len(), 1);
+                cache = new_cache;
+            }
+            _ => panic!("expected the feed again after a failed summary"),
+        }
+
+        let outcome = fetch_rss_feed(&client, &url, &cache, &config).await;
+        assert!(matches!(outcome, Ok(FetchOutcome::NotModified)));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,177 @@
+// item.rs
+//
+// One entry of a feed, whatever its format. RSS items and Atom entries are both
+// converted to a `FeedItem`, so the rest of the summarizer doesn't care which kind of
+// feed it polled. Fields a feed leaves out are `None`, like in `rss::Item`.
+
+use atom_syndication::Feed;
+use rss::Channel;
+use std::fmt;
+
+/// A feed entry with the fields the summarizer uses
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeedItem {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    /// Publication date as given by the feed; Atom dates are written as RFC 2822 like RSS
+    pub pub_date: Option<String>,
+    pub description: Option<String>,
+}
+
+impl FeedItem {
+    /// The link, which is what identifies an item across polls and feeds
+    pub fn link(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+
+    /// An item with nothing but a link, as test fixture
+    #[cfg(test)]
+    pub fn with_link(link: &str) -> Self {
+        Self {
+            link: Some(link.to_string()),
+            ..Self::default()
+        }
+    }
+}
+
+impl From<&rss::Item> for FeedItem {
+    fn from(item: &rss::Item) -> Self {
+        Self {
+            title: item.title().map(str::to_string),
+            link: item.link().map(str::to_string),
+            pub_date: item.pub_date().map(str::to_string),
+            description: item.description().map(str::to_string),
+        }
+    }
+}
+
+impl From<&atom_syndication::Entry> for FeedItem {
+    /// Uses the `alternate` link (the default relation) or else the first one, the
+    /// published date or else the updated one, and the summary or else the content
+    fn from(entry: &atom_syndication::Entry) -> Self {
+        let link = entry
+            .links()
+            .iter()
+            .find(|link| link.rel() == "alternate")
+            .or_else(|| entry.links().first())
+            .map(|link| link.href().to_string());
+        let date = entry.published().unwrap_or_else(|| entry.updated());
+        let description = entry
+            .summary()
+            .map(|summary| summary.as_str())
+            .or_else(|| entry.content().and_then(|content| content.value()))
+            .map(str::to_string);
+
+        Self {
+            title: Some(entry.title().as_str().to_string()).filter(|title| !title.is_empty()),
+            link,
+            pub_date: Some(date.to_rfc2822()),
+            description,
+        }
+    }
+}
+
+/// A document that is neither a valid RSS nor a valid Atom feed, with both errors
+#[derive(Debug)]
+pub struct FeedParseError {
+    pub rss: rss::Error,
+    pub atom: atom_syndication::Error,
+}
+
+impl fmt::Display for FeedParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not an RSS feed ({}) nor an Atom feed ({})", self.rss, self.atom)
+    }
+}
+
+impl std::error::Error for FeedParseError {}
+
+/// Parse an RSS or Atom document into its items, trying RSS first
+pub fn parse_feed(body: &str) -> Result<Vec<FeedItem>, FeedParseError> {
+    let rss = match body.parse::<Channel>() {
+        Ok(channel) => return Ok(channel.items().iter().map(FeedItem::from).collect()),
+        Err(e) => e,
+    };
+    match body.parse::<Feed>() {
+        Ok(feed) => Ok(feed.entries().iter().map(FeedItem::from).collect()),
+        Err(atom) => Err(FeedParseError { rss, atom }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rss_document() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0"><channel>
+                <title>Hacker News</title>
+                <link>https://news.ycombinator.com/</link>
+                <description>Links for the intellectually curious</description>
+                <item>
+                    <title>Show HN: A tiny database</title>
+                    <link>https://example.com/db</link>
+                    <pubDate>Mon, 04 Nov 2024 10:00:00 +0000</pubDate>
+                    <description>Comments</description>
+                </item>
+                <item><link>https://example.com/untitled</link></item>
+            </channel></rss>"#;
+
+        let items = parse_feed(rss).unwrap();
+        assert_eq!(
+            items,
+            [
+                FeedItem {
+                    title: Some("Show HN: A tiny database".to_string()),
+                    link: Some("https://example.com/db".to_string()),
+                    pub_date: Some("Mon, 04 Nov 2024 10:00:00 +0000".to_string()),
+                    description: Some("Comments".to_string()),
+                },
+                FeedItem {
+                    link: Some("https://example.com/untitled".to_string()),
+                    ..FeedItem::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_atom_document() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>A Rust Blog</title>
+                <id>urn:uuid:60a76c80-d399-11d9-b93c-0003939e0af6</id>
+                <updated>2024-11-05T09:00:00Z</updated>
+                <entry>
+                    <title>Async closures are stable</title>
+                    <id>urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a</id>
+                    <link rel="edit" href="https://example.com/edit/1"/>
+                    <link href="https://example.com/async-closures"/>
+                    <published>2024-11-04T10:00:00Z</published>
+                    <updated>2024-11-05T09:00:00Z</updated>
+                    <summary>What changed and why</summary>
+                </entry>
+                <entry>
+                    <title>Draft</title>
+                    <id>urn:uuid:2</id>
+                    <updated>2024-11-05T09:00:00Z</updated>
+                    <content type="text">Full text only</content>
+                </entry>
+            </feed>"#;
+
+        let items = parse_feed(atom).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title.as_deref(), Some("Async closures are stable"));
+        assert_eq!(items[0].link(), Some("https://example.com/async-closures"));
+        assert_eq!(items[0].pub_date.as_deref(), Some("Mon, 4 Nov 2024 10:00:00 +0000"));
+        assert_eq!(items[0].description.as_deref(), Some("What changed and why"));
+
+        // Without a link or summary, the updated date and content fill in
+        assert_eq!(items[1].link(), None);
+        assert_eq!(items[1].pub_date.as_deref(), Some("Tue, 5 Nov 2024 09:00:00 +0000"));
+        assert_eq!(items[1].description.as_deref(), Some("Full text only"));
+
+        assert!(parse_feed("<html><body>Not a feed</body></html>").is_err());
+    }
+}
@@ -1,53 +1,32 @@
+mod article;
+mod feeds;
+mod fetch;
+mod item;
+mod seen;
+mod sink;
+mod summary;
+
+use crate::article::fetch_articles;
+use crate::feeds::{fetch_feeds, merge_feeds, SummarizerConfig, FEED_CONCURRENCY};
+use crate::fetch::{FeedCache, FetchConfig, FetchOutcome};
+use crate::item::FeedItem;
+use crate::seen::{SeenItems, DEFAULT_SEEN_TTL_DAYS};
+use crate::sink::{OutputFormat, OutputSink};
+use crate::summary::RssSummary;
+use clap::Parser;
+use examples_common::guard::LimitedExtractor;
+use examples_common::cli::CommonArgs;
+use examples_common::logging::init_tracing;
+use examples_common::shutdown::shutdown_signal;
 use rig::providers::openai::Client;
-use schemars::{JsonSchema, schema_for};
-use serde::{Deserialize, Serialize};
+use schemars::schema_for;
 use chrono::{DateTime, Utc};
-use reqwest;
-use rss::Channel;
 use tokio::time::{self, Duration};
 use std::error::Error;
+use std::future::Future;
 use regex::Regex;
 use std::iter::FromIterator;
-
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-struct SummarizedRssItem {
-    title: String,
-    link: String,
-    #[schemars(with = "String")]
-    pub_date: DateTime<Utc>,
-    summary: String,
-    relevance_score: f32,
-}
-
-#[derive(Debug, Deserialize, JsonSchema, Serialize)]
-struct RssSummary {
-    items: Vec<SummarizedRssItem>,
-    total_count: usize,
-    extraction_time: String, // ISO 8601 formatted string
-    overall_summary: String,
-}
-
-fn pretty_print_summary(summary: &RssSummary) {
-    println!("RSS Feed Summary:");
-    println!("Total Items: {}", summary.total_count);
-    println!("Extraction Time: {}", summary.extraction_time);
-    println!("\nTop Items:");
-    for (i, item) in summary.items.iter().enumerate() {
-        println!("{}. {}", i + 1, item.title);
-        println!("   Link: {}", item.link);
-        println!("   Published: {}", item.pub_date);
-        println!("   Summary: {}", item.summary);
-        println!("   Relevance Score: {:.2}", item.relevance_score);
-        println!();
-    }
-    println!("Overall Summary: {}", summary.overall_summary);
-}
-
-async fn fetch_rss_feed(url: &str) -> Result<Channel, Box<dyn Error>> {
-    let response = reqwest::get(url).await?.text().await?;
-    let channel = response.parse::<Channel>()?;
-    Ok(channel)
-}
+use tracing::{error, info, info_span, warn, Instrument};
 
 fn sanitize_string(input: &str) -> String {
     let mut sanitized = input.to_string();
@@ -58,21 +37,91 @@ fn sanitize_string(input: &str) -> String {
     sanitized
 }
 
-async fn summarize_rss_feed(channel: Channel) -> Result<RssSummary, Box<dyn Error>> {
+const DEFAULT_MODEL: &str = "gpt-4";
+
+/// Periodically summarize one or more RSS feeds
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Feed to summarize; repeat to summarize several feeds together
+    #[arg(long, default_value = "https://news.ycombinator.com/rss")]
+    feed_url: Vec<String>,
+
+    /// Seconds between polls of the feeds
+    #[arg(
+        long,
+        visible_alias = "interval-secs",
+        default_value_t = 3600,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    interval: u64,
+
+    /// Poll and summarize once, then exit, e.g. when run from cron
+    #[arg(long)]
+    once: bool,
+
+    /// Summarize the linked articles instead of the feed's teasers
+    #[arg(long)]
+    fetch_full: bool,
+
+    /// File remembering which items were already summarized, kept between runs
+    #[arg(long, default_value = "seen_items.json")]
+    seen_file: String,
+
+    /// Where summaries go: printed, or saved as JSON or Markdown files
+    #[arg(long, value_enum, default_value_t = OutputFormat::Stdout)]
+    output: OutputFormat,
+
+    /// Directory for the files written by `--output json` or `--output markdown`
+    #[arg(long, default_value = "summaries")]
+    output_dir: String,
+
+    /// Only show items the model scored at least this relevant, from 0.0 to 1.0
+    #[arg(long, default_value_t = 0.0, value_parser = parse_relevance)]
+    min_relevance: f32,
+
+    /// Days an item is remembered after it was last in the feed
+    #[arg(long, default_value_t = DEFAULT_SEEN_TTL_DAYS, value_parser = clap::value_parser!(i64).range(1..))]
+    seen_ttl_days: i64,
+
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+// Relevance scores run from 0.0 to 1.0, so a threshold outside that range (or NaN)
+// would silently keep or drop every item
+fn parse_relevance(value: &str) -> Result<f32, String> {
+    let relevance = value.parse::<f32>().map_err(|e| e.to_string())?;
+    if !(0.0..=1.0).contains(&relevance) {
+        return Err(format!("{} is not between 0.0 and 1.0", value));
+    }
+    Ok(relevance)
+}
+
+// Options for `--fetch-full`: how many article pages to download at once and how long
+// to wait for each
+const ARTICLE_CONCURRENCY: usize = 4;
+const ARTICLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// `articles` holds the full text for each item where it was fetched; the item's
+// description is used for the others
+async fn summarize_rss_feed(
+    rss_items: Vec<FeedItem>,
+    articles: Vec<Option<String>>,
+    model: &str,
+) -> Result<RssSummary, Box<dyn Error>> {
     // Initialize the OpenAI client
     let openai_client = Client::from_env();
 
     // Create the extractor
-    let extractor = openai_client
-        .extractor::<RssSummary>("gpt-4")
-        .preamble("You are an AI assistant specialized in summarizing RSS feeds. \
-                   Your task is to analyze the RSS items, extract the most relevant information, \
-                   and provide concise summaries. For each item, provide a brief summary and a \
-                   relevance score from 0.0 to 1.0. Also, provide an overall summary of the feed.")
-        .build();
-
-    // Convert RSS items to a format suitable for summarization
-    let rss_items = channel.items();
+    let extractor = LimitedExtractor::<_, RssSummary>::new(
+        openai_client.completion_model(model),
+        "You are an AI assistant specialized in summarizing RSS feeds. \
+         Your task is to analyze the RSS items, extract the most relevant information, \
+         and provide concise summaries. For each item, provide a brief summary and a \
+         relevance score from 0.0 to 1.0. Also, provide an overall summary of the feed.",
+    );
+
+    // Convert the feed items to a format suitable for summarization
     let mut formatted_rss = String::new();
 
     // Create regex to remove HTML tags and CDATA sections
@@ -80,14 +129,20 @@ async fn summarize_rss_feed(channel: Channel) -> Result<RssSummary, Box<dyn Erro
     let re_cdata = Regex::new(r"(?i)<!\[CDATA\[.*?\]\]>").unwrap();
 
     for (i, item) in rss_items.iter().enumerate() {
-        let title = item.title().unwrap_or("").to_string();
+        let title = item.title.as_deref().unwrap_or("").to_string();
         let link = item.link().unwrap_or("").to_string();
-        let pub_date = item.pub_date().unwrap_or("").to_string();
-        let description = item.description().unwrap_or("").to_string();
+        let pub_date = item.pub_date.as_deref().unwrap_or("").to_string();
+        let description = item.description.as_deref().unwrap_or("").to_string();
 
-        // Remove CDATA sections and HTML tags
-        let clean_description = re_html.replace_all(&re_cdata.replace_all(&description, ""), "").to_string();
-        let sanitized_description = sanitize_string(&clean_description);
+        // Prefer the full article text, falling back to the description
+        let sanitized_description = match articles.get(i).cloned().flatten() {
+            Some(article) => sanitize_string(&article),
+            None => {
+                // Remove CDATA sections and HTML tags
+                let clean_description = re_html.replace_all(&re_cdata.replace_all(&description, ""), "").to_string();
+                sanitize_string(&clean_description)
+            }
+        };
 
         formatted_rss.push_str(&format!(
             "{}. Title: {}\nLink: {}\nDate: {}\nDescription: {}\n\n",
@@ -99,7 +154,7 @@ async fn summarize_rss_feed(channel: Channel) -> Result<RssSummary, Box<dyn Erro
         ));
     }
 
-    println!("Extracting summary from the RSS feed...\n");
+    info!("Extracting summary from the RSS feed");
 
     // Extract summary
     let rss_summary = extractor.extract(&formatted_rss).await?;
@@ -107,24 +162,231 @@ async fn summarize_rss_feed(channel: Channel) -> Result<RssSummary, Box<dyn Erro
     Ok(rss_summary)
 }
 
+async fn poll_feeds(
+    client: &reqwest::Client,
+    config: &SummarizerConfig,
+    caches: &mut [FeedCache],
+    seen: &mut SeenItems,
+    fetch_config: &FetchConfig,
+    fetch_full: bool,
+    model: &str,
+) {
+    let feeds = &config.feeds;
+    let mut updated = Vec::new();
+    let mut new_caches = Vec::new();
+    for ((i, url), outcome) in feeds
+        .iter()
+        .enumerate()
+        .zip(fetch_feeds(client, feeds, caches, fetch_config, FEED_CONCURRENCY).await)
+    {
+        match outcome {
+            Ok(FetchOutcome::NotModified) => info!("{} unchanged since the last poll", url),
+            Ok(FetchOutcome::Updated { items, cache }) => {
+                updated.push(items);
+                new_caches.push((i, cache));
+            }
+            Err(e) => error!("Error fetching RSS feed {}: {}", url, e),
+        }
+    }
+    if updated.is_empty() {
+        info!("No feed changed since the last poll, skipping summarization");
+        return;
+    }
+    let items = merge_feeds(updated);
+
+    let summary = summarize_unseen(items, seen, Utc::now(), |items| async move {
+        let articles = if fetch_full {
+            let links: Vec<String> = items
+                .iter()
+                .map(|item| item.link().unwrap_or("").to_string())
+                .collect();
+            info!("Fetching full text for {} articles", links.len());
+            fetch_articles(client, &links, ARTICLE_CONCURRENCY, ARTICLE_TIMEOUT).await
+        } else {
+            Vec::new()
+        };
+        summarize_rss_feed(items, articles, model).await
+    })
+    .await;
+    let summary = match summary {
+        Ok(summary) => summary,
+        Err(e) => {
+            // The old caches stay, so the next poll fetches these items again
+            error!("Error summarizing RSS feed: {}", e);
+            return;
+        }
+    };
+    for (i, cache) in new_caches {
+        caches[i] = cache;
+    }
+
+    // A failed write loses this summary but keeps the polling loop going
+    if let Some(mut summary) = summary {
+        summary.filter_by_relevance(config.min_relevance);
+        match config.output.write(&summary, Utc::now()) {
+            Ok(Some(path)) => info!("Summary written to {}", path.display()),
+            Ok(None) => {}
+            Err(e) => error!("Failed to write summary: {}", e),
+        }
+    }
+}
+
+// Hand only the items not summarized before to `summarize`, skipping it when there
+// are none, and remember them once they were summarized. `Ok(None)` means there was
+// nothing new to summarize.
+async fn summarize_unseen<F, Fut>(
+    items: Vec<FeedItem>,
+    seen: &mut SeenItems,
+    now: DateTime<Utc>,
+    summarize: F,
+) -> Result<Option<RssSummary>, Box<dyn Error>>
+where
+    F: FnOnce(Vec<FeedItem>) -> Fut,
+    Fut: Future<Output = Result<RssSummary, Box<dyn Error>>>,
+{
+    let items = seen.unseen(items, now);
+    if items.is_empty() {
+        info!("No new items since the last summary, skipping summarization");
+        save_seen(seen);
+        return Ok(None);
+    }
+    info!("{} new items to summarize", items.len());
+
+    let summary = summarize(items.clone()).await;
+    if summary.is_ok() {
+        seen.mark_seen(&items, now);
+    }
+    save_seen(seen);
+    summary.map(Some)
+}
+
+// A failed save only means some items may be summarized again after a restart
+fn save_seen(seen: &SeenItems) {
+    if let Err(e) = seen.save() {
+        warn!("Failed to save seen items: {}", e);
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let rss_url = "https://news.ycombinator.com/rss";
-    let mut interval = time::interval(Duration::from_secs(3600)); // 1 hour interval
+    let cli = Cli::parse();
+    init_tracing(cli.common.log_level.as_deref());
+    let config = SummarizerConfig {
+        feeds: cli.feed_url.clone(),
+        interval: Duration::from_secs(cli.interval),
+        output: OutputSink {
+            format: cli.output,
+            dir: cli.output_dir.clone().into(),
+        },
+        min_relevance: cli.min_relevance,
+    };
+    let model = cli.common.model_or(DEFAULT_MODEL);
+    let mut interval = time::interval(config.interval);
+    let fetch_config = FetchConfig {
+        timeout: Duration::from_secs(10),
+        retries: 2,
+        backoff: Duration::from_secs(2),
+    };
+    let client = fetch_config.client()?;
+    let mut caches = vec![FeedCache::default(); config.feeds.len()];
+    let mut seen = SeenItems::load(&cli.seen_file, chrono::Duration::days(cli.seen_ttl_days), Utc::now())?;
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
 
     loop {
-        interval.tick().await;
-        
-        match fetch_rss_feed(rss_url).await {
-            Ok(channel) => {
-                match summarize_rss_feed(channel).await {
-                    Ok(rss_summary) => {
-                        pretty_print_summary(&rss_summary);
-                    }
-                    Err(e) => eprintln!("Error summarizing RSS feed: {}", e),
+        // Ctrl-C cancels whatever is in flight, whether waiting for the next tick or polling
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = async {
+                interval.tick().await;
+                poll_feeds(&client, &config, &mut caches, &mut seen, &fetch_config, cli.fetch_full, model)
+                    .instrument(info_span!("poll", feeds = config.feeds.len()))
+                    .await;
+            } => {
+                if cli.once {
+                    break;
                 }
             }
-            Err(e) => eprintln!("Error fetching RSS feed: {}", e),
+        }
+    }
+
+    info!("Shutting down");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_second_run_only_summarizes_new_items() {
+        let path = std::env::temp_dir().join(format!("seen-main-test-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let ttl = chrono::Duration::days(DEFAULT_SEEN_TTL_DAYS);
+        let now = Utc::now();
+        let sent = Mutex::new(Vec::new());
+
+        // Stands in for the extractor, recording which links it was asked about
+        let extractor = |items: Vec<FeedItem>| {
+            let links: Vec<String> = items.iter().filter_map(FeedItem::link).map(str::to_string).collect();
+            sent.lock().unwrap().push(links);
+            async {
+                Ok(RssSummary {
+                    items: Vec::new(),
+                    total_count: 0,
+                    extraction_time: now.to_rfc3339(),
+                    overall_summary: String::new(),
+                })
+            }
+        };
+
+        let feed = |links: [&str; 2]| links.map(FeedItem::with_link).to_vec();
+
+        let mut seen = SeenItems::load(&path, ttl, now).unwrap();
+        summarize_unseen(feed(["https://a", "https://b"]), &mut seen, now, extractor).await.unwrap();
+
+        // A restart later the feed overlaps with the first run
+        let mut seen = SeenItems::load(&path, ttl, now).unwrap();
+        summarize_unseen(feed(["https://b", "https://c"]), &mut seen, now, extractor).await.unwrap();
+        // Nothing new, so the extractor isn't called
+        assert!(summarize_unseen(feed(["https://a", "https://c"]), &mut seen, now, extractor).await.unwrap().is_none());
+
+        assert_eq!(
+            *sent.lock().unwrap(),
+            [vec!["https://a", "https://b"], vec!["https://c"]]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cli_once_and_interval() {
+        let cli = Cli::try_parse_from(["summarizer"]).unwrap();
+        assert_eq!(cli.interval, 3600);
+        assert!(!cli.once);
+
+        let cli = Cli::try_parse_from(["summarizer", "--once", "--interval-secs", "600"]).unwrap();
+        assert_eq!(cli.interval, 600);
+        assert!(cli.once);
+        assert_eq!(Cli::try_parse_from(["summarizer", "--interval", "90"]).unwrap().interval, 90);
+
+        assert!(Cli::try_parse_from(["summarizer", "--interval-secs", "0"]).is_err());
+        assert!(Cli::try_parse_from(["summarizer", "--interval-secs", "-5"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_min_relevance_range() {
+        let min_relevance = |value: &str| {
+            Cli::try_parse_from(["summarizer", "--min-relevance", value]).map(|cli| cli.min_relevance)
+        };
+        assert_eq!(min_relevance("0.5").unwrap(), 0.5);
+        assert_eq!(min_relevance("0").unwrap(), 0.0);
+        assert_eq!(min_relevance("1.0").unwrap(), 1.0);
+
+        for value in ["-0.1", "1.5", "NaN", "high"] {
+            assert!(min_relevance(value).is_err(), "{}", value);
         }
     }
 }
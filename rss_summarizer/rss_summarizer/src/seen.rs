@@ -0,0 +1,118 @@
+// seen.rs
+//
+// Remembers which feed items were already summarized, across restarts, so each poll
+// only sends new items to the model. Entries expire after a TTL so the file stays
+// bounded and a story re-posted months later counts as new again.
+
+use crate::item::FeedItem;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// How long an item is remembered after it was last in the feed
+pub const DEFAULT_SEEN_TTL_DAYS: i64 = 30;
+
+/// Links of summarized items and when each was last seen in the feed, persisted as JSON
+#[derive(Debug)]
+pub struct SeenItems {
+    path: PathBuf,
+    ttl: Duration,
+    last_seen: HashMap<String, DateTime<Utc>>,
+}
+
+impl SeenItems {
+    /// Load the set saved at `path`, dropping entries older than `ttl`. A missing file
+    /// gives an empty set.
+    pub fn load(path: impl Into<PathBuf>, ttl: Duration, now: DateTime<Utc>) -> io::Result<Self> {
+        let path = path.into();
+        let last_seen = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let mut seen = Self { path, ttl, last_seen };
+        seen.prune(now);
+        Ok(seen)
+    }
+
+    /// Write the set back to its file, replacing it in one step so a crash mid-write
+    /// can't leave it half written
+    pub fn save(&self) -> io::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.last_seen)?)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// Forget entries last seen more than the TTL before `now`, returning how many
+    pub fn prune(&mut self, now: DateTime<Utc>) -> usize {
+        let before = self.last_seen.len();
+        let ttl = self.ttl;
+        self.last_seen.retain(|_, last_seen| now - *last_seen <= ttl);
+        let pruned = before - self.last_seen.len();
+        if pruned > 0 {
+            debug!("Forgot {} seen items older than {} days", pruned, ttl.num_days());
+        }
+        pruned
+    }
+
+    /// Keep the items not seen before. Items already seen get their timestamp
+    /// refreshed, so a story that stays in the feed doesn't expire and come back.
+    /// Items without a link can't be tracked and are always kept.
+    pub fn unseen(&mut self, items: Vec<FeedItem>, now: DateTime<Utc>) -> Vec<FeedItem> {
+        self.prune(now);
+        items
+            .into_iter()
+            .filter(|item| match item.link().and_then(|link| self.last_seen.get_mut(link)) {
+                Some(last_seen) => {
+                    *last_seen = now;
+                    false
+                }
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Remember `items` as summarized
+    pub fn mark_seen<'a>(&mut self, items: impl IntoIterator<Item = &'a FeedItem>, now: DateTime<Utc>) {
+        for link in items.into_iter().filter_map(FeedItem::link) {
+            self.last_seen.insert(link.to_string(), now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_items_are_summarized_again() {
+        let path = std::env::temp_dir().join(format!("seen-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let ttl = Duration::days(DEFAULT_SEEN_TTL_DAYS);
+        let start = Utc::now();
+
+        let mut seen = SeenItems::load(&path, ttl, start).unwrap();
+        let fresh = seen.unseen(["https://a", "https://b"].map(FeedItem::with_link).to_vec(), start);
+        assert_eq!(fresh.len(), 2);
+        seen.mark_seen(&fresh, start);
+        seen.save().unwrap();
+
+        // A day later, after a restart, only the new story is unseen
+        let day_later = start + Duration::days(1);
+        let mut seen = SeenItems::load(&path, ttl, day_later).unwrap();
+        let fresh = seen.unseen(["https://a", "https://c"].map(FeedItem::with_link).to_vec(), day_later);
+        assert_eq!(fresh.iter().filter_map(FeedItem::link).collect::<Vec<_>>(), ["https://c"]);
+        seen.save().unwrap();
+
+        // Two months on, every entry has expired and is pruned on load, so "b" is new again
+        let months_later = start + Duration::days(60);
+        let mut seen = SeenItems::load(&path, ttl, months_later).unwrap();
+        assert!(seen.last_seen.is_empty());
+        assert_eq!(seen.unseen(vec![FeedItem::with_link("https://b")], months_later).len(), 1);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
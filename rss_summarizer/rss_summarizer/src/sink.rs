@@ -0,0 +1,129 @@
+// sink.rs
+//
+// Where finished summaries go. Printing to stdout keeps nothing, so a summary can
+// also be saved as JSON (the `RssSummary` itself) or as a Markdown report, one
+// timestamped file per poll.
+
+use crate::summary::{pretty_print_summary, RssSummary};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How summaries are written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Print to the terminal
+    #[default]
+    Stdout,
+    /// Save the summary as JSON
+    Json,
+    /// Save a formatted Markdown report
+    Markdown,
+}
+
+/// An output format and, for the file formats, the directory to write to
+#[derive(Debug, Clone)]
+pub struct OutputSink {
+    pub format: OutputFormat,
+    pub dir: PathBuf,
+}
+
+impl OutputSink {
+    /// Write `summary` out, returning the file it was saved to, if any. The directory
+    /// is created if it doesn't exist yet.
+    pub fn write(&self, summary: &RssSummary, now: DateTime<Utc>) -> io::Result<Option<PathBuf>> {
+        let (extension, contents) = match self.format {
+            OutputFormat::Stdout => {
+                pretty_print_summary(summary);
+                return Ok(None);
+            }
+            OutputFormat::Json => ("json", serde_json::to_string_pretty(summary)?),
+            OutputFormat::Markdown => ("md", format_markdown(summary)),
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        let path = summary_path(&self.dir, now, extension);
+        fs::write(&path, contents)?;
+        Ok(Some(path))
+    }
+}
+
+/// `summary-<UTC time>.<extension>` inside `dir`, so every poll gets its own file
+fn summary_path(dir: &Path, now: DateTime<Utc>, extension: &str) -> PathBuf {
+    dir.join(format!("summary-{}.{}", now.format("%Y%m%dT%H%M%SZ"), extension))
+}
+
+/// Render a summary as a Markdown report with the same fields as the terminal output
+pub fn format_markdown(summary: &RssSummary) -> String {
+    let mut report = format!(
+        "# RSS Feed Summary\n\n_{} items, extracted {}_\n\n{}\n\n## Top Items\n\n",
+        summary.total_count, summary.extraction_time, summary.overall_summary
+    );
+    for (i, item) in summary.items.iter().enumerate() {
+        report.push_str(&format!(
+            "{}. [{}]({})\n   - Published: {}\n   - Relevance: {:.2}\n   - {}\n\n",
+            i + 1,
+            item.title,
+            item.link,
+            item.pub_date,
+            item.relevance_score,
+            item.summary
+        ));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::SummarizedRssItem;
+
+    fn sample_summary(now: DateTime<Utc>) -> RssSummary {
+        RssSummary {
+            items: vec![SummarizedRssItem {
+                title: "Show HN: A tiny database".to_string(),
+                link: "https://example.com/db".to_string(),
+                pub_date: now,
+                summary: "A key-value store in 500 lines.".to_string(),
+                relevance_score: 0.8,
+            }],
+            total_count: 1,
+            extraction_time: now.to_rfc3339(),
+            overall_summary: "Mostly databases today.".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_json_output_reads_back() {
+        let dir = std::env::temp_dir().join(format!("rss-sink-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let now = "2024-11-04T10:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let summary = sample_summary(now);
+
+        // The directory doesn't exist yet and is created
+        let sink = OutputSink {
+            format: OutputFormat::Json,
+            dir: dir.join("summaries"),
+        };
+        let path = sink.write(&summary, now).unwrap().unwrap();
+        assert_eq!(path.file_name().unwrap(), "summary-20241104T100000Z.json");
+
+        let read: RssSummary = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(read.total_count, 1);
+        assert_eq!(read.overall_summary, summary.overall_summary);
+        assert_eq!(read.items[0].link, "https://example.com/db");
+        assert_eq!(read.items[0].pub_date, now);
+
+        let markdown = OutputSink {
+            format: OutputFormat::Markdown,
+            ..sink
+        };
+        let path = markdown.write(&summary, now).unwrap().unwrap();
+        let report = fs::read_to_string(path).unwrap();
+        assert!(report.contains("1. [Show HN: A tiny database](https://example.com/db)"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
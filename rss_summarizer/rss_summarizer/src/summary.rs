@@ -0,0 +1,96 @@
+// summary.rs
+//
+// What the model returns for a batch of feed items, and how it's shown. Shared by the
+// polling loop in main.rs and the output sinks.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+pub struct SummarizedRssItem {
+    pub title: String,
+    pub link: String,
+    #[schemars(with = "String")]
+    pub pub_date: DateTime<Utc>,
+    pub summary: String,
+    pub relevance_score: f32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct RssSummary {
+    pub items: Vec<SummarizedRssItem>,
+    pub total_count: usize,
+    pub extraction_time: String, // ISO 8601 formatted string
+    pub overall_summary: String,
+}
+
+impl RssSummary {
+    /// Drop items scored below `min_relevance` and list the rest most relevant first.
+    /// `total_count` becomes the number of items kept; a threshold of 0.0 or less
+    /// keeps every item.
+    pub fn filter_by_relevance(&mut self, min_relevance: f32) {
+        if min_relevance > 0.0 {
+            self.items.retain(|item| item.relevance_score >= min_relevance);
+            self.total_count = self.items.len();
+        }
+        self.items.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    }
+}
+
+pub fn pretty_print_summary(summary: &RssSummary) {
+    println!("RSS Feed Summary:");
+    println!("Total Items: {}", summary.total_count);
+    println!("Extraction Time: {}", summary.extraction_time);
+    println!("\nTop Items:");
+    for (i, item) in summary.items.iter().enumerate() {
+        println!("{}. {}", i + 1, item.title);
+        println!("   Link: {}", item.link);
+        println!("   Published: {}", item.pub_date);
+        println!("   Summary: {}", item.summary);
+        println!("   Relevance Score: {:.2}", item.relevance_score);
+        println!();
+    }
+    println!("Overall Summary: {}", summary.overall_summary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scored(scores: &[f32]) -> RssSummary {
+        RssSummary {
+            items: scores
+                .iter()
+                .map(|&relevance_score| SummarizedRssItem {
+                    title: format!("Story {}", relevance_score),
+                    link: String::new(),
+                    pub_date: Utc::now(),
+                    summary: String::new(),
+                    relevance_score,
+                })
+                .collect(),
+            total_count: scores.len(),
+            extraction_time: String::new(),
+            overall_summary: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_filter_by_relevance() {
+        let scores = [0.3, 0.9, 0.5, 0.1, 0.7];
+
+        let mut summary = scored(&scores);
+        summary.filter_by_relevance(0.5);
+        let kept: Vec<f32> = summary.items.iter().map(|item| item.relevance_score).collect();
+        assert_eq!(kept, [0.9, 0.7, 0.5]);
+        assert_eq!(summary.total_count, 3);
+
+        // No threshold keeps everything, still sorted
+        let mut summary = scored(&scores);
+        summary.filter_by_relevance(0.0);
+        assert_eq!(summary.total_count, 5);
+        assert_eq!(summary.items[0].relevance_score, 0.9);
+        assert_eq!(summary.items[4].relevance_score, 0.1);
+    }
+}
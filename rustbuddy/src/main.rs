@@ -1,11 +1,18 @@
 use std::error::Error;
+use std::fs;
 use std::io;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyModifiers,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use examples_common::openai::openai_client_from_env;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout},
@@ -14,10 +21,12 @@ use ratatui::{
     widgets::{Block, Borders, Paragraph, Wrap},
     Frame, Terminal,
 };
-use rig::completion::Chat;
+use rig::completion::{Chat, Message, PromptError};
 use rig::embeddings::EmbeddingsBuilder;
 use rig::providers::openai;
-use rig::vector_store::{in_memory_store::InMemoryVectorStore, VectorStore};
+use rig::vector_store::in_memory_store::{InMemoryVectorIndex, InMemoryVectorStore};
+use rig::vector_store::{VectorStore, VectorStoreIndex};
+use tokio::task::JoinHandle;
 
 const RUST_DOCS: &[(&str, &str)] = &[
     ("compilation error", "Rust compilation errors occur when the code doesn't meet the language's rules. Common causes include syntax errors, type mismatches, and borrowing rule violations."),
@@ -42,12 +51,180 @@ const RUST_DOCS: &[(&str, &str)] = &[
     ("cargo", "Cargo is Rust's package manager and build system, used for managing dependencies and building projects."),
 ];
 
+/// How often the screen is redrawn while waiting for a key, which animates the spinner
+const TICK: Duration = Duration::from_millis(100);
+/// Most lines the input box grows to before it scrolls
+const MAX_INPUT_LINES: u16 = 8;
+/// How long a status message such as "Saved to ..." stays on screen
+const STATUS_DURATION: Duration = Duration::from_secs(3);
+/// How many documents are retrieved for each question
+const CONTEXT_DOCUMENTS: usize = 3;
+/// Longest snippet of a retrieved document shown in the Sources panel
+const SNIPPET_CHARS: usize = 160;
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+type BuddyAgent = rig::rag::RagAgent<openai::CompletionModel, InMemoryVectorStore, InMemoryVectorStore>;
+type BuddyIndex = InMemoryVectorIndex<openai::EmbeddingModel>;
+
+/// A document retrieved for the last question
+struct Source {
+    key: String,
+    score: f64,
+    snippet: String,
+}
+
+/// The start of `text` on one line, at most `max_chars` long, with an ellipsis if cut
+fn snippet(text: &str, max_chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+    let cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    format!("{}…", cut.trim_end())
+}
+
+/// The documents the agent's dynamic context will see for `query`. This repeats the
+/// agent's own lookup, since the agent doesn't say what it retrieved.
+async fn retrieve_sources(index: &BuddyIndex, query: &str) -> Vec<Source> {
+    let Ok(results) = index.top_n_from_query(query, CONTEXT_DOCUMENTS).await else {
+        return Vec::new();
+    };
+    results
+        .into_iter()
+        .map(|(score, doc)| {
+            let text = match &doc.document {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            Source {
+                key: doc.id,
+                score,
+                snippet: snippet(&text, SNIPPET_CHARS),
+            }
+        })
+        .collect()
+}
+
+/// Directory of extra notes to load instead of `RUST_DOCS`, unless `RUSTBUDDY_DOCS_DIR` is set
+const DEFAULT_DOCS_DIR: &str = "docs";
+
+/// Load every `.md` and `.txt` file in `dir` as a `(key, document)` pair, sorted by
+/// file name. The key is the file's first Markdown heading, or else its name without
+/// the extension. Empty and unreadable files are skipped with a warning. Returns
+/// `None` if the directory can't be read or holds no usable files.
+fn load_docs(dir: &Path) -> Option<Vec<(String, String)>> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && matches!(path.extension().and_then(|ext| ext.to_str()), Some("md" | "txt"))
+        })
+        .collect();
+    paths.sort();
+
+    let docs: Vec<(String, String)> = paths
+        .iter()
+        .filter_map(|path| {
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", path.display(), e);
+                    return None;
+                }
+            };
+            if contents.trim().is_empty() {
+                eprintln!("Skipping {}: file is empty", path.display());
+                return None;
+            }
+            let heading = contents
+                .lines()
+                .find_map(|line| line.strip_prefix('#'))
+                .map(|heading| heading.trim_start_matches('#').trim().to_string())
+                .filter(|heading| !heading.is_empty());
+            let key = heading.unwrap_or_else(|| {
+                path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+            });
+            Some((key, contents))
+        })
+        .collect();
+
+    (!docs.is_empty()).then_some(docs)
+}
+
+/// Write `history` to a new `rustbuddy-<unix time>.json` in `dir`, returning its path
+fn save_history(history: &[String], dir: &Path, now: SystemTime) -> io::Result<PathBuf> {
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("rustbuddy-{}.json", secs));
+    fs::write(&path, serde_json::to_string_pretty(history)?)?;
+    Ok(path)
+}
+
+/// Read a chat history written by `save_history`
+fn load_history(path: &Path) -> io::Result<Vec<String>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// The questions and answers in `chat_history` as messages for the agent, so it remembers
+/// the conversation. Failed and cancelled answers are left out.
+fn agent_history(chat_history: &[String]) -> Vec<Message> {
+    chat_history
+        .iter()
+        .filter_map(|message| {
+            let (role, content) = if let Some(question) = message.strip_prefix("You: ") {
+                ("user", question)
+            } else {
+                ("assistant", message.strip_prefix("RustBuddy: ")?)
+            };
+            (content != "(cancelled)").then(|| Message {
+                role: role.to_string(),
+                content: content.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The file given with `--load <file>`, if any
+fn load_flag(mut args: impl Iterator<Item = String>) -> Result<Option<PathBuf>, String> {
+    let mut load = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--load" => match args.next() {
+                Some(path) => load = Some(PathBuf::from(path)),
+                None => return Err("--load needs a file".to_string()),
+            },
+            _ => return Err(format!("unexpected argument '{}'; usage: rustbuddy [--load <file>]", arg)),
+        }
+    }
+    Ok(load)
+}
+
 struct App {
     input: String,
     output: String,
     chat_history: Vec<String>,
+    /// First line of the chat history shown in the Messages box
+    scroll: u16,
+    /// Largest useful `scroll`, as of the last draw
+    max_scroll: u16,
+    /// Lines visible in the Messages box, as of the last draw; used for PageUp/PageDown
+    page_height: u16,
+    /// Keep the newest message in view; cleared when the user scrolls up
+    follow: bool,
     input_mode: InputMode,
-    rag_agent: rig::rag::RagAgent<openai::CompletionModel, InMemoryVectorStore, InMemoryVectorStore>,
+    rag_agent: Arc<BuddyAgent>,
+    /// Same documents as the agent's dynamic context, queried to show the sources
+    index: Arc<BuddyIndex>,
+    /// The request in flight, if any; input stays disabled until it finishes
+    pending: Option<JoinHandle<(Result<String, PromptError>, Vec<Source>)>>,
+    /// Documents retrieved for the last answer
+    sources: Vec<Source>,
+    /// Whether the Sources panel is shown next to the messages
+    show_sources: bool,
+    /// Advances on every redraw to animate the spinner
+    spinner_frame: usize,
+    /// A message shown under the help line until `STATUS_DURATION` after the given time
+    status: Option<(String, Instant)>,
 }
 
 enum InputMode {
@@ -56,48 +233,187 @@ enum InputMode {
 }
 
 impl App {
-    fn new(rag_agent: rig::rag::RagAgent<openai::CompletionModel, InMemoryVectorStore, InMemoryVectorStore>) -> App {
+    fn new(rag_agent: BuddyAgent, index: BuddyIndex) -> App {
         App {
             input: String::new(),
             output: String::new(),
             chat_history: Vec::new(),
+            scroll: 0,
+            max_scroll: 0,
+            page_height: 0,
+            follow: true,
             input_mode: InputMode::Normal,
-            rag_agent,
+            rag_agent: Arc::new(rag_agent),
+            index: Arc::new(index),
+            pending: None,
+            sources: Vec::new(),
+            show_sources: true,
+            spinner_frame: 0,
+            status: None,
+        }
+    }
+
+    /// Send `input` to the agent in the background, so the UI keeps drawing
+    fn send(&mut self, input: String) {
+        let history = agent_history(&self.chat_history);
+        self.push_message(format!("You: {}", input));
+        let agent = Arc::clone(&self.rag_agent);
+        let index = Arc::clone(&self.index);
+        self.pending = Some(tokio::spawn(async move {
+            let (sources, response) =
+                tokio::join!(retrieve_sources(&index, &input), agent.chat(&input, history));
+            (response, sources)
+        }));
+    }
+
+    /// Save the chat history to the current directory
+    fn save(&mut self) {
+        let status = match save_history(&self.chat_history, Path::new("."), SystemTime::now()) {
+            Ok(path) => format!("Saved to {}", path.display()),
+            Err(e) => format!("Couldn't save the conversation: {}", e),
+        };
+        self.set_status(status);
+    }
+
+    /// Replace the chat history with the one saved in `path`; later questions are sent
+    /// with it, so the agent picks up where it left off
+    fn load(&mut self, path: &Path) {
+        let status = match load_history(path) {
+            Ok(history) => {
+                self.chat_history = history;
+                self.follow = true;
+                format!("Loaded {}", path.display())
+            }
+            Err(e) => format!("Couldn't load {}: {}", path.display(), e),
+        };
+        self.set_status(status);
+    }
+
+    fn set_status(&mut self, status: String) {
+        self.status = Some((status, Instant::now()));
+    }
+
+    /// Show the answer once the pending request is done
+    async fn collect_response(&mut self) {
+        if !self.pending.as_ref().is_some_and(JoinHandle::is_finished) {
+            return;
+        }
+        let Some(handle) = self.pending.take() else { return };
+        let message = match handle.await {
+            Ok((response, sources)) => {
+                self.sources = sources;
+                match response {
+                    Ok(response) => {
+                        self.output = response.clone();
+                        format!("RustBuddy: {}", response)
+                    }
+                    Err(e) => format!("RustBuddy failed to answer: {}", e),
+                }
+            }
+            Err(e) => format!("RustBuddy failed to answer: {}", e),
+        };
+        self.push_message(message);
+    }
+
+    /// Stop waiting for the pending request, if any
+    fn cancel(&mut self) {
+        if let Some(handle) = self.pending.take() {
+            handle.abort();
+            self.push_message("RustBuddy: (cancelled)".to_string());
         }
     }
+
+    fn scroll_up(&mut self, lines: u16) {
+        self.scroll = self.scroll.saturating_sub(lines);
+        self.follow = self.scroll >= self.max_scroll;
+    }
+
+    fn scroll_down(&mut self, lines: u16) {
+        self.scroll = self.scroll.saturating_add(lines).min(self.max_scroll);
+        self.follow = self.scroll >= self.max_scroll;
+    }
+
+    /// Add a message and jump to the bottom so it is visible
+    fn push_message(&mut self, message: String) {
+        self.chat_history.push(message);
+        self.follow = true;
+    }
+}
+
+/// Break `input` into the lines shown in the input box: at each newline, and every
+/// `width` characters. A trailing newline gives an empty last line for the cursor.
+fn wrap_input(input: &str, width: u16) -> Vec<String> {
+    let width = width.max(1) as usize;
+    input
+        .split('\n')
+        .flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+            chars.chunks(width).map(|chunk| chunk.iter().collect()).collect()
+        })
+        .collect()
+}
+
+/// Roughly how many lines `messages` take when wrapped to `width` columns. Word
+/// wrapping can add a line here and there, so this is an estimate for scrolling.
+fn wrapped_line_count(messages: &[String], width: u16) -> u16 {
+    let width = width.max(1) as usize;
+    let lines: usize = messages
+        .iter()
+        .flat_map(|message| message.lines())
+        .map(|line| line.chars().count().max(1).div_ceil(width))
+        .sum();
+    lines.min(u16::MAX as usize) as u16
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let load = load_flag(std::env::args().skip(1))?;
+
     // Initialize OpenAI client
-    let openai_client = openai::Client::from_env();
+    let openai_client =
+        openai_client_from_env(openai::Client::from_env, openai::Client::from_url);
 
     // Create embedding model and vector store
     let embedding_model = openai_client.embedding_model("text-embedding-ada-002");
     let mut vector_store = InMemoryVectorStore::default();
 
-    // Populate vector store with Rust documentation
+    // Load the user's notes, falling back to the built-in Rust documentation
+    let docs_dir = std::env::var("RUSTBUDDY_DOCS_DIR").unwrap_or_else(|_| DEFAULT_DOCS_DIR.to_string());
+    let docs = load_docs(Path::new(&docs_dir)).unwrap_or_else(|| {
+        RUST_DOCS.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    });
+
+    // Populate vector store with the documents
     let embeddings = EmbeddingsBuilder::new(embedding_model.clone())
-        .documents(RUST_DOCS.iter().map(|(k, v)| (k.to_string(), v.to_string(), vec![v.to_string()])).collect())
+        .documents(docs.into_iter().map(|(k, v)| (k, v.clone(), vec![v])).collect())
         .build()
         .await?;
     vector_store.add_documents(embeddings).await?;
 
+    // A second index over the same documents shows which ones each answer drew on
+    let index = vector_store.clone().index(embedding_model.clone());
+
     // Create RAG agent
     let rag_agent = openai_client.context_rag_agent("gpt-4")
         .preamble("You are RustBuddy, an AI assistant specialized in explaining Rust compilation errors and suggesting fixes. Provide clear, concise, and accurate explanations. Format your response in Markdown.")
-        .dynamic_context(3, vector_store.index(embedding_model))
+        .dynamic_context(CONTEXT_DOCUMENTS, vector_store.index(embedding_model))
         .build();
 
     // Set up terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
     // Create app state
-    let mut app = App::new(rag_agent);
+    let mut app = App::new(rag_agent, index);
+    if let Some(path) = load {
+        app.load(&path);
+    }
 
     // Run the main loop
     run_app(&mut terminal, &mut app).await?;
@@ -107,7 +423,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
@@ -116,45 +433,79 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 async fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
     loop {
+        app.collect_response().await;
+        app.spinner_frame = app.spinner_frame.wrapping_add(1);
+        if app.status.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= STATUS_DURATION) {
+            app.status = None;
+        }
         terminal.draw(|f| ui(f, app))?;
 
-        if let Event::Key(key) = event::read()? {
-            match app.input_mode {
-                InputMode::Normal => match key.code {
-                    KeyCode::Char('e') => {
-                        app.input_mode = InputMode::Editing;
-                    }
-                    KeyCode::Char('q') => {
-                        return Ok(());
-                    }
-                    _ => {}
-                },
-                InputMode::Editing => match key.code {
-                    KeyCode::Enter => {
-                        let input = app.input.drain(..).collect();
-                        app.chat_history.push(format!("You: {}", input));
-                        let response = app.rag_agent.chat(&input, vec![]).await.unwrap();
-                        app.chat_history.push(format!("RustBuddy: {}", response));
-                        app.output = response;
-                        app.input_mode = InputMode::Normal;
-                    }
-                    KeyCode::Char(c) => {
-                        app.input.push(c);
-                    }
-                    KeyCode::Backspace => {
-                        app.input.pop();
-                    }
-                    KeyCode::Esc => {
-                        app.input_mode = InputMode::Normal;
-                    }
-                    _ => {}
-                },
+        // Wait for a key only briefly, so the spinner moves and responses show up
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            // Pasted text arrives whole, so its newlines don't submit it
+            Event::Paste(text) => {
+                if let InputMode::Editing = app.input_mode {
+                    app.input.push_str(&text.replace("\r\n", "\n").replace('\r', "\n"));
+                }
+                continue;
             }
+            _ => continue,
+        };
+        match app.input_mode {
+            InputMode::Normal => match key.code {
+                KeyCode::Char('e') if app.pending.is_none() => {
+                    app.input_mode = InputMode::Editing;
+                }
+                KeyCode::Char('q') => {
+                    app.cancel();
+                    return Ok(());
+                }
+                KeyCode::Char('s') => app.save(),
+                KeyCode::Char('c') => app.show_sources = !app.show_sources,
+                KeyCode::Esc => app.cancel(),
+                KeyCode::Up => app.scroll_up(1),
+                KeyCode::Down => app.scroll_down(1),
+                KeyCode::PageUp => app.scroll_up(app.page_height.max(1)),
+                KeyCode::PageDown => app.scroll_down(app.page_height.max(1)),
+                _ => {}
+            },
+            InputMode::Editing => match key.code {
+                // Shift+Enter, or Alt+Enter where the terminal can't report Shift
+                KeyCode::Enter
+                    if key.modifiers.intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) =>
+                {
+                    app.input.push('\n');
+                }
+                KeyCode::Enter => {
+                    let input = app.input.drain(..).collect();
+                    app.send(input);
+                    app.input_mode = InputMode::Normal;
+                }
+                KeyCode::Char(c) => {
+                    app.input.push(c);
+                }
+                KeyCode::Backspace => {
+                    app.input.pop();
+                }
+                KeyCode::Esc => {
+                    app.input_mode = InputMode::Normal;
+                }
+                _ => {}
+            },
         }
     }
 }
 
-fn ui<B: ratatui::backend::Backend>(f: &mut Frame<B>, app: &App) {
+fn ui<B: ratatui::backend::Backend>(f: &mut Frame<B>, app: &mut App) {
+    // The input box grows with its text: the margins and borders take 6 columns
+    let input_width = f.size().width.saturating_sub(6);
+    let input_lines = wrap_input(&app.input, input_width);
+    let visible_input_lines = (input_lines.len() as u16).clamp(1, MAX_INPUT_LINES);
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
@@ -162,20 +513,38 @@ fn ui<B: ratatui::backend::Backend>(f: &mut Frame<B>, app: &App) {
             [
                 Constraint::Length(3),
                 Constraint::Min(1),
-                Constraint::Length(3),
+                Constraint::Length(visible_input_lines + 2),
             ]
             .as_ref(),
         )
         .split(f.size());
 
     let (msg, style) = match app.input_mode {
+        InputMode::Normal if app.pending.is_some() => (
+            vec![
+                Span::raw("Press "),
+                Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to exit, "),
+                Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to cancel the request, "),
+                Span::styled("↑/↓ PgUp/PgDn", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to scroll."),
+            ],
+            Style::default(),
+        ),
         InputMode::Normal => (
             vec![
                 Span::raw("Press "),
                 Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to exit, "),
                 Span::styled("e", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to start editing."),
+                Span::raw(" to start editing, "),
+                Span::styled("s", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to save, "),
+                Span::styled("c", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to show/hide sources, "),
+                Span::styled("↑/↓ PgUp/PgDn", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" to scroll."),
             ],
             Style::default().add_modifier(Modifier::RAPID_BLINK),
         ),
@@ -185,22 +554,32 @@ fn ui<B: ratatui::backend::Backend>(f: &mut Frame<B>, app: &App) {
                 Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
                 Span::raw(" to stop editing, "),
                 Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" to submit."),
+                Span::raw(" to submit, "),
+                Span::styled("Shift+Enter", Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" for a new line."),
             ],
             Style::default(),
         ),
     };
     let mut text = Text::from(Spans::from(msg));
     text.patch_style(style);
-    let help_message = Paragraph::new(text);
+    if let Some((status, _)) = &app.status {
+        text.extend(Text::styled(status.as_str(), Style::default().fg(Color::Green)));
+    }
+    let help_message = Paragraph::new(text).wrap(Wrap { trim: true });
     f.render_widget(help_message, chunks[0]);
 
-    let input = Paragraph::new(app.input.as_ref())
+    // Keep the end of the input, where the cursor is, in view
+    let hidden_input_lines = input_lines.len() as u16 - visible_input_lines;
+    let last_line = input_lines.last().map_or(0, |line| line.chars().count() as u16);
+    let input_text: Vec<Spans> = input_lines.iter().map(|line| Spans::from(line.as_str())).collect();
+    let input = Paragraph::new(input_text)
         .style(match app.input_mode {
             InputMode::Normal => Style::default(),
             InputMode::Editing => Style::default().fg(Color::Yellow),
         })
-        .block(Block::default().borders(Borders::ALL).title("Input"));
+        .block(Block::default().borders(Borders::ALL).title("Input"))
+        .scroll((hidden_input_lines, 0));
     f.render_widget(input, chunks[2]);
     match app.input_mode {
         InputMode::Normal =>
@@ -210,22 +589,141 @@ fn ui<B: ratatui::backend::Backend>(f: &mut Frame<B>, app: &App) {
         InputMode::Editing => {
             // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
             f.set_cursor(
-                // Put cursor at the end of the input text
-                chunks[2].x + app.input.len() as u16 + 1,
-                // Move one line down, from the border to the input line
-                chunks[2].y + 1,
+                // Put cursor at the end of the last input line, inside the border
+                chunks[2].x + last_line.min(input_width.saturating_sub(1)) + 1,
+                chunks[2].y + visible_input_lines,
             )
         }
     }
 
-    let messages: Vec<Spans> = app
+    // The Sources panel takes the right third of the middle, once there is something in it
+    let (messages_area, sources_area) = if app.show_sources && !app.sources.is_empty() {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+            .split(chunks[1]);
+        (columns[0], Some(columns[1]))
+    } else {
+        (chunks[1], None)
+    };
+
+    // Inside the borders of the Messages box
+    let width = messages_area.width.saturating_sub(2);
+    app.page_height = messages_area.height.saturating_sub(2);
+    // The typing line is one more line at the bottom
+    let typing_lines = u16::from(app.pending.is_some());
+    app.max_scroll = wrapped_line_count(&app.chat_history, width)
+        .saturating_add(typing_lines)
+        .saturating_sub(app.page_height);
+    app.scroll = if app.follow {
+        app.max_scroll
+    } else {
+        app.scroll.min(app.max_scroll)
+    };
+
+    let mut messages: Vec<Spans> = app
         .chat_history
         .iter()
         .map(|m| Spans::from(Span::styled(m, Style::default().add_modifier(Modifier::BOLD))))
         .collect();
+    if app.pending.is_some() {
+        let spinner = SPINNER[app.spinner_frame % SPINNER.len()];
+        messages.push(Spans::from(Span::styled(
+            format!("{} RustBuddy is typing…", spinner),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
     let messages =
         Paragraph::new(messages)
             .block(Block::default().borders(Borders::ALL).title("Messages"))
+            .wrap(Wrap { trim: true })
+            .scroll((app.scroll, 0));
+    f.render_widget(messages, messages_area);
+
+    if let Some(area) = sources_area {
+        let mut sources: Vec<Spans> = Vec::new();
+        for source in &app.sources {
+            sources.push(Spans::from(Span::styled(
+                format!("{} ({:.2})", source.key, source.score),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            sources.push(Spans::from(Span::styled(
+                source.snippet.as_str(),
+                Style::default().fg(Color::DarkGray),
+            )));
+            sources.push(Spans::from(""));
+        }
+        let sources = Paragraph::new(sources)
+            .block(Block::default().borders(Borders::ALL).title("Sources"))
             .wrap(Wrap { trim: true });
-    f.render_widget(messages, chunks[1]);
-}
\ No newline at end of file
+        f.render_widget(sources, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_docs_from_directory() {
+        let dir = std::env::temp_dir().join(format!("rustbuddy-docs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert!(load_docs(&dir).is_none());
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pinning.md"), "Intro\n\n## Pin and Unpin\nPinned values can't move.").unwrap();
+        fs::write(dir.join("send.txt"), "Send types can move between threads.").unwrap();
+        fs::write(dir.join("empty.md"), "  \n").unwrap();
+        fs::write(dir.join("image.png"), "not text").unwrap();
+
+        let docs = load_docs(&dir).unwrap();
+        let keys: Vec<&str> = docs.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["Pin and Unpin", "send"]);
+        assert_eq!(docs[1].1, "Send types can move between threads.");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_wrap_input_splits_newlines_and_long_lines() {
+        assert_eq!(wrap_input("", 10), [""]);
+        assert_eq!(wrap_input("fn main() {}", 5), ["fn ma", "in() ", "{}"]);
+        assert_eq!(wrap_input("one\n\ntwo\n", 10), ["one", "", "two", ""]);
+        assert_eq!(wrap_input("héllo", 0), ["h", "é", "l", "l", "o"]);
+    }
+
+    #[test]
+    fn test_save_and_load_history() {
+        let dir = std::env::temp_dir().join(format!("rustbuddy-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let history = vec![
+            "You: Why does this move?\nlet b = a;".to_string(),
+            "RustBuddy: `a` isn't `Copy`.".to_string(),
+            "You: And now?".to_string(),
+            "RustBuddy: (cancelled)".to_string(),
+            "RustBuddy failed to answer: timeout".to_string(),
+        ];
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let path = save_history(&history, &dir, now).unwrap();
+        assert_eq!(path.file_name().unwrap(), "rustbuddy-1700000000.json");
+        assert_eq!(load_history(&path).unwrap(), history);
+
+        let messages = agent_history(&history);
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+        assert_eq!(messages[1].content, "`a` isn't `Copy`.");
+
+        fs::write(&path, "not json").unwrap();
+        assert!(load_history(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_snippet_is_one_short_line() {
+        assert_eq!(snippet("Borrowing   rules\n\napply.", 40), "Borrowing rules apply.");
+        assert_eq!(snippet("The borrow checker", 10), "The borro…");
+        assert_eq!(snippet("Lifetimes are named regions", 10), "Lifetimes…");
+        assert_eq!(snippet("", 10), "");
+    }
+}
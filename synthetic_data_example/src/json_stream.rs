@@ -0,0 +1,215 @@
+use futures::stream::{self, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+/// Incrementally parses the elements of a JSON array as text arrives.
+///
+/// Feed chunks with [`push`](Self::push); every element completed by a chunk is parsed
+/// and returned right away. Anything before the opening `[` (prose, a code fence) is
+/// skipped. An element that fails to parse is reported on its own without affecting
+/// the rest, and if the input stops mid-array, everything completed so far has already
+/// been returned.
+pub struct JsonArrayParser<T> {
+    buffer: String,
+    /// Next byte of `buffer` to scan
+    pos: usize,
+    /// Start of the element currently being scanned, if any
+    element_start: Option<usize>,
+    started: bool,
+    finished: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Default for JsonArrayParser<T> {
+    fn default() -> Self {
+        Self {
+            buffer: String::new(),
+            pos: 0,
+            element_start: None,
+            started: false,
+            finished: false,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> JsonArrayParser<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk of text and return the elements it completed
+    pub fn push(&mut self, chunk: &str) -> Vec<Result<T, serde_json::Error>> {
+        let mut elements = Vec::new();
+        if self.finished {
+            return elements;
+        }
+        self.buffer.push_str(chunk);
+
+        // Structural characters are ASCII, so scanning bytes never splits a UTF-8 character
+        let bytes = self.buffer.as_bytes();
+        while self.pos < bytes.len() {
+            let c = bytes[self.pos];
+
+            if !self.started {
+                self.started = c == b'[';
+                self.pos += 1;
+                continue;
+            }
+
+            let Some(start) = self.element_start else {
+                match c {
+                    b']' => {
+                        self.finished = true;
+                        break;
+                    }
+                    b',' => {}
+                    c if c.is_ascii_whitespace() => {}
+                    _ => {
+                        // Scan this byte again as the first byte of the element
+                        self.element_start = Some(self.pos);
+                        continue;
+                    }
+                }
+                self.pos += 1;
+                continue;
+            };
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == b'\\' {
+                    self.escaped = true;
+                } else if c == b'"' {
+                    self.in_string = false;
+                }
+            } else {
+                match c {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' => self.depth += 1,
+                    b'}' | b']' if self.depth > 0 => {
+                        self.depth -= 1;
+                        if self.depth == 0 {
+                            elements.push(serde_json::from_str(&self.buffer[start..=self.pos]));
+                            self.element_start = None;
+                        }
+                    }
+                    // A scalar element ends at the next separator outside any object or array
+                    b',' | b']' if self.depth == 0 => {
+                        elements.push(serde_json::from_str(self.buffer[start..self.pos].trim()));
+                        self.element_start = None;
+                        if c == b']' {
+                            self.finished = true;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            self.pos += 1;
+        }
+
+        // Drop the text that has been fully consumed
+        let consumed = self.element_start.unwrap_or(self.pos);
+        self.buffer.drain(..consumed);
+        self.pos -= consumed;
+        if let Some(start) = self.element_start.as_mut() {
+            *start = 0;
+        }
+
+        elements
+    }
+}
+
+/// Turn a stream of text chunks holding a JSON array into a stream of its elements
+pub fn parse_json_array<T, S>(chunks: S) -> impl Stream<Item = Result<T, serde_json::Error>>
+where
+    T: DeserializeOwned,
+    S: Stream<Item = String>,
+{
+    chunks
+        .scan(JsonArrayParser::new(), |parser, chunk| {
+            futures::future::ready(Some(stream::iter(parser.push(&chunk))))
+        })
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        name: String,
+    }
+
+    #[test]
+    fn test_parses_elements_across_chunks_and_keeps_them_when_truncated() {
+        let mut parser = JsonArrayParser::<Item>::new();
+        let mut items = Vec::new();
+
+        let chunks = [
+            "```json\n[{\"name\": \"Ada\"}, {\"na",
+            "me\": \"Grace [\\\"G\\\"] Hopper\"},",
+            " {\"name\": 42}, {\"name\": \"Linus",
+        ];
+        for chunk in chunks {
+            items.push(parser.push(chunk));
+        }
+
+        assert_eq!(items[0].len(), 1);
+        assert_eq!(items[0][0].as_ref().unwrap().name, "Ada");
+        assert_eq!(items[1][0].as_ref().unwrap().name, "Grace [\"G\"] Hopper");
+        // A bad element is reported without losing the others, and the truncated tail is held back
+        assert_eq!(items[2].len(), 1);
+        assert!(items[2][0].is_err());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Person {
+        name: String,
+        age: u8,
+        languages: Vec<String>,
+    }
+
+    #[test]
+    fn test_commas_inside_objects_and_nested_arrays_do_not_end_an_element() {
+        let mut parser = JsonArrayParser::<Person>::new();
+        let mut people = parser.push(r#"[{"name": "Ada", "age": 36, "languages": ["English", "#);
+        people.extend(parser.push(r#""French"]}, {"name": "Alan", "age": 41, "languages": []}]"#));
+
+        let people: Vec<Person> = people.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            people,
+            [
+                Person {
+                    name: "Ada".to_string(),
+                    age: 36,
+                    languages: vec!["English".to_string(), "French".to_string()],
+                },
+                Person {
+                    name: "Alan".to_string(),
+                    age: 41,
+                    languages: vec![],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_json_array_stream() {
+        let chunks = stream::iter(vec!["[1, 2".to_string(), "3, 4]".to_string()]);
+        let numbers: Vec<u32> = parse_json_array::<u32, _>(chunks)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(numbers, vec![1, 23, 4]);
+    }
+}
@@ -1,7 +1,42 @@
+mod json_stream;
+
+use futures::stream::{self, Stream, StreamExt};
+use json_stream::parse_json_array;
 use rig::providers::openai;
 use rig::completion::Prompt;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::sync::OnceLock;
+
+// Number of valid entries to generate
+const ENTRIES: usize = 5;
+// How many times to ask the model before giving up on the missing entries
+const MAX_ATTEMPTS: usize = 3;
+const MIN_AGE: u8 = 18;
+const MAX_AGE: u8 = 80;
+
+const SCHEMA_AND_INSTRUCTIONS: &str = r#"
+    Generate synthetic personal data based on the following schema:
+    {
+        "name": "String (full name)",
+        "age": "Integer (18-80)",
+        "email": "String (valid email format)",
+        "occupation": "String",
+        "favorite_color": "String"
+    }
+
+    Instructions:
+    1. Generate realistic and diverse data.
+    2. Ensure email addresses are in a valid format but fictional.
+    3. Vary the occupations and favorite colors.
+    4. Provide the data in JSON format.
+    "#;
+
+fn email_regex() -> &'static Regex {
+    static EMAIL: OnceLock<Regex> = OnceLock::new();
+    EMAIL.get_or_init(|| Regex::new(r"^[^@\s]+@[^@\s]+\.[A-Za-z]{2,}$").unwrap())
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 struct PersonData {
@@ -12,6 +47,41 @@ struct PersonData {
     favorite_color: String,
 }
 
+impl PersonData {
+    /// Check the record against the constraints given to the model.
+    ///
+    /// Returns every violation, so they can all be sent back in a retry prompt.
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut violations = Vec::new();
+
+        for (field, value) in [
+            ("name", &self.name),
+            ("email", &self.email),
+            ("occupation", &self.occupation),
+            ("favorite_color", &self.favorite_color),
+        ] {
+            if value.trim().is_empty() {
+                violations.push(format!("{} must not be empty", field));
+            }
+        }
+        if !(MIN_AGE..=MAX_AGE).contains(&self.age) {
+            violations.push(format!(
+                "age {} is outside the range {}-{}",
+                self.age, MIN_AGE, MAX_AGE
+            ));
+        }
+        if !self.email.trim().is_empty() && !email_regex().is_match(&self.email) {
+            violations.push(format!("email {:?} is not a valid address", self.email));
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
 fn pretty_print_person(person: &PersonData) {
     println!("Generated Person Data:");
     println!("  Name: {}", person.name);
@@ -22,6 +92,47 @@ fn pretty_print_person(person: &PersonData) {
     println!();
 }
 
+// Build the generation prompt, listing the problems of rejected entries on retries
+fn generation_prompt(count: usize, problems: &[String]) -> String {
+    let mut prompt = format!("{}\n    Generate {} unique entries.\n", SCHEMA_AND_INSTRUCTIONS, count);
+    if !problems.is_empty() {
+        prompt.push_str("\n    Some previous entries were rejected. Avoid these problems:\n");
+        for problem in problems {
+            prompt.push_str(&format!("    - {}\n", problem));
+        }
+    }
+    prompt
+}
+
+// Parse the people in a generated JSON array and validate each one as it completes.
+// Returns the valid people, and a description of every malformed or invalid entry for
+// the retry prompt.
+async fn check_entries(chunks: impl Stream<Item = String>) -> (Vec<PersonData>, Vec<String>) {
+    let mut entries = Box::pin(parse_json_array::<PersonData, _>(chunks));
+    let mut people = Vec::new();
+    let mut problems = Vec::new();
+    while let Some(entry) = entries.next().await {
+        match entry {
+            Ok(person) => match person.validate() {
+                Ok(()) => {
+                    pretty_print_person(&person);
+                    people.push(person);
+                }
+                Err(violations) => {
+                    let problem = format!("{}: {}", person.name, violations.join("; "));
+                    eprintln!("Rejecting invalid entry {}", problem);
+                    problems.push(problem);
+                }
+            },
+            Err(e) => {
+                eprintln!("Skipping malformed entry: {}", e);
+                problems.push(format!("malformed JSON entry: {}", e));
+            }
+        }
+    }
+    (people, problems)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     // Initialize the OpenAI client
@@ -32,35 +143,78 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .model("gpt-4")
         .build();
 
-    // Define the schema and instructions
-    let schema_and_instructions = r#"
-    Generate synthetic personal data based on the following schema:
-    {
-        "name": "String (full name)",
-        "age": "Integer (18-80)",
-        "email": "String (valid email format)",
-        "occupation": "String",
-        "favorite_color": "String"
+    let mut people = Vec::new();
+    let mut prompt = generation_prompt(ENTRIES, &[]);
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        // Generate synthetic data
+        let generated_data = data_generator.prompt(&prompt).await?;
+
+        // Parse and check the entries one at a time. The response arrives in one piece
+        // here, but a stream of chunks from a streaming API can be passed in the same way.
+        let (valid, problems) = check_entries(stream::once(async { generated_data })).await;
+        people.extend(valid);
+
+        let missing = ENTRIES.saturating_sub(people.len());
+        if missing == 0 {
+            break;
+        }
+        if attempt == MAX_ATTEMPTS {
+            eprintln!("Giving up after {} attempts, {} entries missing.", attempt, missing);
+            break;
+        }
+        println!("Requesting {} more entries...\n", missing);
+        prompt = generation_prompt(missing, &problems);
     }
+    println!("Generated {} valid entries.", people.len());
 
-    Instructions:
-    1. Generate realistic and diverse data.
-    2. Ensure email addresses are in a valid format but fictional.
-    3. Vary the occupations and favorite colors.
-    4. Provide the data in JSON format.
+    Ok(())
+}
 
-    Generate 5 unique entries.
-    "#;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Generate synthetic data
-    let generated_data = data_generator.prompt(schema_and_instructions).await?;
+    #[test]
+    fn test_validate_person() {
+        let mut person = PersonData {
+            name: "Ada Lovelace".to_string(),
+            age: 36,
+            email: "ada@example.com".to_string(),
+            occupation: "Mathematician".to_string(),
+            favorite_color: "Purple".to_string(),
+        };
+        assert!(person.validate().is_ok());
 
-    // Parse and print the generated data
-    let people: Vec<PersonData> = serde_json::from_str(&generated_data)?;
-    
-    for person in people {
-        pretty_print_person(&person);
+        person.age = 150;
+        person.email = "ada.example.com".to_string();
+        person.occupation = " ".to_string();
+        assert_eq!(person.validate().unwrap_err().len(), 3);
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn test_check_entries_of_a_model_reply() {
+        // Shaped like a real reply: prose and a code fence around five-field objects,
+        // split into chunks mid-object
+        let reply = r#"Here is the data:
+```json
+[
+  {"name": "Ada Lovelace", "age": 36, "email": "ada@example.com", "occupation": "Mathematician", "favorite_color": "Purple"},
+  {"name": "Old Tom", "age": 150, "email": "tom.example.com", "occupation": "Sailor", "favorite_color": "Blue"},
+  {"name": "Grace Hopper", "age": 45, "email": "grace@navy.example.org", "occupation": "Rear Admiral, Computer Scientist", "favorite_color": "Green"}
+]
+```"#;
+        let chunks: Vec<String> = reply
+            .as_bytes()
+            .chunks(40)
+            .map(|chunk| String::from_utf8(chunk.to_vec()).unwrap())
+            .collect();
+
+        let (people, problems) = check_entries(stream::iter(chunks)).await;
+        let names: Vec<&str> = people.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Ada Lovelace", "Grace Hopper"]);
+        assert_eq!(people[1].occupation, "Rear Admiral, Computer Scientist");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Old Tom: age 150"));
+    }
+}
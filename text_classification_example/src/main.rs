@@ -1,3 +1,5 @@
+use examples_common::eval::{eval_from_args, EvalCase};
+use examples_common::guard::LimitedExtractor;
 use rig::providers::openai;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -28,21 +30,47 @@ fn pretty_print_result(text: &str, result: &ClassificationResult) {
     println!();
 }
 
+// Expected classifications for `--eval`
+fn eval_cases() -> Vec<EvalCase<ClassificationResult>> {
+    vec![
+        EvalCase::new("Apple announced its new M2 chip, promising significant performance improvements for MacBooks.")
+            .expect("category is Technology", |r: &ClassificationResult| matches!(r.category, Category::Technology))
+            .expect("confidence > 0.6", |r: &ClassificationResult| r.confidence > 0.6),
+        EvalCase::new("Scientists have discovered a new exoplanet that could potentially harbor life.")
+            .expect("category is Science", |r: &ClassificationResult| matches!(r.category, Category::Science))
+            .expect("confidence > 0.6", |r: &ClassificationResult| r.confidence > 0.6),
+        EvalCase::new("The underdog team pulled off a stunning victory in the championship final.")
+            .expect("category is Sports", |r: &ClassificationResult| matches!(r.category, Category::Sports)),
+        EvalCase::new("The annual flower show attracted gardening enthusiasts from across the country.")
+            .expect("category is Other", |r: &ClassificationResult| matches!(r.category, Category::Other(_)))
+            .expect("summary is not empty", |r: &ClassificationResult| !r.summary.trim().is_empty()),
+    ]
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize the OpenAI client
     let openai_client = openai::Client::from_env();
 
     // Create the classifier
-    let classifier = openai_client
-        .extractor::<ClassificationResult>("gpt-4")
-        .preamble(
-            "You are an AI assistant specialized in classifying text into predefined categories. \
-            The categories are: Technology, Science, Politics, Sports, and Entertainment. \
-            If the text doesn't fit into these categories, use the Other category and specify a suitable label. \
-            Provide a confidence score and a brief summary for each classification."
-        )
-        .build();
+    let classifier = LimitedExtractor::<_, ClassificationResult>::new(
+        openai_client.completion_model("gpt-4"),
+        "You are an AI assistant specialized in classifying text into predefined categories. \
+        The categories are: Technology, Science, Politics, Sports, and Entertainment. \
+        If the text doesn't fit into these categories, use the Other category and specify a suitable label. \
+        Provide a confidence score and a brief summary for each classification.",
+    );
+
+    // `--eval` checks the classifier against expected results instead of printing samples
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let classifier = &classifier;
+    let evaluated = eval_from_args(&args, &eval_cases(), |text| async move {
+        classifier.extract(&text).await
+    })
+    .await?;
+    if evaluated {
+        return Ok(());
+    }
 
     // Sample texts for classification
     let sample_texts = vec![
@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Player {
+    X,
+    O,
+    Empty,
+}
+
+impl Player {
+    pub fn opponent(self) -> Player {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+            Player::Empty => Player::Empty,
+        }
+    }
+}
+
+/// A single mark placed on the board
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Move {
+    pub player: Player,
+    pub position: usize,
+}
+
+/// Smallest and largest board sides accepted by `Board::with_size`
+pub const MIN_SIZE: usize = 3;
+pub const MAX_SIZE: usize = 9;
+
+/// Board side and win length used when none is given, the classic game
+pub fn default_size() -> usize {
+    3
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Board {
+    /// Cells per row and per column
+    pub size: usize,
+    /// How many marks in a row, column or diagonal win the game
+    pub win_length: usize,
+    /// Cells row by row; position `n` is `cells[n - 1]`
+    pub cells: Vec<Player>,
+}
+
+impl Board {
+    /// An empty `size`×`size` board won by `win_length` marks in a line, e.g. 3 in a
+    /// row on a 5×5 board
+    pub fn with_size(size: usize, win_length: usize) -> Result<Self, String> {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+            return Err(format!("Board size must be between {} and {}.", MIN_SIZE, MAX_SIZE));
+        }
+        if !(MIN_SIZE..=size).contains(&win_length) {
+            return Err(format!("Win length must be between {} and the board size {}.", MIN_SIZE, size));
+        }
+        Ok(Board {
+            size,
+            win_length,
+            cells: vec![Player::Empty; size * size],
+        })
+    }
+
+    /// Check that `position` is on the board and still free, without playing it
+    pub fn check_move(&self, position: usize) -> Result<(), String> {
+        if position < 1 || position > self.cells.len() {
+            return Err(format!(
+                "Invalid position. Choose a number between 1 and {}.",
+                self.cells.len()
+            ));
+        }
+        if self.cells[position - 1] != Player::Empty {
+            return Err("This cell is already occupied.".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn make_move(&mut self, position: usize, player: Player) -> Result<(), String> {
+        self.check_move(position)?;
+        self.cells[position - 1] = player;
+        Ok(())
+    }
+
+    /// Replay a sequence of moves onto this board, returning the board state after each one.
+    ///
+    /// Fails on the first illegal move: an empty player, an out-of-range or occupied cell,
+    /// or any move made after the game was already won or drawn.
+    pub fn apply_log(&mut self, log: &[Move]) -> Result<Vec<Board>, String> {
+        let mut states = Vec::with_capacity(log.len());
+        for (i, mv) in log.iter().enumerate() {
+            if mv.player == Player::Empty {
+                return Err(format!("Move {} has no player", i + 1));
+            }
+            if self.has_winner().is_some() || self.is_full() {
+                return Err(format!("Move {} was played after the game ended", i + 1));
+            }
+            self.make_move(mv.position, mv.player)
+                .map_err(|e| format!("Move {} ({:?} at {}): {}", i + 1, mv.player, mv.position, e))?;
+            states.push(self.clone());
+        }
+        Ok(states)
+    }
+
+    /// Positions (1-based) of the cells nobody has played yet
+    pub fn free_positions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, &cell)| cell == Player::Empty)
+            .map(|(index, _)| index + 1)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.cells.iter().all(|&cell| cell != Player::Empty)
+    }
+
+    pub fn has_winner(&self) -> Option<Player> {
+        // Every line of `win_length` cells starts somewhere and runs right, down, or
+        // along either diagonal
+        const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+        let size = self.size as isize;
+        for row in 0..size {
+            for col in 0..size {
+                let player = self.cells[(row * size + col) as usize];
+                if player == Player::Empty {
+                    continue;
+                }
+                for (row_step, col_step) in DIRECTIONS {
+                    let wins = (1..self.win_length as isize).all(|i| {
+                        let (r, c) = (row + i * row_step, col + i * col_step);
+                        (0..size).contains(&r)
+                            && (0..size).contains(&c)
+                            && self.cells[(r * size + c) as usize] == player
+                    });
+                    if wins {
+                        return Some(player);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn to_string(&self) -> String {
+        // Wide enough for the largest position number with a space either side
+        let width = self.cells.len().to_string().len() + 2;
+        let border = |left: &str, middle: &str, right: &str| {
+            let segments = vec!["─".repeat(width); self.size];
+            format!("{}{}{}\n", left, segments.join(middle), right)
+        };
+
+        let mut result = border("┌", "┬", "┐");
+        for i in 0..self.size {
+            result.push('│');
+            for j in 0..self.size {
+                let index = i * self.size + j;
+                let symbol = match self.cells[index] {
+                    Player::X => "X".to_string(),
+                    Player::O => "O".to_string(),
+                    Player::Empty => (index + 1).to_string(),
+                };
+                result.push_str(&format!("{:^width$}", symbol, width = width));
+                if j < self.size - 1 {
+                    result.push('│');
+                }
+            }
+            result.push_str("│\n");
+            if i < self.size - 1 {
+                result.push_str(&border("├", "┼", "┤"));
+            }
+        }
+        result.push_str(&border("└", "┴", "┘"));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &mut Board, player: Player, positions: &[usize]) {
+        for &position in positions {
+            board.make_move(position, player).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_four_by_four_wins() {
+        //  1  2  3  4
+        //  5  6  7  8
+        //  9 10 11 12
+        // 13 14 15 16
+        let lines: [&[usize]; 4] = [&[5, 6, 7, 8], &[3, 7, 11, 15], &[1, 6, 11, 16], &[4, 7, 10, 13]];
+        for line in lines {
+            let mut board = Board::with_size(4, 4).unwrap();
+            play(&mut board, Player::O, &line[..3]);
+            assert_eq!(board.has_winner(), None, "{:?}", line);
+            play(&mut board, Player::O, &line[3..]);
+            assert_eq!(board.has_winner(), Some(Player::O), "{:?}", line);
+        }
+
+        // Lines don't wrap around the edge of the board
+        let mut board = Board::with_size(4, 4).unwrap();
+        play(&mut board, Player::X, &[3, 4, 5, 6]);
+        assert_eq!(board.has_winner(), None);
+
+        // Three in a row wins when that's the win length
+        let mut board = Board::with_size(4, 3).unwrap();
+        play(&mut board, Player::X, &[8, 11, 14]);
+        assert_eq!(board.has_winner(), Some(Player::X));
+    }
+
+    #[test]
+    fn test_board_size_limits() {
+        assert!(Board::with_size(2, 2).is_err());
+        assert!(Board::with_size(4, 5).is_err());
+        assert!(Board::with_size(10, 3).is_err());
+
+        let mut board = Board::with_size(5, 3).unwrap();
+        assert!(board.make_move(25, Player::X).is_ok());
+        assert!(board.make_move(26, Player::X).is_err());
+        assert!(board.to_string().starts_with("┌────┬"));
+    }
+
+    #[test]
+    fn test_check_move() {
+        let mut board = Board::with_size(3, 3).unwrap();
+        board.make_move(5, Player::X).unwrap();
+
+        assert!(board.check_move(1).is_ok());
+        assert!(board.check_move(9).is_ok());
+        assert_eq!(board.check_move(5), Err("This cell is already occupied.".to_string()));
+        assert!(board.check_move(0).is_err());
+        assert!(board.check_move(10).is_err());
+
+        // Checking doesn't play the move
+        assert_eq!(board.cells[0], Player::Empty);
+    }
+}
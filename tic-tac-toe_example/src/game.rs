@@ -0,0 +1,300 @@
+use crate::board::{default_size, Board, Move, Player};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Something that can pick a move for a player: a human at the keyboard, an LLM,
+/// or a plain closure for automated play.
+pub trait Strategy {
+    /// Returns the position (1 to the number of cells) to play, or `None` to forfeit the turn.
+    async fn choose_move(
+        &mut self,
+        board: &Board,
+        player: Player,
+    ) -> Result<Option<usize>, Box<dyn Error>>;
+}
+
+impl<F> Strategy for F
+where
+    F: FnMut(&Board, Player) -> Option<usize>,
+{
+    async fn choose_move(
+        &mut self,
+        board: &Board,
+        player: Player,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        Ok(self(board, player))
+    }
+}
+
+/// Events emitted by the engine while a game is being played
+pub enum GameEvent<'a> {
+    /// A player is about to choose a move on the given board
+    Turn { board: &'a Board, player: Player },
+    /// A player placed a mark at `position`
+    Moved { player: Player, position: usize },
+    /// A player forfeited their turn, either voluntarily or with an illegal move
+    Forfeited { player: Player, reason: String },
+    /// The game stopped on a winner or a draw
+    Finished { board: &'a Board, result: &'a GameResult },
+}
+
+/// Outcome of a finished game, doubling as a replayable transcript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameResult {
+    /// The winning player, or `None` for a draw
+    pub winner: Option<Player>,
+    /// Every move played, in order
+    pub moves: Vec<Move>,
+    /// Side of the board the game was played on; logs from before larger boards are 3×3
+    #[serde(default = "default_size")]
+    pub size: usize,
+    /// Marks in a line needed to win
+    #[serde(default = "default_size")]
+    pub win_length: usize,
+}
+
+impl GameResult {
+    /// Write the transcript to a JSON file
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Load a transcript from a JSON file and check that it describes a legal game
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let result: GameResult = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        result.validate()?;
+        Ok(result)
+    }
+
+    /// An empty board of the size this game was played on
+    pub fn empty_board(&self) -> Result<Board, String> {
+        Board::with_size(self.size, self.win_length)
+    }
+
+    /// Replay the moves on an empty board and check the recorded winner matches
+    pub fn validate(&self) -> Result<Vec<Board>, String> {
+        let mut board = self.empty_board()?;
+        let states = board.apply_log(&self.moves)?;
+        if board.has_winner() != self.winner {
+            return Err(format!(
+                "Recorded winner {:?} does not match the replayed winner {:?}",
+                self.winner,
+                board.has_winner()
+            ));
+        }
+        Ok(states)
+    }
+}
+
+/// Turns forfeited in a row, by either player, after which the game ends in a draw
+pub const MAX_CONSECUTIVE_FORFEITS: usize = 4;
+
+/// Play a full game on `board` between two strategies without any interactive I/O.
+///
+/// X always moves first. The game stops as soon as there is a winner, the board is
+/// full, or [`MAX_CONSECUTIVE_FORFEITS`] turns pass without a move, and `on_event` is
+/// called for every turn, move, forfeit and the final result.
+pub async fn play_game<X, O>(
+    mut board: Board,
+    x_strategy: &mut X,
+    o_strategy: &mut O,
+    mut on_event: impl FnMut(GameEvent<'_>),
+) -> Result<GameResult, Box<dyn Error>>
+where
+    X: Strategy,
+    O: Strategy,
+{
+    let mut current_player = Player::X;
+    let mut moves = Vec::new();
+    let mut forfeits = 0;
+
+    loop {
+        on_event(GameEvent::Turn {
+            board: &board,
+            player: current_player,
+        });
+
+        let choice = match current_player {
+            Player::X => x_strategy.choose_move(&board, current_player).await?,
+            Player::O => o_strategy.choose_move(&board, current_player).await?,
+            Player::Empty => unreachable!(),
+        };
+
+        match choice {
+            Some(position) => match board.make_move(position, current_player) {
+                Ok(()) => {
+                    forfeits = 0;
+                    moves.push(Move {
+                        player: current_player,
+                        position,
+                    });
+                    on_event(GameEvent::Moved {
+                        player: current_player,
+                        position,
+                    });
+                }
+                Err(e) => {
+                    forfeits += 1;
+                    on_event(GameEvent::Forfeited {
+                        player: current_player,
+                        reason: e,
+                    });
+                }
+            },
+            None => {
+                forfeits += 1;
+                on_event(GameEvent::Forfeited {
+                    player: current_player,
+                    reason: "No move was chosen".to_string(),
+                });
+            }
+        }
+
+        let winner = board.has_winner();
+        if winner.is_some() || board.is_full() || forfeits >= MAX_CONSECUTIVE_FORFEITS {
+            let result = GameResult {
+                winner,
+                moves,
+                size: board.size,
+                win_length: board.win_length,
+            };
+            on_event(GameEvent::Finished {
+                board: &board,
+                result: &result,
+            });
+            return Ok(result);
+        }
+
+        current_player = current_player.opponent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A strategy that plays `positions` in order, then forfeits
+    fn scripted<const N: usize>(positions: [usize; N]) -> impl FnMut(&Board, Player) -> Option<usize> {
+        let mut positions = positions.into_iter();
+        move |_, _| positions.next()
+    }
+
+    /// Play a 3×3 game, returning the result and how many forfeits were reported
+    async fn play(x: &mut impl Strategy, o: &mut impl Strategy) -> (GameResult, usize) {
+        let mut forfeits = 0;
+        let result = play_game(Board::with_size(3, 3).unwrap(), x, o, |event| {
+            if let GameEvent::Forfeited { .. } = event {
+                forfeits += 1;
+            }
+        })
+        .await
+        .unwrap();
+        (result, forfeits)
+    }
+
+    #[tokio::test]
+    async fn test_game_ends_on_a_win() {
+        let (result, forfeits) = play(&mut scripted([1, 2, 3]), &mut scripted([4, 5])).await;
+        assert_eq!(result.winner, Some(Player::X));
+        assert_eq!(result.moves.len(), 5);
+        assert_eq!(forfeits, 0);
+        assert!(result.validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_game_ends_in_a_draw_on_a_full_board() {
+        // X O X
+        // X O O
+        // O X X
+        let (result, _) = play(&mut scripted([1, 3, 4, 8, 9]), &mut scripted([2, 5, 6, 7])).await;
+        assert_eq!(result.winner, None);
+        assert_eq!(result.moves.len(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_illegal_move_forfeits_the_turn() {
+        // O tries to play X's cell, then X wins the top row
+        let (result, forfeits) = play(&mut scripted([1, 2, 3]), &mut scripted([1, 5])).await;
+        assert_eq!(result.winner, Some(Player::X));
+        assert_eq!(forfeits, 1);
+        assert_eq!(result.moves.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_game_stops_after_too_many_forfeits() {
+        let (result, forfeits) = play(&mut |_: &Board, _| None, &mut |_: &Board, _| None).await;
+        assert_eq!(result.winner, None);
+        assert!(result.moves.is_empty());
+        assert_eq!(forfeits, MAX_CONSECUTIVE_FORFEITS);
+
+        // A move in between starts the count again
+        let (result, forfeits) = play(&mut scripted([5, 1]), &mut |_: &Board, _| None).await;
+        assert_eq!(result.moves.len(), 2);
+        assert_eq!(forfeits, MAX_CONSECUTIVE_FORFEITS + 1);
+    }
+
+    /// A 3×3 game from alternating moves, X first
+    fn game(winner: Option<Player>, positions: &[usize]) -> GameResult {
+        let moves = positions
+            .iter()
+            .zip([Player::X, Player::O].into_iter().cycle())
+            .map(|(&position, player)| Move { player, position })
+            .collect();
+        GameResult {
+            winner,
+            moves,
+            size: 3,
+            win_length: 3,
+        }
+    }
+
+    #[test]
+    fn test_illegal_cells_are_rejected() {
+        let occupied = game(None, &[1, 5, 1]);
+        assert!(occupied.validate().unwrap_err().contains("already occupied"));
+
+        let out_of_range = game(None, &[1, 10]);
+        assert!(out_of_range.validate().unwrap_err().contains("Invalid position"));
+    }
+
+    #[test]
+    fn test_moves_after_a_win_are_rejected() {
+        // X completes the top row with its third move, then O keeps playing
+        let result = game(Some(Player::X), &[1, 4, 2, 5, 3, 6]);
+        assert_eq!(
+            result.validate().unwrap_err(),
+            "Move 6 was played after the game ended"
+        );
+    }
+
+    #[test]
+    fn test_recorded_winner_must_match_the_board() {
+        let positions = [1, 4, 2, 5, 3];
+        assert!(game(Some(Player::X), &positions).validate().is_ok());
+        assert!(game(Some(Player::O), &positions).validate().is_err());
+        assert!(game(None, &positions).validate().is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("tic_tac_toe_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let result = game(Some(Player::X), &[1, 4, 2, 5, 3]);
+        result.save(path).unwrap();
+        let loaded = GameResult::load(path);
+        std::fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.winner, result.winner);
+        assert_eq!(loaded.moves, result.moves);
+        assert_eq!((loaded.size, loaded.win_length), (3, 3));
+
+        // A log that fails validation isn't loaded
+        game(Some(Player::O), &[1, 4, 2, 5, 3]).save(path).unwrap();
+        let loaded = GameResult::load(path);
+        std::fs::remove_file(path).unwrap();
+        assert!(loaded.is_err());
+    }
+}
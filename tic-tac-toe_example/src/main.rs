@@ -1,178 +1,321 @@
+mod board;
+mod game;
+mod minimax;
+
+use crate::board::{default_size, Board, Player};
+use crate::game::{play_game, GameEvent, GameResult, Strategy};
+use crate::minimax::best_move;
+use examples_common::openai::openai_client_from_env;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use rig::extractor::Extractor;
 use rig::providers::openai;
-use rig::completion::Prompt;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::io::{self, Write};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-enum Player {
-    X,
-    O,
-    Empty,
+/// The AI's next move
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+struct AiMove {
+    /// Number of the free cell to play
+    position: usize,
+    /// Why this move, in a sentence
+    rationale: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Board {
-    cells: [Player; 9],
-}
+/// Reads moves from stdin, asking again until the chosen cell is legal
+struct HumanStrategy;
 
-impl Board {
-    fn new() -> Self {
-        Board {
-            cells: [Player::Empty; 9],
+impl Strategy for HumanStrategy {
+    async fn choose_move(
+        &mut self,
+        board: &Board,
+        player: Player,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        loop {
+            print!("Your move ({:?}): ", player);
+            io::stdout().flush()?;
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            let position: usize = match input.trim().parse() {
+                Ok(position) => position,
+                Err(e) => {
+                    println!("Error: {}. Try again.", e);
+                    continue;
+                }
+            };
+            if let Err(e) = board.check_move(position) {
+                println!("Error: {}. Try again.", e);
+                continue;
+            }
+            return Ok(Some(position));
         }
     }
+}
 
-    fn make_move(&mut self, position: usize, player: Player) -> Result<(), String> {
-        if position < 1 || position > 9 {
-            return Err("Invalid position. Choose a number between 1 and 9.".to_string());
-        }
-        let index = position - 1;
-        if self.cells[index] != Player::Empty {
-            return Err("This cell is already occupied.".to_string());
+/// Asks an LLM for its next move as an `AiMove`. An illegal or missing move is asked
+/// for once more, with the reason; after that the turn is forfeited.
+struct LlmStrategy {
+    extractor: Extractor<openai::CompletionModel, AiMove>,
+    /// Print the AI's rationale for each move
+    verbose: bool,
+}
+
+impl Strategy for LlmStrategy {
+    async fn choose_move(
+        &mut self,
+        board: &Board,
+        player: Player,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        println!("AI is thinking...");
+        let mut prompt = format!(
+            "You are playing Tic-Tac-Toe as {:?} on a {}x{} board; {} marks in a row, column or diagonal win. Here's the current board state:\n{}\nWhat's your next move? Choose the number (1-{}) of a free position.",
+            player,
+            board.size,
+            board.size,
+            board.win_length,
+            board.to_string(),
+            board.cells.len()
+        );
+
+        for attempt in 1..=2 {
+            let problem = match self.extractor.extract(&prompt).await {
+                Ok(ai_move) => match board.check_move(ai_move.position) {
+                    Ok(()) => {
+                        if self.verbose {
+                            println!("AI: {}", ai_move.rationale);
+                        }
+                        return Ok(Some(ai_move.position));
+                    }
+                    Err(e) => format!("Position {} can't be played: {}", ai_move.position, e),
+                },
+                Err(e) => format!("No move could be read from the reply: {}", e),
+            };
+            println!("AI move rejected: {}", problem);
+            if attempt == 1 {
+                prompt.push_str(&format!("\n\nYour previous answer was rejected. {} Pick again.", problem));
+            }
         }
-        self.cells[index] = player;
-        Ok(())
+        Ok(None)
     }
+}
+
+/// Plays the move `best_move` finds, without any API calls. On `Difficulty::Easy` it
+/// plays a random free cell instead half of the time.
+struct MinimaxStrategy {
+    difficulty: Difficulty,
+}
 
-    fn is_full(&self) -> bool {
-        self.cells.iter().all(|&cell| cell != Player::Empty)
+/// How often an easy minimax opponent ignores the search and moves at random
+const EASY_RANDOM_CHANCE: f64 = 0.5;
+
+impl Strategy for MinimaxStrategy {
+    async fn choose_move(
+        &mut self,
+        board: &Board,
+        player: Player,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        let mut rng = rand::thread_rng();
+        if self.difficulty == Difficulty::Easy && rng.gen_bool(EASY_RANDOM_CHANCE) {
+            return Ok(board.free_positions().choose(&mut rng));
+        }
+        Ok(Some(best_move(board, player)))
     }
+}
 
-    fn has_winner(&self) -> Option<Player> {
-        const WINNING_COMBINATIONS: [[usize; 3]; 8] = [
-            [0, 1, 2], [3, 4, 5], [6, 7, 8], // Rows
-            [0, 3, 6], [1, 4, 7], [2, 5, 8], // Columns
-            [0, 4, 8], [2, 4, 6],            // Diagonals
-        ];
-
-        for combo in WINNING_COMBINATIONS.iter() {
-            if self.cells[combo[0]] != Player::Empty
-                && self.cells[combo[0]] == self.cells[combo[1]]
-                && self.cells[combo[1]] == self.cells[combo[2]]
-            {
-                return Some(self.cells[combo[0]]);
-            }
+/// The AI player picked with `--opponent`
+enum Opponent {
+    Llm(LlmStrategy),
+    Minimax(MinimaxStrategy),
+}
+
+impl Strategy for Opponent {
+    async fn choose_move(
+        &mut self,
+        board: &Board,
+        player: Player,
+    ) -> Result<Option<usize>, Box<dyn Error>> {
+        match self {
+            Opponent::Llm(llm) => llm.choose_move(board, player).await,
+            Opponent::Minimax(minimax) => minimax.choose_move(board, player).await,
         }
-        None
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Difficulty {
+    Easy,
+    Hard,
+}
+
+/// Command-line options
+struct Args {
+    /// Replay a saved transcript instead of playing
+    replay: Option<String>,
+    /// Where to save the transcript of a played game
+    log: String,
+    /// Cells per side of the board
+    size: usize,
+    /// Marks in a line needed to win; the board size unless given
+    win_length: Option<usize>,
+    /// Show why the AI picked each move
+    verbose: bool,
+    /// Play against the local minimax search instead of the LLM
+    minimax: bool,
+    /// How well the minimax opponent plays
+    difficulty: Difficulty,
+}
 
-    fn to_string(&self) -> String {
-        let mut result = String::new();
-        result.push_str("┌───┬───┬───┐\n");
-        for i in 0..3 {
-            result.push_str("│");
-            for j in 0..3 {
-                let index = i * 3 + j;
-                let symbol = match self.cells[index] {
-                    Player::X => " X ".to_string(),
-                    Player::O => " O ".to_string(),
-                    Player::Empty => format!(" {} ", index + 1),
-                };
-                result.push_str(&symbol);
-                if j < 2 {
-                    result.push_str("│");
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Args {
+            replay: None,
+            log: "tic_tac_toe_log.json".to_string(),
+            size: default_size(),
+            win_length: None,
+            verbose: false,
+            minimax: false,
+            difficulty: Difficulty::Hard,
+        };
+        let mut difficulty = None;
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--replay" => {
+                    args.replay = Some(iter.next().ok_or("--replay expects a file path")?);
+                }
+                "--log" => {
+                    args.log = iter.next().ok_or("--log expects a file path")?;
+                }
+                "--size" => {
+                    args.size = parse_number(iter.next(), "--size")?;
+                }
+                "--win-length" => {
+                    args.win_length = Some(parse_number(iter.next(), "--win-length")?);
+                }
+                "--verbose" => args.verbose = true,
+                "--opponent" => {
+                    args.minimax = match iter.next().as_deref() {
+                        Some("minimax") => true,
+                        Some("llm") => false,
+                        _ => return Err("--opponent expects minimax or llm".to_string()),
+                    };
                 }
+                "--difficulty" => {
+                    difficulty = match iter.next().as_deref() {
+                        Some("easy") => Some(Difficulty::Easy),
+                        Some("hard") => Some(Difficulty::Hard),
+                        _ => return Err("--difficulty expects easy or hard".to_string()),
+                    };
+                }
+                other => return Err(format!("Unknown argument: {}", other)),
             }
-            result.push_str("│\n");
-            if i < 2 {
-                result.push_str("├───┼───┼───┤\n");
+        }
+        // The LLM has no difficulty setting, so don't let the flag be silently ignored
+        if let Some(difficulty) = difficulty {
+            if !args.minimax {
+                return Err("--difficulty only applies with --opponent minimax".to_string());
             }
+            args.difficulty = difficulty;
         }
-        result.push_str("└───┴───┴───┘\n");
-        result
+        Ok(args)
     }
 }
 
-fn parse_ai_response(response: &str) -> Result<usize, String> {
-    // First, try to parse the entire response as a number
-    if let Ok(num) = response.trim().parse::<usize>() {
-        return Ok(num);
-    }
+fn parse_number(value: Option<String>, flag: &str) -> Result<usize, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} expects a number", flag))
+}
 
-    // If that fails, try to find the first number in the response
-    for word in response.split_whitespace() {
-        if let Ok(num) = word.parse::<usize>() {
-            return Ok(num);
-        }
+/// Re-render a saved game move by move, waiting for Enter between steps
+fn replay(path: &str) -> Result<(), Box<dyn Error>> {
+    let result = GameResult::load(path)?;
+    let states = result.validate()?;
+
+    println!("Replaying {} ({} moves)", path, result.moves.len());
+    println!("{}", result.empty_board()?.to_string());
+
+    for (mv, board) in result.moves.iter().zip(states.iter()) {
+        print!("Press Enter for the next move...");
+        io::stdout().flush()?;
+        io::stdin().read_line(&mut String::new())?;
+        println!("Player {:?} plays {}", mv.player, mv.position);
+        println!("{}", board.to_string());
     }
 
-    // If we still can't find a number, return an error
-    Err("Could not find a valid move in the AI's response".to_string())
+    match result.winner {
+        Some(winner) => println!("Player {:?} wins!", winner),
+        None => println!("It's a draw!"),
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let openai_client = openai::Client::from_env();
-    let ai_player = openai_client.model("gpt-3.5-turbo").build();
+    let args = Args::parse()?;
 
-    let mut board = Board::new();
-    let mut current_player = Player::X;
+    if let Some(path) = &args.replay {
+        return replay(path);
+    }
 
-    println!("Welcome to Tic-Tac-Toe! You are X, and the AI is O.");
-    println!("Enter a number from 1-9 to make your move.");
+    let board = Board::with_size(args.size, args.win_length.unwrap_or(args.size))?;
 
-    loop {
-        println!("\nCurrent board:");
-        println!("{}", board.to_string());
+    // The minimax opponent runs locally, so only the LLM needs an API key
+    let mut ai = if args.minimax {
+        Opponent::Minimax(MinimaxStrategy {
+            difficulty: args.difficulty,
+        })
+    } else {
+        let openai_client =
+            openai_client_from_env(openai::Client::from_env, openai::Client::from_url);
+        let extractor = openai_client
+            .extractor::<AiMove>("gpt-3.5-turbo")
+            .preamble("You are playing Tic-Tac-Toe. Pick the position of your next move and briefly explain why.")
+            .build();
+        Opponent::Llm(LlmStrategy {
+            extractor,
+            verbose: args.verbose,
+        })
+    };
 
-        match current_player {
-            Player::X => {
-                print!("Your move (X): ");
-                io::stdout().flush()?;
-                let mut input = String::new();
-                io::stdin().read_line(&mut input)?;
-                let position: usize = input.trim().parse()?;
-                if let Err(e) = board.make_move(position, Player::X) {
-                    println!("Error: {}. Try again.", e);
-                    continue;
-                }
-            }
-            Player::O => {
-                println!("AI is thinking...");
-                let prompt = format!(
-                    "You are playing Tic-Tac-Toe as O. Here's the current board state:\n{}\nWhat's your next move? Respond with just the number (1-9) of the position you want to play.",
-                    board.to_string()
-                );
-                let ai_response = ai_player.prompt(&prompt).await?;
-                let position = parse_ai_response(&ai_response);
-                match position {
-                    Ok(pos) => {
-                        if let Err(e) = board.make_move(pos, Player::O) {
-                            println!("AI made an invalid move: {}. It forfeits its turn.", e);
-                            continue;
-                        }
-                        println!("AI chose position {}", pos);
-                    }
-                    Err(e) => {
-                        println!("Failed to parse AI's move: {}. AI forfeits its turn.", e);
-                        continue;
-                    }
-                }
-            }
-            Player::Empty => unreachable!(),
-        }
+    println!("Welcome to Tic-Tac-Toe! You are X, and the AI is O.");
+    println!(
+        "Get {} in a row on the {}x{} board. Enter a number from 1-{} to make your move.",
+        board.win_length,
+        board.size,
+        board.size,
+        board.cells.len()
+    );
 
-        if let Some(winner) = board.has_winner() {
-            println!("\nFinal board:");
+    let mut human = HumanStrategy;
+
+    let result = play_game(board, &mut human, &mut ai, |event| match event {
+        GameEvent::Turn { board, .. } => {
+            println!("\nCurrent board:");
             println!("{}", board.to_string());
-            println!("Player {:?} wins!", winner);
-            break;
         }
-
-        if board.is_full() {
+        GameEvent::Moved { player: Player::O, position } => {
+            println!("AI chose position {}", position);
+        }
+        GameEvent::Moved { .. } => {}
+        GameEvent::Forfeited { player, reason } => {
+            println!("Player {:?} made an invalid move: {}. It forfeits its turn.", player, reason);
+        }
+        GameEvent::Finished { board, result } => {
             println!("\nFinal board:");
             println!("{}", board.to_string());
-            println!("It's a draw!");
-            break;
+            match result.winner {
+                Some(winner) => println!("Player {:?} wins!", winner),
+                None => println!("It's a draw!"),
+            }
         }
+    })
+    .await?;
 
-        current_player = match current_player {
-            Player::X => Player::O,
-            Player::O => Player::X,
-            Player::Empty => unreachable!(),
-        };
-    }
+    result.save(&args.log)?;
+    println!("Game log saved to {}", args.log);
 
     Ok(())
-}
\ No newline at end of file
+}
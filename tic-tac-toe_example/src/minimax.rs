@@ -0,0 +1,113 @@
+// minimax.rs
+//
+// An offline opponent. `best_move` searches the game tree with minimax (in its negamax
+// form, with alpha-beta pruning) and needs no API calls. On the classic 3×3 board the
+// search is exhaustive, so the opponent never loses.
+
+use crate::board::{Board, Player};
+
+/// How many moves ahead the search looks on boards bigger than 3×3, where searching
+/// every game to the end would take far too long
+pub const LARGE_BOARD_DEPTH: usize = 4;
+
+/// The best position for `player` to play next. Wins come before draws and sooner wins
+/// before later ones; a loss is put off as long as possible.
+///
+/// `board` must have a free cell and no winner yet.
+pub fn best_move(board: &Board, player: Player) -> usize {
+    let depth = if board.size <= 3 { usize::MAX } else { LARGE_BOARD_DEPTH };
+
+    let mut best = None;
+    let mut alpha = -i32::MAX;
+    for position in board.free_positions() {
+        let mut next = board.clone();
+        next.cells[position - 1] = player;
+        let score = -negamax(&next, player.opponent(), depth - 1, -i32::MAX, -alpha);
+        if best.is_none() || score > alpha {
+            best = Some(position);
+            alpha = score;
+        }
+    }
+    best.expect("best_move needs a board with a free cell")
+}
+
+/// Score of `board` for `player`, who is about to move: positive if they can force a
+/// win, negative if the opponent can, 0 for a draw or when `depth` runs out
+fn negamax(board: &Board, player: Player, depth: usize, mut alpha: i32, beta: i32) -> i32 {
+    let free = board.free_positions().count() as i32;
+    if board.has_winner().is_some() {
+        // The opponent just completed a line; losing later, with fewer free cells, is better
+        return -(1 + free);
+    }
+    if free == 0 || depth == 0 {
+        return 0;
+    }
+
+    for position in board.free_positions() {
+        let mut next = board.clone();
+        next.cells[position - 1] = player;
+        alpha = alpha.max(-negamax(&next, player.opponent(), depth - 1, -beta, -alpha));
+        if alpha >= beta {
+            break;
+        }
+    }
+    alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Play every possible opponent reply against `best_move` for `me` and check the
+    /// opponent never wins
+    fn never_loses(board: &mut Board, me: Player, to_move: Player) {
+        if let Some(winner) = board.has_winner() {
+            assert_eq!(winner, me, "minimax lost:\n{}", board.to_string());
+            return;
+        }
+        if board.is_full() {
+            return;
+        }
+
+        if to_move == me {
+            let position = best_move(board, me);
+            let mut next = board.clone();
+            next.make_move(position, me).unwrap();
+            never_loses(&mut next, me, me.opponent());
+        } else {
+            let positions: Vec<usize> = board.free_positions().collect();
+            for position in positions {
+                let mut next = board.clone();
+                next.make_move(position, to_move).unwrap();
+                never_loses(&mut next, me, me);
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimax_never_loses_from_an_empty_board() {
+        let mut board = Board::with_size(3, 3).unwrap();
+        never_loses(&mut board, Player::X, Player::X);
+        never_loses(&mut board, Player::O, Player::X);
+    }
+
+    #[test]
+    fn test_minimax_takes_a_win_and_blocks_a_loss() {
+        // X X .
+        // O O .
+        // . . .
+        let mut board = Board::with_size(3, 3).unwrap();
+        for (position, player) in [(1, Player::X), (4, Player::O), (2, Player::X), (5, Player::O)] {
+            board.make_move(position, player).unwrap();
+        }
+        assert_eq!(best_move(&board, Player::X), 3);
+        assert_eq!(best_move(&board, Player::O), 6);
+
+        // On a larger board, where the search is cut off, a win is still found
+        let mut board = Board::with_size(5, 4).unwrap();
+        for position in [7, 8, 9] {
+            board.make_move(position, Player::O).unwrap();
+        }
+        assert!([6, 10].contains(&best_move(&board, Player::O)));
+    }
+}